@@ -72,6 +72,9 @@ target "peerdb" {
     "linux/amd64",
     "linux/arm64",
   ]
+  args = {
+    PEERDB_VERSION_SHA_SHORT = "${SHA_SHORT}"
+  }
   tags = [
     "${REGISTRY}/peerdb-server:${TAG}",
     "${REGISTRY}/peerdb-server:${SHA_SHORT}",
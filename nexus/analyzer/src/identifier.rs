@@ -0,0 +1,124 @@
+// identifiers written by users inside mirror options and table mappings.
+//
+// Flow expects identifiers without quotes and with their case intact, and
+// quotes them itself when generating SQL, so the parts here are returned
+// unquoted. Quoted parts keep their case and may contain `.`, `,` or spaces,
+// but not control characters; unquoted parts are trimmed but otherwise kept
+// as written, and must be legal unquoted SQL identifiers.
+
+use sqlparser::ast::ObjectName;
+
+/// Parses a comma separated list of possibly schema qualified identifiers,
+/// such as `"Public"."MyTable", other.t`, into the parts of each identifier.
+/// An empty or blank string is an empty list.
+pub fn parse_identifier_list(s: &str) -> anyhow::Result<Vec<Vec<String>>> {
+    if s.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut list = Vec::new();
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut quoted = false;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if !quoted && part.trim().is_empty() => {
+                part.clear();
+                quoted = true;
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            part.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => part.push(c),
+                        None => anyhow::bail!("unterminated quoted identifier in {:?}", s),
+                    }
+                }
+            }
+            '.' | ',' => {
+                parts.push(finish_part(&mut part, &mut quoted, s)?);
+                if c == ',' {
+                    list.push(std::mem::take(&mut parts));
+                }
+            }
+            c if quoted && c.is_whitespace() => {}
+            c if quoted => anyhow::bail!("unexpected {:?} after quoted identifier in {:?}", c, s),
+            '"' => anyhow::bail!("unexpected quote inside identifier {:?}", s),
+            c => part.push(c),
+        }
+    }
+    parts.push(finish_part(&mut part, &mut quoted, s)?);
+    list.push(parts);
+    Ok(list)
+}
+
+fn finish_part(part: &mut String, quoted: &mut bool, s: &str) -> anyhow::Result<String> {
+    let part = std::mem::take(part);
+    let was_quoted = std::mem::take(quoted);
+    let part = if was_quoted {
+        part
+    } else {
+        part.trim().to_string()
+    };
+    if part.is_empty() {
+        anyhow::bail!("empty identifier in {:?}", s);
+    }
+    if part.chars().any(char::is_control) {
+        anyhow::bail!("identifier {:?} contains a control character", part);
+    }
+    if !was_quoted && !is_unquoted_identifier(&part) {
+        anyhow::bail!(
+            "{:?} is not a valid identifier, quote it to use other characters",
+            part
+        );
+    }
+    Ok(part)
+}
+
+// like postgres, a letter or underscore followed by letters, digits,
+// underscores and dollar signs
+fn is_unquoted_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+/// Parses a single, possibly schema qualified, identifier.
+pub fn parse_qualified_name(s: &str) -> anyhow::Result<Vec<String>> {
+    let mut list = parse_identifier_list(s)?;
+    match list.len() {
+        0 => anyhow::bail!("empty identifier"),
+        1 => Ok(list.remove(0)),
+        _ => anyhow::bail!("expected a single identifier, got {:?}", s),
+    }
+}
+
+/// Parses a column name, which must not be qualified.
+pub fn parse_column_name(s: &str) -> anyhow::Result<String> {
+    let mut parts = parse_qualified_name(s)?;
+    if parts.len() != 1 {
+        anyhow::bail!("expected a column name, got {:?}", s);
+    }
+    Ok(parts.remove(0))
+}
+
+/// Joins the parts of a table name into the `schema.table` form flow parses.
+/// Flow splits on `.`, so a part containing one can't be passed through.
+pub fn table_identifier(parts: &[String]) -> anyhow::Result<String> {
+    if let Some(part) = parts.iter().find(|part| part.contains('.')) {
+        anyhow::bail!("table identifier part {:?} must not contain '.'", part);
+    }
+    Ok(parts.join("."))
+}
+
+pub fn object_name_identifier(name: &ObjectName) -> anyhow::Result<String> {
+    let parts = name
+        .0
+        .iter()
+        .map(|ident| ident.value.clone())
+        .collect::<Vec<_>>();
+    table_identifier(&parts)
+}
@@ -6,6 +6,7 @@ use std::{
 };
 
 use anyhow::Context;
+use identifier::object_name_identifier;
 use pt::{
     flow_model::{FlowJob, FlowJobTableMapping, QRepFlowJob},
     peerdb_peers::{
@@ -13,6 +14,7 @@ use pt::{
         KafkaConfig, MongoConfig, Peer, PostgresConfig, PubSubConfig, S3Config, SnowflakeConfig,
         SqlServerConfig, SshConfig,
     },
+    secrets,
 };
 use qrep::process_options;
 use sqlparser::ast::{
@@ -21,8 +23,12 @@ use sqlparser::ast::{
     Expr, FetchDirection, SqlOption, Statement,
 };
 
+mod identifier;
+mod partition;
 mod qrep;
 
+pub use partition::{preview_partitions, PartitionRange, Watermark, MAX_PREVIEW_PARTITIONS};
+
 pub trait StatementAnalyzer {
     type Output;
 
@@ -31,14 +37,27 @@ pub trait StatementAnalyzer {
 
 /// PeerExistanceAnalyzer is a statement analyzer that checks if the given
 /// statement touches a peer that exists in the system. If there isn't a peer
-/// this points to a catalog query.
+/// this points to the default peer of the session when the statement reads
+/// any table, and to a catalog query otherwise.
 pub struct PeerExistanceAnalyzer<'a> {
     peers: &'a HashMap<String, Peer>,
+    default_peer: Option<&'a str>,
 }
 
 impl<'a> PeerExistanceAnalyzer<'a> {
     pub fn new(peers: &'a HashMap<String, Peer>) -> Self {
-        Self { peers }
+        Self {
+            peers,
+            default_peer: None,
+        }
+    }
+
+    /// Routes tables not qualified by a peer name, like `schema.table` or
+    /// `table`, to `default_peer`. Tables qualified by a peer name still go
+    /// to that peer.
+    pub fn with_default_peer(mut self, default_peer: Option<&'a str>) -> Self {
+        self.default_peer = default_peer;
+        self
     }
 }
 
@@ -53,11 +72,13 @@ impl<'a> StatementAnalyzer for PeerExistanceAnalyzer<'a> {
 
     fn analyze(&self, statement: &Statement) -> anyhow::Result<Self::Output> {
         let mut peers_touched: HashSet<String> = HashSet::new();
+        let mut reads_tables = false;
         let mut analyze_name = |name: &str| {
             let name = name.to_lowercase();
             if self.peers.contains_key(&name) {
                 peers_touched.insert(name);
             }
+            reads_tables = true;
         };
 
         // Necessary as visit_relations fails to deeply visit some structures.
@@ -94,6 +115,13 @@ impl<'a> StatementAnalyzer for PeerExistanceAnalyzer<'a> {
         } else if let Some(peer_name) = peers_touched.iter().next() {
             let peer = self.peers.get(peer_name).unwrap();
             Ok(QueryAssociation::Peer(Box::new(peer.clone())))
+        } else if let Some(peer_name) = self.default_peer.filter(|_| reads_tables) {
+            // the peer may have been dropped since the session picked it
+            let peer = self
+                .peers
+                .get(&peer_name.to_lowercase())
+                .with_context(|| format!("default peer {} does not exist", peer_name))?;
+            Ok(QueryAssociation::Peer(Box::new(peer.clone())))
         } else {
             Ok(QueryAssociation::Catalog)
         }
@@ -123,6 +151,8 @@ pub enum PeerDDL {
     CreateMirrorForSelect {
         if_not_exists: bool,
         qrep_flow_job: Box<QRepFlowJob>,
+        /// Options of the mirror that are valid but likely mistakes.
+        warnings: Vec<&'static str>,
     },
     ExecuteMirrorForSelect {
         flow_job_name: String,
@@ -179,26 +209,33 @@ impl StatementAnalyzer for PeerDDLAnalyzer {
                         let flow_job_table_mappings = cdc
                             .mapping_options
                             .iter()
-                            .map(|table_mapping| FlowJobTableMapping {
-                                source_table_identifier: table_mapping.source.to_string(),
-                                destination_table_identifier: table_mapping.destination.to_string(),
-                                partition_key: table_mapping
-                                    .partition_key
-                                    .as_ref()
-                                    .map(|s| s.value.clone()),
-                                exclude: table_mapping
-                                    .exclude
-                                    .as_ref()
-                                    .map(|ss| ss.iter().map(|s| s.value.clone()).collect())
-                                    .unwrap_or_default(),
+                            .map(|table_mapping| {
+                                Ok(FlowJobTableMapping {
+                                    source_table_identifier: object_name_identifier(
+                                        &table_mapping.source,
+                                    )?,
+                                    destination_table_identifier: object_name_identifier(
+                                        &table_mapping.destination,
+                                    )?,
+                                    partition_key: table_mapping
+                                        .partition_key
+                                        .as_ref()
+                                        .map(|s| s.value.clone()),
+                                    exclude: table_mapping
+                                        .exclude
+                                        .as_ref()
+                                        .map(|ss| ss.iter().map(|s| s.value.clone()).collect())
+                                        .unwrap_or_default(),
+                                })
                             })
-                            .collect::<Vec<_>>();
+                            .collect::<anyhow::Result<Vec<_>>>()?;
 
                         // get do_initial_copy from with_options
-                        let mut raw_options = HashMap::with_capacity(cdc.with_options.len());
-                        for option in &cdc.with_options {
-                            raw_options.insert(&option.name.value as &str, &option.value);
-                        }
+                        let mut raw_options = mirror_options(
+                            cdc.with_options
+                                .iter()
+                                .map(|option| (option.name.value.as_str(), &option.value)),
+                        )?;
                         let do_initial_copy = match raw_options.remove("do_initial_copy") {
                             Some(Expr::Value(ast::Value::Boolean(b))) => *b,
                             // also support "true" and "false" as strings
@@ -361,12 +398,15 @@ impl StatementAnalyzer for PeerDDLAnalyzer {
                         }))
                     }
                     Select(select) => {
-                        let mut raw_options = HashMap::with_capacity(select.with_options.len());
-                        for option in &select.with_options {
-                            if let Expr::Value(ref value) = option.value {
-                                raw_options.insert(&option.name.value as &str, value);
-                            }
-                        }
+                        let mut raw_options =
+                            mirror_options(select.with_options.iter().filter_map(|option| {
+                                match option.value {
+                                    Expr::Value(ref value) => {
+                                        Some((option.name.value.as_str(), value))
+                                    }
+                                    _ => None,
+                                }
+                            }))?;
 
                         // we treat disabled as a special option, and do not pass it to the
                         // flow server, this is primarily used for external orchestration.
@@ -375,14 +415,21 @@ impl StatementAnalyzer for PeerDDLAnalyzer {
                             disabled = *b;
                         }
 
-                        let processed_options = process_options(raw_options)?;
+                        // strict is also special, it changes how the other options are
+                        // processed rather than being passed on
+                        let mut strict = false;
+                        if let Some(ast::Value::Boolean(b)) = raw_options.remove("strict") {
+                            strict = *b;
+                        }
+
+                        let processed = process_options(raw_options, strict)?;
 
                         let qrep_flow_job = QRepFlowJob {
                             name: select.mirror_name.to_string().to_lowercase(),
                             source_peer: select.source_peer.to_string().to_lowercase(),
                             target_peer: select.target_peer.to_string().to_lowercase(),
                             query_string: select.query_string.to_string(),
-                            flow_options: processed_options,
+                            flow_options: processed.options,
                             description: "".to_string(), // TODO: add description
                             disabled,
                         };
@@ -390,6 +437,7 @@ impl StatementAnalyzer for PeerDDLAnalyzer {
                         Ok(Some(PeerDDL::CreateMirrorForSelect {
                             if_not_exists: *if_not_exists,
                             qrep_flow_job: Box::new(qrep_flow_job),
+                            warnings: processed.warnings,
                         }))
                     }
                 }
@@ -416,10 +464,11 @@ impl StatementAnalyzer for PeerDDLAnalyzer {
                 mirror_name,
                 with_options,
             } => {
-                let mut raw_options = HashMap::with_capacity(with_options.len());
-                for option in with_options {
-                    raw_options.insert(&option.name.value as &str, &option.value);
-                }
+                let mut raw_options = mirror_options(
+                    with_options
+                        .iter()
+                        .map(|option| (option.name.value.as_str(), &option.value)),
+                )?;
 
                 let query_string = match raw_options.remove("query_string") {
                     Some(Expr::Value(ast::Value::SingleQuotedString(s))) => Some(s.clone()),
@@ -507,6 +556,23 @@ impl StatementAnalyzer for PeerCursorAnalyzer {
     }
 }
 
+/// Collects mirror options by their lowercased names, so option names are
+/// matched case-insensitively like other SQL identifiers. A name given more
+/// than once, in any case, is an error rather than the last one winning.
+fn mirror_options<'a, T>(
+    options: impl Iterator<Item = (&'a str, T)>,
+) -> anyhow::Result<HashMap<String, T>> {
+    let mut raw_options = HashMap::new();
+    for (name, value) in options {
+        let name = name.to_lowercase();
+        if raw_options.contains_key(&name) {
+            anyhow::bail!("option {} is specified more than once", name);
+        }
+        raw_options.insert(name, value);
+    }
+    Ok(raw_options)
+}
+
 fn parse_db_options(db_type: DbType, with_options: &[SqlOption]) -> anyhow::Result<Option<Config>> {
     let mut opts: HashMap<&str, &str> = HashMap::with_capacity(with_options.len());
     for opt in with_options {
@@ -525,8 +591,11 @@ fn parse_db_options(db_type: DbType, with_options: &[SqlOption]) -> anyhow::Resu
             let pem_str = opts
                 .get("private_key")
                 .ok_or_else(|| anyhow::anyhow!("missing private_key option for bigquery"))?;
-            pem::parse(pem_str.as_bytes())
-                .map_err(|err| anyhow::anyhow!("unable to parse private_key: {:?}", err))?;
+            // secret references are only resolved when connecting
+            if !secrets::is_reference(pem_str) {
+                pem::parse(pem_str.as_bytes())
+                    .map_err(|err| anyhow::anyhow!("unable to parse private_key: {:?}", err))?;
+            }
             let bq_config = BigqueryConfig {
                 auth_type: opts
                     .get("type")
@@ -676,6 +745,11 @@ fn parse_db_options(db_type: DbType, with_options: &[SqlOption]) -> anyhow::Resu
                     .to_string(),
                 metadata_schema: opts.get("metadata_schema").map(|s| s.to_string()),
                 ssh_config: ssh_fields,
+                fetch_size: opts
+                    .get("fetch_size")
+                    .map(|s| s.parse::<u32>())
+                    .transpose()
+                    .context("unable to parse fetch_size as valid int")?,
             };
 
             Config::PostgresConfig(postgres_config)
@@ -794,8 +868,11 @@ fn parse_db_options(db_type: DbType, with_options: &[SqlOption]) -> anyhow::Resu
             let pem_str = opts
                 .get("private_key")
                 .ok_or_else(|| anyhow::anyhow!("missing private_key option for bigquery"))?;
-            pem::parse(pem_str.as_bytes())
-                .map_err(|err| anyhow::anyhow!("unable to parse private_key: {:?}", err))?;
+            // secret references are only resolved when connecting
+            if !secrets::is_reference(pem_str) {
+                pem::parse(pem_str.as_bytes())
+                    .map_err(|err| anyhow::anyhow!("unable to parse private_key: {:?}", err))?;
+            }
             let ps_config = PubSubConfig {
                 service_account: Some(GcpServiceAccount {
                     auth_type: opts
@@ -0,0 +1,113 @@
+use std::{collections::HashMap, fmt};
+
+use chrono::{Duration, NaiveDateTime};
+use serde_json::Value;
+
+/// Partitions a preview lists at most, so that a typo in the bounds doesn't
+/// plan millions of them.
+pub const MAX_PREVIEW_PARTITIONS: usize = 10_000;
+
+/// A value of a QRep watermark column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Watermark {
+    Int(i64),
+    Timestamp(NaiveDateTime),
+}
+
+impl fmt::Display for Watermark {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Watermark::Int(n) => write!(f, "{}", n),
+            Watermark::Timestamp(ts) => write!(f, "'{}'", ts.format("%Y-%m-%d %H:%M:%S%.f")),
+        }
+    }
+}
+
+/// The rows of one partition of a QRep mirror, those whose watermark is in
+/// `[lo, hi)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartitionRange {
+    pub lo: Watermark,
+    pub hi: Watermark,
+}
+
+impl PartitionRange {
+    /// The condition on `column` selecting the rows of the partition.
+    pub fn predicate(&self, column: &str) -> String {
+        format!("{} >= {} AND {} < {}", column, self.lo, column, self.hi)
+    }
+}
+
+fn int_option(opts: &HashMap<String, Value>, name: &str) -> anyhow::Result<u64> {
+    match opts.get(name).and_then(Value::as_u64) {
+        Some(n) if n > 0 => Ok(n),
+        _ => anyhow::bail!("{} must be a positive number", name),
+    }
+}
+
+/// Previews the partitions a QRep mirror with the processed options `opts`
+/// would copy, given the smallest and largest watermark of the source, both
+/// included. Integer watermarks split into ranges of
+/// `num_rows_per_partition` values, and timestamp watermarks into
+/// `parallelism` buckets of equal time. A source with `min` above `max` has
+/// nothing to copy and no partitions.
+pub fn preview_partitions(
+    opts: &HashMap<String, Value>,
+    min: Watermark,
+    max: Watermark,
+) -> anyhow::Result<Vec<PartitionRange>> {
+    if min > max {
+        return Ok(Vec::new());
+    }
+    match (min, max) {
+        (Watermark::Int(min), Watermark::Int(max)) => {
+            let step = int_option(opts, "num_rows_per_partition")? as i128;
+            let (min, end) = (min as i128, max as i128 + 1);
+            let count = (end - min + step - 1) / step;
+            check_count(count)?;
+            (0..count)
+                .map(|i| {
+                    let lo = min + i * step;
+                    let hi = (lo + step).min(end);
+                    Ok(PartitionRange {
+                        lo: Watermark::Int(lo as i64),
+                        hi: Watermark::Int(i64::try_from(hi).map_err(|_| {
+                            anyhow::anyhow!("partition from {} ends past the largest bigint", lo)
+                        })?),
+                    })
+                })
+                .collect()
+        }
+        (Watermark::Timestamp(min), Watermark::Timestamp(max)) => {
+            let buckets = int_option(opts, "parallelism")? as i64;
+            // the bounds are microseconds, and max is included
+            let span = (max - min)
+                .num_microseconds()
+                .and_then(|span| span.checked_add(1))
+                .ok_or_else(|| anyhow::anyhow!("watermark range is too wide"))?;
+            let width = (span + buckets - 1) / buckets;
+            let count = (span + width - 1) / width;
+            check_count(count as i128)?;
+            Ok((0..count)
+                .map(|i| PartitionRange {
+                    lo: Watermark::Timestamp(min + Duration::microseconds(i * width)),
+                    hi: Watermark::Timestamp(
+                        min + Duration::microseconds(((i + 1) * width).min(span)),
+                    ),
+                })
+                .collect())
+        }
+        _ => anyhow::bail!("watermark bounds must both be integers or both be timestamps"),
+    }
+}
+
+fn check_count(count: i128) -> anyhow::Result<()> {
+    if count > MAX_PREVIEW_PARTITIONS as i128 {
+        anyhow::bail!(
+            "{} partitions, more than the {} a preview lists",
+            count,
+            MAX_PREVIEW_PARTITIONS
+        );
+    }
+    Ok(())
+}
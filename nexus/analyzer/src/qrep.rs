@@ -1,8 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use serde_json::Value;
 use sqlparser::ast;
 
+use crate::identifier::{
+    parse_column_name, parse_identifier_list, parse_qualified_name, table_identifier,
+};
+
 enum QRepOptionType {
     String {
         name: &'static str,
@@ -26,6 +30,17 @@ enum QRepOptionType {
     },
 }
 
+impl QRepOptionType {
+    fn name(&self) -> &'static str {
+        match self {
+            QRepOptionType::String { name, .. }
+            | QRepOptionType::Int { name, .. }
+            | QRepOptionType::Boolean { name, .. }
+            | QRepOptionType::StringArray { name } => name,
+        }
+    }
+}
+
 const QREP_OPTIONS: &[QRepOptionType] = &[
     QRepOptionType::String {
         name: "destination_table_name",
@@ -54,6 +69,12 @@ const QREP_OPTIONS: &[QRepOptionType] = &[
     QRepOptionType::StringArray {
         name: "unique_key_columns",
     },
+    QRepOptionType::String {
+        name: "snapshot_isolation_level",
+        default_val: Some("repeatable_read"),
+        required: false,
+        accepted_values: Some(&["repeatable_read", "serializable"]),
+    },
     QRepOptionType::String {
         name: "staging_path",
         default_val: Some(""),
@@ -93,11 +114,104 @@ const QREP_OPTIONS: &[QRepOptionType] = &[
         default_value: false,
         required: false,
     },
+    QRepOptionType::Boolean {
+        name: "dry_run",
+        default_value: false,
+        required: false,
+    },
 ];
 
+/// A rule on how two options combine, checked once every option has been
+/// processed. `violated` sees the processed options, defaults included, and
+/// the names of the options that were given explicitly.
+struct OptionRule {
+    violated: fn(&HashMap<String, Value>, &HashSet<String>) -> bool,
+    message: &'static str,
+}
+
+const OPTION_RULES: &[OptionRule] = &[
+    OptionRule {
+        violated: |opts, _| {
+            is_one_of(opts, "mode", &["upsert"]) && !opts.contains_key("watermark_column")
+        },
+        message: "mode = 'upsert' needs a watermark_column, \
+            set watermark_column or use mode = 'overwrite'",
+    },
+    OptionRule {
+        violated: |opts, _| {
+            is_one_of(opts, "mode", &["upsert"]) && !has_columns(opts, "unique_key_columns")
+        },
+        message: "mode = 'upsert' needs unique_key_columns, list the columns to upsert on",
+    },
+    OptionRule {
+        violated: |opts, _| {
+            is_one_of(opts, "mode", &["overwrite"]) && has_columns(opts, "unique_key_columns")
+        },
+        message: "unique_key_columns is unused with mode = 'overwrite', \
+            remove unique_key_columns or use mode = 'upsert'",
+    },
+    OptionRule {
+        violated: |opts, specified| {
+            opts.get("initial_copy_only") == Some(&Value::Bool(true))
+                && specified.contains("refresh_interval")
+        },
+        message: "refresh_interval has no effect with initial_copy_only = true, \
+            remove refresh_interval or set initial_copy_only = false",
+    },
+    OptionRule {
+        violated: |opts, _| {
+            opts.get("setup_watermark_table_on_destination") == Some(&Value::Bool(true))
+                && !opts.contains_key("watermark_table_name")
+        },
+        message: "setup_watermark_table_on_destination = true needs a watermark_table_name, \
+            set watermark_table_name or remove setup_watermark_table_on_destination",
+    },
+    OptionRule {
+        // an initial copy only mirror has nothing to plan beyond the copy itself
+        violated: |opts, _| {
+            opts.get("dry_run") == Some(&Value::Bool(true))
+                && opts.get("initial_copy_only") == Some(&Value::Bool(true))
+        },
+        message: "dry_run cannot be combined with initial_copy_only = true, remove one of them",
+    },
+];
+
+/// Rules whose violations are likely mistakes rather than invalid mirrors,
+/// reported as warnings instead of failing the options.
+const OPTION_WARNINGS: &[OptionRule] = &[OptionRule {
+    // a one-off copy has no later runs to copy new rows in
+    violated: |opts, _| {
+        is_one_of(opts, "mode", &["append"])
+            && !opts.contains_key("watermark_column")
+            && opts.get("initial_copy_only") != Some(&Value::Bool(true))
+    },
+    message: "mode = 'append' without a watermark_column copies every row again on each run, \
+        set watermark_column to copy only new rows",
+}];
+
+fn is_one_of(opts: &HashMap<String, Value>, name: &str, values: &[&str]) -> bool {
+    matches!(opts.get(name), Some(Value::String(v)) if values.contains(&v.as_str()))
+}
+
+fn has_columns(opts: &HashMap<String, Value>, name: &str) -> bool {
+    matches!(opts.get(name), Some(Value::Array(columns)) if !columns.is_empty())
+}
+
+/// QRep options that passed validation, along with warnings about options
+/// that make a valid mirror which likely doesn't do what was meant.
+pub struct ProcessedOptions {
+    pub options: HashMap<String, Value>,
+    pub warnings: Vec<&'static str>,
+}
+
+/// Validates QRep options keyed by their lowercased names. When `strict`,
+/// options that have a default must be given explicitly, so that the
+/// mirror's configuration is fully spelled out by its definition.
 pub fn process_options(
-    mut raw_opts: HashMap<&str, &ast::Value>,
-) -> anyhow::Result<HashMap<String, Value>> {
+    mut raw_opts: HashMap<String, &ast::Value>,
+    strict: bool,
+) -> anyhow::Result<ProcessedOptions> {
+    let specified = raw_opts.keys().cloned().collect::<HashSet<_>>();
     let mut opts: HashMap<String, Value> = HashMap::new();
 
     for opt_type in QREP_OPTIONS {
@@ -115,13 +229,32 @@ pub fn process_options(
                                 anyhow::bail!("{} must be one of {:?}", name, values);
                             }
                         }
-                        opts.insert(name.to_string(), Value::String(str.clone()));
+                        let str = match *name {
+                            "destination_table_name" | "watermark_table_name" => {
+                                parse_qualified_name(str)
+                                    .and_then(|parts| table_identifier(&parts))
+                                    .map_err(|err| {
+                                        anyhow::anyhow!(
+                                            "{} must be a table name, optionally schema \
+                                            qualified and quoted: {}",
+                                            name,
+                                            err
+                                        )
+                                    })?
+                            }
+                            "watermark_column" => parse_column_name(str)?,
+                            _ => str.clone(),
+                        };
+                        opts.insert(name.to_string(), Value::String(str));
                     } else {
                         anyhow::bail!("Invalid value for {}", name);
                     }
                 } else if *required {
                     anyhow::bail!("{} is required", name);
                 } else if let Some(default) = default_val {
+                    if strict {
+                        anyhow::bail!(strict_default_error(name));
+                    }
                     opts.insert(name.to_string(), Value::String(default.to_string()));
                 }
             }
@@ -145,19 +278,24 @@ pub fn process_options(
                     }
                 } else if *required {
                     anyhow::bail!("{} is required", name);
+                } else if strict {
+                    anyhow::bail!(strict_default_error(name));
                 } else {
                     let v = *default_value;
                     opts.insert(name.to_string(), Value::Number(v.into()));
                 }
             }
             QRepOptionType::StringArray { name } => {
-                // read it as a string and split on comma
+                // read it as a string and split on commas outside quotes
                 if let Some(raw_value) = raw_opts.remove(*name) {
                     if let ast::Value::SingleQuotedString(str) = raw_value {
-                        let values: Vec<Value> = str
-                            .split(',')
-                            .map(|s| Value::String(s.trim().to_string()))
-                            .collect();
+                        let values = parse_identifier_list(str)?
+                            .into_iter()
+                            .map(|parts| match <[String; 1]>::try_from(parts) {
+                                Ok([column]) => Ok(Value::String(column)),
+                                Err(_) => anyhow::bail!("{} must list column names", name),
+                            })
+                            .collect::<anyhow::Result<Vec<_>>>()?;
                         opts.insert(name.to_string(), Value::Array(values));
                     } else {
                         anyhow::bail!("Invalid value for {}", name);
@@ -177,6 +315,8 @@ pub fn process_options(
                     }
                 } else if *required {
                     anyhow::bail!("{} is required", name);
+                } else if strict {
+                    anyhow::bail!(strict_default_error(name));
                 } else {
                     let v = *default_value;
                     opts.insert(name.to_string(), Value::Bool(v));
@@ -188,20 +328,67 @@ pub fn process_options(
     // all options processed have been removed from the map
     // so any leftover keys are options that shouldn't be here
     if !raw_opts.is_empty() {
+        let mut unknown = raw_opts
+            .into_keys()
+            .map(|name| match suggest_option(&name) {
+                Some(known) => format!("{} (did you mean {}?)", name, known),
+                None => name,
+            })
+            .collect::<Vec<_>>();
+        unknown.sort();
+        anyhow::bail!("Unknown options for QRep mirrors: {:#?}", unknown);
+    }
+
+    let violations = OPTION_RULES
+        .iter()
+        .filter(|rule| (rule.violated)(&opts, &specified))
+        .map(|rule| rule.message)
+        .collect::<Vec<_>>();
+    if !violations.is_empty() {
         anyhow::bail!(
-            "Unknown options for QRep mirrors: {:#?}",
-            raw_opts.into_keys().collect::<Vec<&str>>()
+            "Conflicting options for QRep mirror: {}",
+            violations.join("; ")
         );
     }
 
-    // If mode is upsert, we need unique key columns
-    if opts.get("mode") == Some(&Value::String(String::from("upsert")))
-        && opts
-            .get("unique_key_columns")
-            .map(|ukc| ukc == &Value::Array(Vec::new()))
-            .unwrap_or(true)
-    {
-        anyhow::bail!("For upsert mode, unique_key_columns must be specified");
+    let warnings = OPTION_WARNINGS
+        .iter()
+        .filter(|rule| (rule.violated)(&opts, &specified))
+        .map(|rule| rule.message)
+        .collect();
+    Ok(ProcessedOptions {
+        options: opts,
+        warnings,
+    })
+}
+
+fn strict_default_error(name: &str) -> String {
+    format!(
+        "{} must be set explicitly, strict mode applies no defaults",
+        name
+    )
+}
+
+// the known option an unknown one is most likely a typo of
+fn suggest_option(name: &str) -> Option<&'static str> {
+    QREP_OPTIONS
+        .iter()
+        .map(|opt| (edit_distance(name, opt.name()), opt.name()))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, known)| known)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
     }
-    Ok(opts)
+    prev[b.len()]
 }
@@ -0,0 +1,559 @@
+use std::collections::HashMap;
+
+use analyzer::{
+    preview_partitions, PartitionRange, PeerDDL, PeerDDLAnalyzer, PeerExistanceAnalyzer,
+    QueryAssociation, StatementAnalyzer, Watermark,
+};
+use pt::peerdb_peers::Peer;
+use sqlparser::{dialect::PostgreSqlDialect, parser::Parser};
+
+fn analyze(sql: &str) -> anyhow::Result<PeerDDL> {
+    let mut statements = Parser::parse_sql(&PostgreSqlDialect {}, sql)?;
+    assert_eq!(statements.len(), 1);
+    let ddl = PeerDDLAnalyzer.analyze(&statements.remove(0))?;
+    Ok(ddl.expect("statement should be peer ddl"))
+}
+
+#[test]
+fn cdc_mapping_keeps_quoted_mixed_case_names() {
+    let ddl = analyze(
+        r#"CREATE MIRROR m FROM src TO dst
+        WITH TABLE MAPPING ("Public"."MyTable":"Target"."OutTable", public.plain:Dst.plain)
+        WITH (do_initial_copy = true)"#,
+    )
+    .unwrap();
+
+    let PeerDDL::CreateMirrorForCDC { flow_job, .. } = ddl else {
+        panic!("expected a cdc mirror, got {:?}", ddl);
+    };
+    let mappings = flow_job
+        .table_mappings
+        .iter()
+        .map(|m| {
+            (
+                m.source_table_identifier.as_str(),
+                m.destination_table_identifier.as_str(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        mappings,
+        [
+            ("Public.MyTable", "Target.OutTable"),
+            ("public.plain", "Dst.plain")
+        ]
+    );
+}
+
+#[test]
+fn cdc_mapping_rejects_dot_inside_quoted_name() {
+    let err = analyze(
+        r#"CREATE MIRROR m FROM src TO dst
+        WITH TABLE MAPPING ("my.schema".t:public.t)
+        WITH (do_initial_copy = true)"#,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("my.schema"), "{}", err);
+}
+
+#[test]
+fn qrep_options_keep_quoted_mixed_case_names() {
+    use serde_json::{json, Value};
+
+    let ddl = analyze(
+        r#"CREATE MIRROR m FROM src TO dst FOR $$ SELECT * FROM "Public"."MyTable" $$
+        WITH (
+            destination_table_name = '"Target"."OutTable"',
+            watermark_table_name = '"Public"."MyTable"',
+            watermark_column = '"updatedAt"',
+            mode = 'upsert',
+            unique_key_columns = '"Key,Part", id ,"Quoted""Name"',
+            num_rows_per_partition = 1000
+        )"#,
+    )
+    .unwrap();
+
+    let PeerDDL::CreateMirrorForSelect { qrep_flow_job, .. } = ddl else {
+        panic!("expected a qrep mirror, got {:?}", ddl);
+    };
+    let opts = &qrep_flow_job.flow_options;
+    assert_eq!(
+        opts["destination_table_name"],
+        Value::from("Target.OutTable")
+    );
+    assert_eq!(opts["watermark_table_name"], Value::from("Public.MyTable"));
+    assert_eq!(opts["watermark_column"], Value::from("updatedAt"));
+    assert_eq!(
+        opts["unique_key_columns"],
+        json!(["Key,Part", "id", "Quoted\"Name"])
+    );
+}
+
+#[test]
+fn qrep_options_reject_malformed_identifiers() {
+    for (option, value) in [
+        ("destination_table_name", r#"'"Public.MyTable'"#),
+        ("destination_table_name", r#"'public..t'"#),
+        ("watermark_column", r#"'t.col'"#),
+        ("unique_key_columns", r#"'a,"b"c'"#),
+    ] {
+        let mut opts = vec![
+            "destination_table_name = 'public.t'".to_string(),
+            "num_rows_per_partition = 1000".to_string(),
+            "watermark_column = 'id'".to_string(),
+        ];
+        opts.retain(|opt| !opt.starts_with(option));
+        opts.push(format!("{} = {}", option, value));
+        let sql = format!(
+            "CREATE MIRROR m FROM src TO dst FOR $$ SELECT 1 $$ WITH ({})",
+            opts.join(", ")
+        );
+        assert!(
+            analyze(&sql).is_err(),
+            "{} = {} was accepted",
+            option,
+            value
+        );
+    }
+}
+
+fn destination_table_name(value: &str) -> anyhow::Result<serde_json::Value> {
+    let ddl = analyze(&format!(
+        "CREATE MIRROR m FROM src TO dst FOR $$ SELECT 1 $$
+        WITH (destination_table_name = '{}', num_rows_per_partition = 1000)",
+        value
+    ))?;
+    let PeerDDL::CreateMirrorForSelect { qrep_flow_job, .. } = ddl else {
+        panic!("expected a qrep mirror, got {:?}", ddl);
+    };
+    Ok(qrep_flow_job.flow_options["destination_table_name"].clone())
+}
+
+#[test]
+fn qrep_destination_table_name_may_quote_spaces() {
+    assert_eq!(
+        destination_table_name(r#"public."My Table""#).unwrap(),
+        "public.My Table"
+    );
+}
+
+#[test]
+fn qrep_destination_table_name_must_be_an_identifier() {
+    for value in [
+        "",
+        "   ",
+        "public.my table",
+        "t\u{7}",
+        "2fa",
+        "public.\"\t\"",
+    ] {
+        let err = destination_table_name(value).unwrap_err().to_string();
+        assert!(
+            err.contains("destination_table_name must be a table name"),
+            "{:?}: {}",
+            value,
+            err
+        );
+    }
+}
+
+#[test]
+fn qrep_dry_run_is_carried_through() {
+    use serde_json::Value;
+
+    let ddl = analyze(
+        "CREATE MIRROR m FROM src TO dst FOR $$ SELECT 1 $$
+        WITH (destination_table_name = 'public.t', num_rows_per_partition = 1000,
+              mode = 'overwrite', dry_run = true)",
+    )
+    .unwrap();
+    let PeerDDL::CreateMirrorForSelect { qrep_flow_job, .. } = ddl else {
+        panic!("expected a qrep mirror, got {:?}", ddl);
+    };
+    assert_eq!(qrep_flow_job.flow_options["dry_run"], Value::Bool(true));
+
+    let ddl = analyze(
+        "CREATE MIRROR m FROM src TO dst FOR $$ SELECT 1 $$
+        WITH (destination_table_name = 'public.t', num_rows_per_partition = 1000,
+              watermark_column = 'id')",
+    )
+    .unwrap();
+    let PeerDDL::CreateMirrorForSelect { qrep_flow_job, .. } = ddl else {
+        panic!("expected a qrep mirror, got {:?}", ddl);
+    };
+    assert_eq!(qrep_flow_job.flow_options["dry_run"], Value::Bool(false));
+}
+
+#[test]
+fn qrep_dry_run_conflicts_with_initial_copy_only() {
+    let err = analyze(
+        "CREATE MIRROR m FROM src TO dst FOR $$ SELECT 1 $$
+        WITH (destination_table_name = 'public.t', num_rows_per_partition = 1000,
+              watermark_column = 'id', dry_run = true, initial_copy_only = true)",
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("dry_run"), "{}", err);
+}
+
+#[test]
+fn qrep_snapshot_isolation_level_is_validated() {
+    use serde_json::Value;
+
+    let opts = qrep_options("num_rows_per_partition = 1000");
+    assert_eq!(
+        opts["snapshot_isolation_level"],
+        Value::from("repeatable_read")
+    );
+    for level in ["repeatable_read", "serializable"] {
+        let opts = qrep_options(&format!(
+            "num_rows_per_partition = 1000, snapshot_isolation_level = '{}'",
+            level
+        ));
+        assert_eq!(opts["snapshot_isolation_level"], Value::from(level));
+    }
+
+    let err = qrep_options_error("snapshot_isolation_level = 'read_committed'");
+    assert!(
+        err.contains("snapshot_isolation_level must be one of"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn option_names_are_case_insensitive() {
+    use serde_json::Value;
+
+    let ddl = analyze(
+        "CREATE MIRROR m FROM src TO dst FOR $$ SELECT 1 $$
+        WITH (Destination_Table_Name = 'public.t', NUM_ROWS_PER_PARTITION = 1000,
+              REFRESH_INTERVAL = 60, Watermark_Column = 'id')",
+    )
+    .unwrap();
+    let PeerDDL::CreateMirrorForSelect { qrep_flow_job, .. } = ddl else {
+        panic!("expected a qrep mirror, got {:?}", ddl);
+    };
+    let opts = &qrep_flow_job.flow_options;
+    assert_eq!(opts["destination_table_name"], Value::from("public.t"));
+    assert_eq!(opts["refresh_interval"], Value::from(60));
+    assert!(opts.keys().all(|k| *k == k.to_lowercase()));
+
+    let ddl = analyze(
+        "CREATE MIRROR m FROM src TO dst WITH TABLE MAPPING (public.a:public.b)
+        WITH (Do_Initial_Copy = true, MAX_BATCH_SIZE = 100)",
+    )
+    .unwrap();
+    let PeerDDL::CreateMirrorForCDC { flow_job, .. } = ddl else {
+        panic!("expected a cdc mirror, got {:?}", ddl);
+    };
+    assert!(flow_job.do_initial_copy);
+    assert_eq!(flow_job.max_batch_size, Some(100));
+}
+
+#[test]
+fn option_names_differing_by_case_are_duplicates() {
+    let err = analyze(
+        "CREATE MIRROR m FROM src TO dst FOR $$ SELECT 1 $$
+        WITH (destination_table_name = 'public.t', num_rows_per_partition = 1000,
+              PARALLELISM = 2, parallelism = 4)",
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("parallelism"), "{}", err);
+
+    let err = analyze(
+        "CREATE MIRROR m FROM src TO dst WITH TABLE MAPPING (public.a:public.b)
+        WITH (do_initial_copy = true, Do_Initial_Copy = false)",
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("do_initial_copy"), "{}", err);
+}
+
+#[test]
+fn unknown_option_suggests_known_name() {
+    let err = analyze(
+        "CREATE MIRROR m FROM src TO dst FOR $$ SELECT 1 $$
+        WITH (destination_table_name = 'public.t', num_rows_per_partition = 1000,
+              Watermark_Colum = 'id')",
+    )
+    .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("watermark_colum (did you mean watermark_column?)"),
+        "{}",
+        err
+    );
+}
+
+fn qrep_options_error(opts: &str) -> String {
+    let sql = format!(
+        "CREATE MIRROR m FROM src TO dst FOR $$ SELECT 1 $$
+        WITH (destination_table_name = 'public.t', num_rows_per_partition = 1000, {})",
+        opts
+    );
+    analyze(&sql).unwrap_err().to_string()
+}
+
+#[test]
+fn qrep_option_combinations_are_validated() {
+    for (opts, named) in [
+        (
+            "mode = 'upsert', unique_key_columns = 'id'",
+            ["mode", "watermark_column"],
+        ),
+        (
+            "watermark_column = 'id', mode = 'upsert'",
+            ["mode", "unique_key_columns"],
+        ),
+        (
+            "mode = 'overwrite', unique_key_columns = 'id'",
+            ["mode", "unique_key_columns"],
+        ),
+        (
+            "mode = 'overwrite', initial_copy_only = true, refresh_interval = 60",
+            ["initial_copy_only", "refresh_interval"],
+        ),
+        (
+            "mode = 'overwrite', setup_watermark_table_on_destination = true",
+            [
+                "setup_watermark_table_on_destination",
+                "watermark_table_name",
+            ],
+        ),
+    ] {
+        let err = qrep_options_error(opts);
+        for name in named {
+            assert!(err.contains(name), "{}: {}", opts, err);
+        }
+    }
+
+    // the default refresh_interval doesn't count as tuned
+    analyze(
+        "CREATE MIRROR m FROM src TO dst FOR $$ SELECT 1 $$
+        WITH (destination_table_name = 'public.t', num_rows_per_partition = 1000,
+              mode = 'overwrite', initial_copy_only = true)",
+    )
+    .unwrap();
+}
+
+fn qrep_warnings(opts: &str) -> Vec<&'static str> {
+    let ddl = analyze(&format!(
+        "CREATE MIRROR m FROM src TO dst FOR $$ SELECT 1 $$
+        WITH (destination_table_name = 'public.t', num_rows_per_partition = 1000, {})",
+        opts
+    ))
+    .unwrap();
+    let PeerDDL::CreateMirrorForSelect { warnings, .. } = ddl else {
+        panic!("expected a qrep mirror, got {:?}", ddl);
+    };
+    warnings
+}
+
+#[test]
+fn qrep_append_without_watermark_warns() {
+    let warnings = qrep_warnings("mode = 'append'");
+    assert_eq!(warnings.len(), 1, "{:?}", warnings);
+    assert!(warnings[0].contains("watermark_column"), "{}", warnings[0]);
+
+    assert!(qrep_warnings("mode = 'append', watermark_column = 'id'").is_empty());
+}
+
+#[test]
+fn qrep_append_without_watermark_is_fine_for_initial_copy_only() {
+    assert!(qrep_warnings("mode = 'append', initial_copy_only = true").is_empty());
+}
+
+#[test]
+fn qrep_strict_mode_rejects_omitted_options() {
+    let err = qrep_options_error("strict = true");
+    assert!(err.contains("mode must be set explicitly"), "{}", err);
+
+    // without strict the omitted options get their defaults
+    analyze(
+        "CREATE MIRROR m FROM src TO dst FOR $$ SELECT 1 $$
+        WITH (destination_table_name = 'public.t', num_rows_per_partition = 1000)",
+    )
+    .unwrap();
+}
+
+#[test]
+fn qrep_strict_mode_accepts_fully_specified_options() {
+    let ddl = analyze(
+        "CREATE MIRROR m FROM src TO dst FOR $$ SELECT 1 $$
+        WITH (destination_table_name = 'public.t', num_rows_per_partition = 1000,
+              strict = true, mode = 'append', watermark_column = 'id',
+              snapshot_isolation_level = 'repeatable_read', staging_path = '',
+              parallelism = 4, refresh_interval = 60, initial_copy_only = false,
+              setup_watermark_table_on_destination = false,
+              dst_table_full_resync = false, dry_run = false)",
+    )
+    .unwrap();
+    let PeerDDL::CreateMirrorForSelect { qrep_flow_job, .. } = ddl else {
+        panic!("expected a qrep mirror, got {:?}", ddl);
+    };
+    assert_eq!(qrep_flow_job.flow_options["parallelism"], 4);
+    assert!(!qrep_flow_job.flow_options.contains_key("strict"));
+}
+
+#[test]
+fn qrep_option_violations_are_reported_together() {
+    let err = qrep_options_error(
+        "mode = 'upsert', setup_watermark_table_on_destination = true, \
+         initial_copy_only = true, refresh_interval = 60",
+    );
+    for name in [
+        "watermark_column",
+        "unique_key_columns",
+        "refresh_interval",
+        "watermark_table_name",
+    ] {
+        assert!(err.contains(name), "{}", err);
+    }
+}
+
+fn route(sql: &str, default_peer: Option<&str>) -> anyhow::Result<Option<String>> {
+    let peers = ["pg_1", "pg_2"]
+        .into_iter()
+        .map(|name| {
+            let peer = Peer {
+                name: name.to_string(),
+                ..Default::default()
+            };
+            (name.to_string(), peer)
+        })
+        .collect::<HashMap<_, _>>();
+    let mut statements = Parser::parse_sql(&PostgreSqlDialect {}, sql)?;
+    let assoc = PeerExistanceAnalyzer::new(&peers)
+        .with_default_peer(default_peer)
+        .analyze(&statements.remove(0))?;
+    Ok(match assoc {
+        QueryAssociation::Peer(peer) => Some(peer.name),
+        QueryAssociation::Catalog => None,
+    })
+}
+
+#[test]
+fn bare_tables_route_to_default_peer() {
+    assert_eq!(
+        route("SELECT * FROM users", Some("pg_1"))
+            .unwrap()
+            .as_deref(),
+        Some("pg_1")
+    );
+    assert_eq!(
+        route("SELECT * FROM public.users", Some("pg_1"))
+            .unwrap()
+            .as_deref(),
+        Some("pg_1")
+    );
+    // without a default peer bare tables are the catalog's
+    assert_eq!(route("SELECT * FROM peers", None).unwrap(), None);
+    // statements without tables stay on the catalog
+    assert_eq!(
+        route("SET peerdb.batch_bytes = 1024", Some("pg_1")).unwrap(),
+        None
+    );
+}
+
+#[test]
+fn peer_qualified_tables_override_default_peer() {
+    assert_eq!(
+        route("SELECT * FROM pg_2.public.users", Some("pg_1"))
+            .unwrap()
+            .as_deref(),
+        Some("pg_2")
+    );
+    assert!(route("SELECT * FROM users", Some("dropped_peer")).is_err());
+}
+
+fn qrep_options(options: &str) -> HashMap<String, serde_json::Value> {
+    let ddl = analyze(&format!(
+        "CREATE MIRROR m FROM src TO dst FOR $$ SELECT 1 $$
+        WITH (destination_table_name = 'public.t', watermark_column = 'id', {})",
+        options
+    ))
+    .unwrap();
+    let PeerDDL::CreateMirrorForSelect { qrep_flow_job, .. } = ddl else {
+        panic!("expected a qrep mirror, got {:?}", ddl);
+    };
+    qrep_flow_job.flow_options
+}
+
+#[test]
+fn numeric_watermarks_split_by_row_count() {
+    let opts = qrep_options("num_rows_per_partition = 100");
+    let ranges = preview_partitions(&opts, Watermark::Int(1), Watermark::Int(250)).unwrap();
+    let bounds = ranges
+        .iter()
+        .map(|range| (range.lo, range.hi))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        bounds,
+        vec![
+            (Watermark::Int(1), Watermark::Int(101)),
+            (Watermark::Int(101), Watermark::Int(201)),
+            (Watermark::Int(201), Watermark::Int(251)),
+        ]
+    );
+    assert_eq!(ranges[0].predicate("id"), "id >= 1 AND id < 101");
+
+    // a single watermark value is a single partition
+    let ranges = preview_partitions(&opts, Watermark::Int(7), Watermark::Int(7)).unwrap();
+    assert_eq!(
+        ranges,
+        vec![PartitionRange {
+            lo: Watermark::Int(7),
+            hi: Watermark::Int(8),
+        }]
+    );
+    assert!(preview_partitions(&opts, Watermark::Int(0), Watermark::Int(i64::MAX)).is_err());
+}
+
+#[test]
+fn timestamp_watermarks_split_into_equal_buckets() {
+    use chrono::NaiveDate;
+
+    let at = |hour| {
+        Watermark::Timestamp(
+            NaiveDate::from_ymd_opt(2024, 1, 1)
+                .unwrap()
+                .and_hms_opt(hour, 0, 0)
+                .unwrap(),
+        )
+    };
+    let opts = qrep_options("num_rows_per_partition = 100, parallelism = 4");
+    let ranges = preview_partitions(&opts, at(0), at(8)).unwrap();
+    assert_eq!(ranges.len(), 4);
+    assert_eq!(ranges[0].lo, at(0));
+    assert!(ranges.windows(2).all(|pair| pair[0].hi == pair[1].lo));
+    // the last bucket takes in the largest watermark
+    let Watermark::Timestamp(last) = ranges[3].hi else {
+        panic!("expected a timestamp bound");
+    };
+    assert_eq!(
+        last,
+        NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_hms_micro_opt(8, 0, 0, 1)
+            .unwrap()
+    );
+    assert_eq!(
+        ranges[0].predicate("updated_at"),
+        "updated_at >= '2024-01-01 00:00:00' AND updated_at < '2024-01-01 02:00:00.000001'"
+    );
+}
+
+#[test]
+fn empty_watermark_range_has_no_partitions() {
+    let opts = qrep_options("num_rows_per_partition = 100");
+    assert!(
+        preview_partitions(&opts, Watermark::Int(10), Watermark::Int(9))
+            .unwrap()
+            .is_empty()
+    );
+    assert!(preview_partitions(
+        &opts,
+        Watermark::Int(1),
+        Watermark::Timestamp(Default::default())
+    )
+    .is_err());
+}
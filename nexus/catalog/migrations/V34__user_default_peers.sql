@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS user_default_peers (
+  user_name TEXT PRIMARY KEY,
+  peer_id INTEGER NOT NULL REFERENCES peers(id) ON DELETE CASCADE
+);
@@ -69,6 +69,7 @@ impl<'a> CatalogConfig<'a> {
             database: self.database.to_string(),
             metadata_schema: Some("".to_string()),
             ssh_config: None,
+            fetch_size: None,
         }
     }
 
@@ -250,6 +251,22 @@ impl Catalog {
         }
     }
 
+    /// The peer a user's sessions route tables not qualified by a peer name
+    /// to, until they set `peerdb.default_peer` themselves.
+    pub async fn get_user_default_peer(&self, user_name: &str) -> anyhow::Result<Option<String>> {
+        let stmt = self
+            .pg
+            .prepare_typed(
+                "SELECT p.name FROM public.user_default_peers u
+                 JOIN public.peers p ON p.id = u.peer_id WHERE u.user_name = $1",
+                &[],
+            )
+            .await?;
+
+        let row = self.pg.query_opt(&stmt, &[&user_name]).await?;
+        Ok(row.map(|row| row.get::<_, String>(0).to_lowercase()))
+    }
+
     pub async fn get_peer_by_id(&self, peer_id: i32) -> anyhow::Result<Peer> {
         let stmt = self
             .pg
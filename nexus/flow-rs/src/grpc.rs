@@ -3,6 +3,7 @@ use pt::{
     flow_model::{FlowJob, QRepFlowJob},
     peerdb_flow::{QRepWriteMode, QRepWriteType, TypeSystem},
     peerdb_route,
+    secrets::resolve,
     tonic,
 };
 use serde_json::Value;
@@ -147,8 +148,9 @@ impl FlowGrpcClient {
             snapshot_num_rows_per_partition: snapshot_num_rows_per_partition.unwrap_or(0),
             snapshot_max_parallel_workers: snapshot_max_parallel_workers.unwrap_or(0),
             snapshot_num_tables_in_parallel: snapshot_num_tables_in_parallel.unwrap_or(0),
-            snapshot_staging_path: job.snapshot_staging_path.clone(),
-            cdc_staging_path: job.cdc_staging_path.clone().unwrap_or_default(),
+            snapshot_staging_path: resolve(&job.snapshot_staging_path)?.into_owned(),
+            cdc_staging_path: resolve(job.cdc_staging_path.as_deref().unwrap_or_default())?
+                .into_owned(),
             soft_delete: job.soft_delete,
             replication_slot_name: replication_slot_name.unwrap_or_default(),
             max_batch_size: job.max_batch_size.unwrap_or_default(),
@@ -223,7 +225,8 @@ impl FlowGrpcClient {
                             _ => return anyhow::Result::Err(anyhow::anyhow!("invalid mode {}", s)),
                         }
                     }
-                    "staging_path" => cfg.staging_path.clone_from(s),
+                    "staging_path" => cfg.staging_path = resolve(s)?.into_owned(),
+                    "snapshot_isolation_level" => cfg.snapshot_isolation_level.clone_from(s),
                     _ => return anyhow::Result::Err(anyhow::anyhow!("invalid str option {}", key)),
                 },
                 Value::Number(n) => match key.as_str() {
@@ -251,6 +254,8 @@ impl FlowGrpcClient {
                         cfg.setup_watermark_table_on_destination = *v;
                     } else if key == "dst_table_full_resync" {
                         cfg.dst_table_full_resync = *v;
+                    } else if key == "dry_run" {
+                        cfg.dry_run = *v;
                     } else {
                         return anyhow::Result::Err(anyhow::anyhow!("invalid bool option {}", key));
                     }
@@ -1,4 +1,7 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
 
 use analyzer::{
     CursorEvent, PeerCursorAnalyzer, PeerDDL, PeerDDLAnalyzer, PeerExistanceAnalyzer,
@@ -6,9 +9,10 @@ use analyzer::{
 };
 use async_trait::async_trait;
 use catalog::Catalog;
+use pgerror::{sqlstate, PgError};
 use pgwire::{
     api::{stmt::QueryParser, Type},
-    error::{ErrorInfo, PgWireError, PgWireResult},
+    error::{PgWireError, PgWireResult},
 };
 use sqlparser::{ast::Statement, dialect::PostgreSqlDialect, parser::Parser};
 
@@ -17,6 +21,9 @@ const DIALECT: PostgreSqlDialect = PostgreSqlDialect {};
 #[derive(Clone)]
 pub struct NexusQueryParser {
     catalog: Arc<Catalog>,
+    // the session's peerdb.default_peer, shared with the clones handed to
+    // pgwire so statements prepared later see it too
+    default_peer: Arc<RwLock<Option<String>>>,
 }
 
 #[derive(Debug, Clone)]
@@ -43,13 +50,10 @@ impl NexusStatement {
     pub fn new(
         peers: HashMap<String, pt::peerdb_peers::Peer>,
         stmt: &Statement,
+        default_peer: Option<&str>,
     ) -> PgWireResult<Self> {
         let ddl = PeerDDLAnalyzer.analyze(stmt).map_err(|e| {
-            PgWireError::UserError(Box::new(ErrorInfo::new(
-                "ERROR".to_owned(),
-                "internal_error".to_owned(),
-                e.to_string(),
-            )))
+            PgWireError::from(PgError::new(sqlstate::INTERNAL_ERROR, e.to_string()))
         })?;
 
         if let Some(ddl) = ddl {
@@ -67,13 +71,9 @@ impl NexusStatement {
         }
 
         let assoc = {
-            let pea = PeerExistanceAnalyzer::new(&peers);
+            let pea = PeerExistanceAnalyzer::new(&peers).with_default_peer(default_peer);
             pea.analyze(stmt).map_err(|e| {
-                PgWireError::UserError(Box::new(ErrorInfo::new(
-                    "ERROR".to_owned(),
-                    "feature_not_supported".to_owned(),
-                    e.to_string(),
-                )))
+                PgWireError::from(PgError::new(sqlstate::FEATURE_NOT_SUPPORTED, e.to_string()))
             })
         }?;
 
@@ -88,56 +88,81 @@ impl NexusStatement {
 pub struct NexusParsedStatement {
     pub statement: NexusStatement,
     pub query: String,
+    /// The default peer the statement was analyzed with. A prepared statement
+    /// keeps routing to it when the session changes its default peer.
+    pub default_peer: Option<String>,
 }
 
 impl NexusQueryParser {
     pub fn new(catalog: Arc<Catalog>) -> Self {
-        Self { catalog }
+        Self {
+            catalog,
+            default_peer: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// The peer tables not qualified by a peer name are routed to.
+    pub fn default_peer(&self) -> Option<String> {
+        self.default_peer.read().unwrap().clone()
+    }
+
+    pub fn set_default_peer(&self, default_peer: Option<String>) {
+        *self.default_peer.write().unwrap() = default_peer;
     }
 
     pub async fn get_peers_bridge(&self) -> PgWireResult<HashMap<String, pt::peerdb_peers::Peer>> {
         let peers = self.catalog.get_peers().await;
 
-        peers.map_err(|e| {
-            PgWireError::UserError(Box::new(ErrorInfo::new(
-                "ERROR".to_owned(),
-                "internal_error".to_owned(),
-                e.to_string(),
-            )))
-        })
+        peers.map_err(|e| PgWireError::from(PgError::new(sqlstate::INTERNAL_ERROR, e.to_string())))
+    }
+
+    /// Splits a simple query message into its statements. An empty message
+    /// has no statements. A lone `RESET name`, which the SQL parser doesn't
+    /// know, is taken as the `SET name TO DEFAULT` it stands for.
+    pub fn parse_statements(&self, sql: &str) -> PgWireResult<Vec<Statement>> {
+        let reset = reset_as_set(sql);
+        Parser::parse_sql(&DIALECT, reset.as_deref().unwrap_or(sql))
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))
     }
 
-    pub async fn parse_simple_sql(&self, sql: &str) -> PgWireResult<NexusParsedStatement> {
-        let mut stmts =
-            Parser::parse_sql(&DIALECT, sql).map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+    /// Parses the statement of a prepared statement, `None` when it is empty.
+    /// Like postgres, a prepared statement holds a single command.
+    pub fn parse_single_statement(&self, sql: &str) -> PgWireResult<Option<Statement>> {
+        let mut stmts = self.parse_statements(sql)?;
         if stmts.len() > 1 {
-            let err_msg = format!("unsupported sql: {}, statements: {:?}", sql, stmts);
-            // TODO (kaushik): Better error message for this. When do we start seeing multiple statements?
-            Err(PgWireError::UserError(Box::new(ErrorInfo::new(
-                "ERROR".to_owned(),
-                "42P14".to_owned(),
-                err_msg,
-            ))))
-        } else if stmts.is_empty() {
+            return Err(PgError::new(
+                sqlstate::SYNTAX_ERROR,
+                "cannot insert multiple commands into a prepared statement",
+            )
+            .into());
+        }
+        Ok(stmts.pop())
+    }
+
+    /// Analyzes a single statement of a simple query message, routing tables
+    /// not qualified by a peer name to `default_peer`. Peers are looked up for
+    /// every statement, so a statement can use a peer created by an earlier
+    /// statement of the same message.
+    pub async fn analyze_statement(
+        &self,
+        stmt: Statement,
+        default_peer: Option<String>,
+    ) -> PgWireResult<NexusParsedStatement> {
+        let query = stmt.to_string();
+        if matches!(stmt, Statement::Rollback { .. }) {
             Ok(NexusParsedStatement {
-                statement: NexusStatement::Empty,
-                query: sql.to_owned(),
+                statement: NexusStatement::Rollback { stmt },
+                query,
+                default_peer,
             })
         } else {
-            let stmt = stmts.remove(0);
-            if matches!(stmt, Statement::Rollback { .. }) {
-                Ok(NexusParsedStatement {
-                    statement: NexusStatement::Rollback { stmt },
-                    query: sql.to_owned(),
-                })
-            } else {
-                let peers = self.get_peers_bridge().await?;
-                let nexus_stmt = NexusStatement::new(peers, &stmt)?;
-                Ok(NexusParsedStatement {
-                    statement: nexus_stmt,
-                    query: sql.to_owned(),
-                })
-            }
+            let peers = self.get_peers_bridge().await?;
+            let nexus_stmt = NexusStatement::new(peers, &stmt, default_peer.as_deref())?;
+            Ok(NexusParsedStatement {
+                statement: nexus_stmt,
+                query,
+                default_peer,
+            })
         }
     }
 }
@@ -147,28 +172,39 @@ impl QueryParser for NexusQueryParser {
     type Statement = NexusParsedStatement;
 
     async fn parse_sql(&self, sql: &str, _types: &[Type]) -> PgWireResult<Self::Statement> {
-        let mut stmts =
-            Parser::parse_sql(&DIALECT, sql).map_err(|e| PgWireError::ApiError(Box::new(e)))?;
-        if stmts.len() > 1 {
-            let err_msg = format!("unsupported sql: {}, statements: {:?}", sql, stmts);
-            Err(PgWireError::UserError(Box::new(ErrorInfo::new(
-                "ERROR".to_owned(),
-                "42P14".to_owned(),
-                err_msg,
-            ))))
-        } else if stmts.is_empty() {
-            Ok(NexusParsedStatement {
+        match self.parse_single_statement(sql)? {
+            None => Ok(NexusParsedStatement {
                 statement: NexusStatement::Empty,
                 query: sql.to_owned(),
-            })
-        } else {
-            let stmt = stmts.remove(0);
-            let peers = self.get_peers_bridge().await?;
-            let nexus_stmt = NexusStatement::new(peers, &stmt)?;
-            Ok(NexusParsedStatement {
-                statement: nexus_stmt,
-                query: sql.to_owned(),
-            })
+                default_peer: None,
+            }),
+            Some(stmt) => {
+                let peers = self.get_peers_bridge().await?;
+                let default_peer = self.default_peer();
+                let nexus_stmt = NexusStatement::new(peers, &stmt, default_peer.as_deref())?;
+                Ok(NexusParsedStatement {
+                    statement: nexus_stmt,
+                    query: sql.to_owned(),
+                    default_peer,
+                })
+            }
         }
     }
 }
+
+// `RESET name` as `SET name TO DEFAULT`, when it is all of the message
+fn reset_as_set(sql: &str) -> Option<String> {
+    let stmt = sql.trim().trim_end_matches(';').trim_end();
+    let (keyword, name) = stmt.split_once(char::is_whitespace)?;
+    let name = name.trim();
+    let is_setting_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+    if keyword.eq_ignore_ascii_case("reset") && is_setting_name && !name.eq_ignore_ascii_case("all")
+    {
+        Some(format!("SET {} TO DEFAULT", name))
+    } else {
+        None
+    }
+}
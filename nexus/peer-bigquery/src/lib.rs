@@ -170,10 +170,10 @@ impl QueryExecutor for BigQueryQueryExecutor {
                 tracing::info!("fetching {} rows", count);
 
                 // Fetch rows from the cursor manager
-                let records = self.cursor_manager.fetch(&name.value, count).await?;
+                let stream = self.cursor_manager.fetch(&name.value, count).await?;
 
-                // Return the fetched records as the query output
-                Ok(QueryOutput::Records(records))
+                // Stream the fetched records as the query output
+                Ok(QueryOutput::Stream(stream))
             }
             Statement::Close { cursor } => {
                 let closed_cursors = match cursor {
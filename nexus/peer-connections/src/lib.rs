@@ -19,6 +19,36 @@ impl PeerConnections {
             .context("Failed to create Pool from manager")?;
         Ok(Self { pool })
     }
+
+    /// Connections of the pool to the catalog, see `PoolStatus`.
+    pub fn status(&self) -> PoolStatus {
+        let status = self.pool.status();
+        PoolStatus {
+            max_size: status.max_size,
+            size: status.size,
+            available: status.available,
+        }
+    }
+
+    /// Whether a pooled connection to the catalog answers a query.
+    pub async fn is_healthy(&self) -> bool {
+        match self.pool.get().await {
+            Ok(conn) => conn.simple_query("SELECT 1").await.is_ok(),
+            Err(err) => {
+                tracing::warn!("catalog health check failed: {}", err);
+                false
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PoolStatus {
+    pub max_size: usize,
+    /// Connections open, in use or idle.
+    pub size: usize,
+    /// Idle connections.
+    pub available: usize,
 }
 
 #[derive(Clone)]
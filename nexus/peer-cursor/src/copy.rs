@@ -0,0 +1,322 @@
+use pgerror::{sqlstate, PgError};
+use pgwire::error::PgWireResult;
+use value::Value;
+
+use crate::{Record, Records, Schema};
+
+/// Format of the rows sent by a `COPY ... FROM STDIN`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyFormat {
+    /// Tab separated fields with backslash escapes, and `\N` for NULL.
+    Text,
+    /// Comma separated fields, quoted with `"` where needed, and an unquoted
+    /// empty field for NULL.
+    Csv,
+}
+
+/// Parses the data of a `COPY ... FROM STDIN` into records of a schema. The
+/// data can come in frames split anywhere, even inside a row or a quoted
+/// field: rows are returned once all their bytes have been pushed.
+pub struct CopyInParser {
+    schema: Schema,
+    format: CopyFormat,
+    delimiter: u8,
+    null: String,
+    skip_header: bool,
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl CopyInParser {
+    /// A parser with the defaults postgres has for `format`.
+    pub fn new(schema: Schema, format: CopyFormat) -> Self {
+        let (delimiter, null) = match format {
+            CopyFormat::Text => (b'\t', "\\N"),
+            CopyFormat::Csv => (b',', ""),
+        };
+        Self {
+            schema,
+            format,
+            delimiter,
+            null: null.to_string(),
+            skip_header: false,
+            buf: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Like the `DELIMITER` option of `COPY`.
+    pub fn with_delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Like the `NULL` option of `COPY`, the field that stands for NULL.
+    pub fn with_null(mut self, null: impl Into<String>) -> Self {
+        self.null = null.into();
+        self
+    }
+
+    /// Like the `HEADER` option of `COPY`, skips the first line.
+    pub fn with_header(mut self, header: bool) -> Self {
+        self.skip_header = header;
+        self
+    }
+
+    /// Adds a frame of data, returning the rows it completes.
+    pub fn push(&mut self, frame: &[u8]) -> PgWireResult<Vec<Record>> {
+        self.buf.extend_from_slice(frame);
+        let mut records = Vec::new();
+        let mut start = 0;
+        while !self.done {
+            let Some(end) = self.row_end(start) else {
+                break;
+            };
+            self.take_row(start, end, &mut records)?;
+            start = end + 1;
+        }
+        self.buf.drain(..start);
+        Ok(records)
+    }
+
+    /// Ends the data, returning its last row if it isn't ended by a newline.
+    pub fn finish(mut self) -> PgWireResult<Vec<Record>> {
+        let mut records = Vec::new();
+        if !self.done && !self.buf.is_empty() {
+            let end = self.buf.len();
+            self.take_row(0, end, &mut records)?;
+        }
+        Ok(records)
+    }
+
+    // the index of the newline ending the row at `start`, if it has arrived
+    fn row_end(&self, start: usize) -> Option<usize> {
+        let mut in_quotes = false;
+        let mut i = start;
+        while i < self.buf.len() {
+            match (self.format, self.buf[i]) {
+                (CopyFormat::Text, b'\\') => i += 1,
+                (CopyFormat::Csv, b'"') => in_quotes = !in_quotes,
+                (_, b'\n') if !in_quotes => return Some(i),
+                _ => {}
+            }
+            i += 1;
+        }
+        None
+    }
+
+    fn take_row(
+        &mut self,
+        start: usize,
+        end: usize,
+        records: &mut Vec<Record>,
+    ) -> PgWireResult<()> {
+        let mut line = &self.buf[start..end];
+        if let Some(rest) = line.strip_suffix(b"\r") {
+            line = rest;
+        }
+        if self.skip_header {
+            self.skip_header = false;
+            return Ok(());
+        }
+        if line == b"\\." {
+            self.done = true;
+            return Ok(());
+        }
+        let fields = match self.format {
+            CopyFormat::Text => self.text_fields(line)?,
+            CopyFormat::Csv => self.csv_fields(line)?,
+        };
+        records.push(self.record(fields)?);
+        Ok(())
+    }
+
+    fn text_fields(&self, line: &[u8]) -> PgWireResult<Vec<Option<String>>> {
+        let mut fields = Vec::new();
+        let mut raw = Vec::new();
+        let mut field = Vec::new();
+        let mut bytes = line.iter().copied().peekable();
+        loop {
+            match bytes.next() {
+                Some(b) if b == self.delimiter => {
+                    fields.push(self.text_field(&raw, &field)?);
+                    raw.clear();
+                    field.clear();
+                }
+                Some(b'\\') => {
+                    raw.push(b'\\');
+                    let Some(escaped) = bytes.next() else {
+                        field.push(b'\\');
+                        continue;
+                    };
+                    raw.push(escaped);
+                    match escaped {
+                        b'b' => field.push(0x08),
+                        b'f' => field.push(0x0c),
+                        b'n' => field.push(b'\n'),
+                        b'r' => field.push(b'\r'),
+                        b't' => field.push(b'\t'),
+                        b'v' => field.push(0x0b),
+                        b'0'..=b'7' => {
+                            let mut n = (escaped - b'0') as u32;
+                            for _ in 0..2 {
+                                match bytes.peek() {
+                                    Some(&d @ b'0'..=b'7') => {
+                                        raw.push(d);
+                                        n = n * 8 + (d - b'0') as u32;
+                                        bytes.next();
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            field.push(n as u8);
+                        }
+                        b'x' => {
+                            let mut n = 0;
+                            let mut digits = 0;
+                            while digits < 2 {
+                                match bytes.peek().and_then(|&d| (d as char).to_digit(16)) {
+                                    Some(d) => {
+                                        raw.push(bytes.next().unwrap());
+                                        n = n * 16 + d;
+                                        digits += 1;
+                                    }
+                                    None => break,
+                                }
+                            }
+                            if digits == 0 {
+                                field.push(b'x');
+                            } else {
+                                field.push(n as u8);
+                            }
+                        }
+                        other => field.push(other),
+                    }
+                }
+                Some(b) => {
+                    raw.push(b);
+                    field.push(b);
+                }
+                None => {
+                    fields.push(self.text_field(&raw, &field)?);
+                    return Ok(fields);
+                }
+            }
+        }
+    }
+
+    // a text field is NULL when it is written as the null string, before
+    // its escapes are taken into account
+    fn text_field(&self, raw: &[u8], field: &[u8]) -> PgWireResult<Option<String>> {
+        if raw == self.null.as_bytes() {
+            return Ok(None);
+        }
+        utf8(field).map(Some)
+    }
+
+    fn csv_fields(&self, line: &[u8]) -> PgWireResult<Vec<Option<String>>> {
+        let mut fields = Vec::new();
+        let mut i = 0;
+        loop {
+            if line.get(i) == Some(&b'"') {
+                // a quoted field, where "" stands for a quote, is never NULL
+                let mut field = Vec::new();
+                i += 1;
+                loop {
+                    match (line.get(i), line.get(i + 1)) {
+                        (Some(b'"'), Some(b'"')) => {
+                            field.push(b'"');
+                            i += 2;
+                        }
+                        (Some(b'"'), _) => {
+                            i += 1;
+                            break;
+                        }
+                        (Some(&b), _) => {
+                            field.push(b);
+                            i += 1;
+                        }
+                        (None, _) => return Err(bad_copy("unterminated CSV quoted field")),
+                    }
+                }
+                fields.push(Some(utf8(&field)?));
+                match line.get(i) {
+                    None => return Ok(fields),
+                    Some(&b) if b == self.delimiter => i += 1,
+                    Some(_) => return Err(bad_copy("extra data after CSV quoted field")),
+                }
+            } else {
+                let end = line[i..]
+                    .iter()
+                    .position(|&b| b == self.delimiter)
+                    .map_or(line.len(), |n| i + n);
+                let field = &line[i..end];
+                fields.push(if field == self.null.as_bytes() {
+                    None
+                } else {
+                    Some(utf8(field)?)
+                });
+                if end == line.len() {
+                    return Ok(fields);
+                }
+                i = end + 1;
+            }
+        }
+    }
+
+    fn record(&self, fields: Vec<Option<String>>) -> PgWireResult<Record> {
+        if fields.len() < self.schema.len() {
+            let missing = self.schema[fields.len()].name();
+            return Err(bad_copy(&format!(
+                "missing data for column \"{}\"",
+                missing
+            )));
+        }
+        if fields.len() > self.schema.len() {
+            return Err(bad_copy("extra data after last expected column"));
+        }
+        let values = fields
+            .into_iter()
+            .zip(self.schema.iter())
+            .map(|(field, column)| match field {
+                None => Ok(Value::Null),
+                Some(text) => Value::parse(column.datatype(), &text).map_err(|err| {
+                    PgError::new(
+                        sqlstate::INVALID_TEXT_REPRESENTATION,
+                        format!("column \"{}\": {:#}", column.name(), err),
+                    )
+                    .into()
+                }),
+            })
+            .collect::<PgWireResult<Vec<_>>>()?;
+        Ok(Record {
+            values,
+            schema: self.schema.clone(),
+        })
+    }
+}
+
+fn utf8(bytes: &[u8]) -> PgWireResult<String> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| bad_copy("invalid UTF-8 in COPY data"))
+}
+
+fn bad_copy(message: &str) -> pgwire::error::PgWireError {
+    PgError::new(sqlstate::BAD_COPY_FILE_FORMAT, message).into()
+}
+
+/// Parses the frames of a `COPY ... FROM STDIN` in `format` into records of
+/// `schema`, with the defaults postgres has for the format. See
+/// [`CopyInParser`] to set other options or to parse frames as they arrive.
+pub fn copy_in_to_records<I>(frames: I, schema: Schema, format: CopyFormat) -> PgWireResult<Records>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    let mut parser = CopyInParser::new(schema.clone(), format);
+    let mut records = Vec::new();
+    for frame in frames {
+        records.extend(parser.push(frame.as_ref())?);
+    }
+    records.extend(parser.finish()?);
+    Ok(Records { records, schema })
+}
@@ -1,11 +1,21 @@
-use std::{pin::Pin, sync::Arc};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::Hasher,
+    pin::Pin,
+    sync::{atomic::AtomicUsize, Arc},
+};
 
 use futures::Stream;
 use pgwire::{api::results::FieldInfo, error::PgWireResult};
 use sqlparser::ast::Statement;
+use tokio::sync::Mutex;
 use value::Value;
 
+pub mod copy;
 mod manager;
+pub mod profile;
+pub mod progress;
+pub mod resume;
 pub mod util;
 
 pub use manager::CursorManager;
@@ -17,6 +27,23 @@ pub struct Record {
     pub schema: Schema,
 }
 
+impl Record {
+    /// Approximate memory taken by the record's values.
+    pub fn heap_size(&self) -> usize {
+        self.values.iter().map(Value::heap_size).sum()
+    }
+
+    /// Hash of the values at `indices`, in that order, see `Value::hash_key`.
+    /// Panics if an index is out of bounds.
+    pub fn key_hash(&self, indices: &[usize]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for &i in indices {
+            self.values[i].hash_key(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
 pub trait RecordStream: Stream<Item = PgWireResult<Record>> {
     fn schema(&self) -> Schema;
 }
@@ -51,7 +78,7 @@ pub trait QueryExecutor: Send + Sync {
 }
 
 pub struct Cursor {
-    position: usize,
-    stream: SendableStream,
+    position: Arc<AtomicUsize>,
+    stream: Arc<Mutex<SendableStream>>,
     schema: Schema,
 }
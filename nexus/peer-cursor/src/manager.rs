@@ -1,10 +1,21 @@
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
 use dashmap::DashMap;
 
-use futures::StreamExt;
-use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+use futures::Stream;
+use pgerror::{sqlstate, PgError};
+use pgwire::error::PgWireResult;
 use sqlparser::ast::Statement;
+use tokio::sync::{Mutex, OwnedMutexGuard};
 
-use crate::{Cursor, QueryExecutor, QueryOutput, Records};
+use crate::{Cursor, QueryExecutor, QueryOutput, Record, RecordStream, Schema, SendableStream};
 
 #[derive(Default)]
 pub struct CursorManager {
@@ -25,8 +36,8 @@ impl CursorManager {
                 let schema = stream.schema();
 
                 let cursor = Cursor {
-                    position: 0,
-                    stream,
+                    position: Arc::new(AtomicUsize::new(0)),
+                    stream: Arc::new(Mutex::new(stream)),
                     schema,
                 };
 
@@ -36,41 +47,45 @@ impl CursorManager {
 
                 Ok(())
             }
-            _ => Err(PgWireError::UserError(Box::new(ErrorInfo::new(
-                "ERROR".to_owned(),
-                "fdw_error".to_owned(),
-                "Only SELECT queries can be used with cursors".to_owned(),
-            )))),
+            _ => Err(PgError::new(
+                sqlstate::FEATURE_NOT_SUPPORTED,
+                "Only SELECT queries can be used with cursors",
+            )
+            .into()),
         }
     }
 
-    pub async fn fetch(&self, name: &str, count: usize) -> PgWireResult<Records> {
-        let mut cursor = self.cursors.get_mut(name).ok_or_else(|| {
-            PgWireError::UserError(Box::new(ErrorInfo::new(
-                "ERROR".to_owned(),
-                "fdw_error".to_owned(),
-                format!("Cursor {} does not exist", name),
-            )))
-        })?;
-
-        let mut records = Vec::new();
-        while records.len() < count {
-            match cursor.stream.next().await {
-                Some(Ok(record)) => {
-                    records.push(record);
-                }
-                Some(Err(err)) => return Err(err),
-                None => break,
-            }
-        }
-
-        tracing::info!("Cursor {} fetched {} records", name, records.len());
-        cursor.position += records.len();
-
-        Ok(Records {
-            records,
-            schema: cursor.schema.clone(),
-        })
+    /// Starts a FETCH of up to `count` records. The returned stream reads from
+    /// the cursor's peer stream directly, so nothing is buffered; whatever it
+    /// does not consume is left to the next FETCH.
+    pub async fn fetch(&self, name: &str, count: usize) -> PgWireResult<SendableStream> {
+        let (stream, schema, position) = {
+            let cursor = self.cursors.get(name).ok_or_else(|| {
+                PgError::new(
+                    sqlstate::INVALID_CURSOR_NAME,
+                    format!("cursor \"{}\" does not exist", name),
+                )
+            })?;
+            (
+                cursor.stream.clone(),
+                cursor.schema.clone(),
+                cursor.position.clone(),
+            )
+        };
+
+        tracing::info!(
+            "Cursor {} fetching up to {} records from position {}",
+            name,
+            count,
+            position.load(Ordering::Relaxed)
+        );
+
+        Ok(Box::pin(FetchStream {
+            stream: stream.lock_owned().await,
+            schema,
+            remaining: count,
+            position,
+        }))
     }
 
     pub async fn close(&self, name: &str) -> PgWireResult<()> {
@@ -79,11 +94,11 @@ impl CursorManager {
         self.cursors
             .remove(name)
             .ok_or_else(|| {
-                PgWireError::UserError(Box::new(ErrorInfo::new(
-                    "ERROR".to_owned(),
-                    "fdw_error".to_owned(),
-                    format!("Cursor {} does not exist", name),
-                )))
+                PgError::new(
+                    sqlstate::INVALID_CURSOR_NAME,
+                    format!("cursor \"{}\" does not exist", name),
+                )
+                .into()
             })
             .map(|_| ())
     }
@@ -100,3 +115,36 @@ impl CursorManager {
         Ok(keys)
     }
 }
+
+/// A single FETCH over a cursor. The cursor's stream stays locked while the
+/// response is sent and is only polled as the client consumes rows, so the
+/// peer stream is simply paused between fetches.
+struct FetchStream {
+    stream: OwnedMutexGuard<SendableStream>,
+    schema: Schema,
+    remaining: usize,
+    position: Arc<AtomicUsize>,
+}
+
+impl Stream for FetchStream {
+    type Item = PgWireResult<Record>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.remaining == 0 {
+            return Poll::Ready(None);
+        }
+
+        let polled = self.stream.as_mut().poll_next(cx);
+        if let Poll::Ready(Some(Ok(_))) = polled {
+            self.remaining -= 1;
+            self.position.fetch_add(1, Ordering::Relaxed);
+        }
+        polled
+    }
+}
+
+impl RecordStream for FetchStream {
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+}
@@ -0,0 +1,216 @@
+use std::{
+    cmp::Ordering,
+    collections::{hash_map::DefaultHasher, HashSet},
+    hash::{Hash, Hasher},
+    pin::Pin,
+    task::{Context, Poll},
+    vec,
+};
+
+use futures::{Stream, StreamExt};
+use pgwire::error::PgWireResult;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use value::Value;
+
+use crate::{Record, RecordStream, Schema, SendableStream};
+
+/// Distinct values are counted exactly up to this many per column.
+pub const DISTINCT_CAP: usize = 10_000;
+
+/// Statistics over one column of a stream, see [`profile_stream`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnProfile {
+    pub name: String,
+    pub null_count: usize,
+    /// Number of distinct non-null values, exact while `distinct_capped` is
+    /// false and a lower bound once it is set.
+    pub distinct: usize,
+    pub distinct_capped: bool,
+    /// Smallest and largest values, for columns whose values are orderable.
+    pub min: Option<Value>,
+    pub max: Option<Value>,
+    /// Largest number of bytes taken by a value's data, see `Value::byte_len`.
+    pub max_byte_width: usize,
+}
+
+impl ColumnProfile {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            null_count: 0,
+            distinct: 0,
+            distinct_capped: false,
+            min: None,
+            max: None,
+            max_byte_width: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamProfile {
+    pub row_count: usize,
+    pub columns: Vec<ColumnProfile>,
+}
+
+/// Consumes `stream` and gathers per column statistics, a common first look
+/// at a source table before configuring a mirror.
+pub async fn profile_stream(mut stream: SendableStream) -> PgWireResult<StreamProfile> {
+    let mut columns = stream
+        .schema()
+        .iter()
+        .map(|field| ColumnProfile::new(field.name().to_string()))
+        .collect::<Vec<_>>();
+    let mut seen = vec![HashSet::new(); columns.len()];
+    let mut row_count = 0;
+
+    while let Some(record) = stream.next().await {
+        let record = record?;
+        row_count += 1;
+        for ((value, column), seen) in record.values.into_iter().zip(&mut columns).zip(&mut seen) {
+            if value.is_null() {
+                column.null_count += 1;
+                continue;
+            }
+
+            column.max_byte_width = column.max_byte_width.max(value.byte_len());
+
+            if !column.distinct_capped {
+                seen.insert(fingerprint(&value));
+                if seen.len() >= DISTINCT_CAP {
+                    column.distinct_capped = true;
+                    seen.clear();
+                }
+            }
+
+            if compare(&value, &value).is_some() {
+                if column
+                    .min
+                    .as_ref()
+                    .map_or(true, |min| compare(&value, min) == Some(Ordering::Less))
+                {
+                    column.min = Some(value.clone());
+                }
+                if column
+                    .max
+                    .as_ref()
+                    .map_or(true, |max| compare(&value, max) == Some(Ordering::Greater))
+                {
+                    column.max = Some(value);
+                }
+            }
+        }
+    }
+
+    for (column, seen) in columns.iter_mut().zip(seen) {
+        column.distinct = if column.distinct_capped {
+            DISTINCT_CAP
+        } else {
+            seen.len()
+        };
+    }
+    Ok(StreamProfile { row_count, columns })
+}
+
+struct SampleStream {
+    inner: SendableStream,
+    k: usize,
+    // records read from `inner` so far
+    seen: u64,
+    reservoir: Vec<Record>,
+    // the sample, once `inner` is exhausted
+    sample: Option<vec::IntoIter<Record>>,
+    rng: StdRng,
+}
+
+impl Stream for SampleStream {
+    type Item = PgWireResult<Record>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some(sample) = &mut this.sample {
+                return Poll::Ready(sample.next().map(Ok));
+            }
+            match this.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(record))) => {
+                    // algorithm R: the n-th record replaces a sampled one
+                    // with probability k/n
+                    this.seen += 1;
+                    if this.reservoir.len() < this.k {
+                        this.reservoir.push(record);
+                    } else {
+                        let i = this.rng.gen_range(0..this.seen);
+                        if i < this.k as u64 {
+                            this.reservoir[i as usize] = record;
+                        }
+                    }
+                }
+                Poll::Ready(None) => {
+                    this.sample = Some(std::mem::take(&mut this.reservoir).into_iter());
+                }
+                other => return other,
+            }
+        }
+    }
+}
+
+impl RecordStream for SampleStream {
+    fn schema(&self) -> Schema {
+        self.inner.schema()
+    }
+}
+
+/// Reads all of `stream` and then yields a uniformly random sample of `k` of
+/// its records, or all of them if it has fewer. At most `k` records are held
+/// at a time, so it suits previews of scans too large to buffer.
+pub fn sample_stream(stream: SendableStream, k: usize) -> SendableStream {
+    Box::pin(SampleStream {
+        inner: stream,
+        k,
+        seen: 0,
+        reservoir: Vec::new(),
+        sample: None,
+        rng: StdRng::from_entropy(),
+    })
+}
+
+fn fingerprint(value: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    format!("{:?}", value).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Orders two values of the same orderable kind; anything else is unordered.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => a.partial_cmp(b),
+        (Value::TinyInt(a), Value::TinyInt(b)) => a.partial_cmp(b),
+        (Value::SmallInt(a), Value::SmallInt(b)) => a.partial_cmp(b),
+        (Value::Oid(a), Value::Oid(b)) | (Value::LargeObject(a), Value::LargeObject(b)) => {
+            a.partial_cmp(b)
+        }
+        (Value::Integer(a), Value::Integer(b)) => a.partial_cmp(b),
+        (Value::BigInt(a), Value::BigInt(b)) => a.partial_cmp(b),
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+        (Value::Double(a), Value::Double(b)) => a.partial_cmp(b),
+        (Value::Numeric(a), Value::Numeric(b)) => a.partial_cmp(b),
+        (Value::Char(a), Value::Char(b)) => a.partial_cmp(b),
+        (
+            Value::VarChar(a) | Value::Text(a) | Value::Enum(a),
+            Value::VarChar(b) | Value::Text(b) | Value::Enum(b),
+        ) => a.partial_cmp(b),
+        (Value::TextBytes(a), Value::TextBytes(b))
+        | (Value::Binary(a), Value::Binary(b))
+        | (Value::VarBinary(a), Value::VarBinary(b)) => a.partial_cmp(b),
+        (Value::Date(a), Value::Date(b)) => a.partial_cmp(b),
+        (Value::Time(a), Value::Time(b))
+        | (Value::TimeWithTimeZone(a), Value::TimeWithTimeZone(b)) => a.partial_cmp(b),
+        (Value::Timestamp(a), Value::Timestamp(b))
+        | (Value::TimestampWithTimeZone(a), Value::TimestampWithTimeZone(b)) => a.partial_cmp(b),
+        (Value::PostgresTimestamp(a), Value::PostgresTimestamp(b)) => a.partial_cmp(b),
+        (Value::Interval(a), Value::Interval(b)) => a.total_micros().partial_cmp(&b.total_micros()),
+        (Value::Uuid(a), Value::Uuid(b)) => a.partial_cmp(b),
+        _ => None,
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use futures::Stream;
+use pgwire::error::PgWireResult;
+
+use crate::{Record, RecordStream, Schema, SendableStream};
+
+/// Rows a stream emits between updates of its shared counters, which are
+/// also updated whenever the stream waits on its source.
+pub const PROGRESS_BATCH_ROWS: u64 = 1024;
+
+/// Counters of a record stream on its way to a client, shared between the
+/// stream and whoever watches it.
+#[derive(Debug)]
+pub struct StreamProgress {
+    pub query_id: String,
+    pub peer: Option<String>,
+    pub query: String,
+    started: Instant,
+    rows: AtomicU64,
+    bytes: AtomicU64,
+    // milliseconds from `started` to the last update
+    last_activity_ms: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressSnapshot {
+    pub rows: u64,
+    /// Bytes of the values emitted, see `Record::heap_size`.
+    pub bytes: u64,
+    pub elapsed: Duration,
+    /// Time since the counters last moved.
+    pub idle: Duration,
+}
+
+impl StreamProgress {
+    pub fn new(query_id: String, peer: Option<String>, query: String) -> Self {
+        Self {
+            query_id,
+            peer,
+            query,
+            started: Instant::now(),
+            rows: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            last_activity_ms: AtomicU64::new(0),
+        }
+    }
+
+    pub fn snapshot(&self) -> ProgressSnapshot {
+        let elapsed = self.started.elapsed();
+        let last_activity = Duration::from_millis(self.last_activity_ms.load(Ordering::Relaxed));
+        ProgressSnapshot {
+            rows: self.rows.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            elapsed,
+            idle: elapsed.saturating_sub(last_activity),
+        }
+    }
+
+    fn add(&self, rows: u64, bytes: u64) {
+        self.rows.fetch_add(rows, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        let now = self.started.elapsed().as_millis() as u64;
+        self.last_activity_ms.store(now, Ordering::Relaxed);
+    }
+}
+
+/// How a stream came to be done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamEnd {
+    Finished,
+    Failed,
+    /// Dropped before its end, as when the client went away.
+    Dropped,
+}
+
+/// Called once a stream has ended, failed or been dropped, with its final
+/// counters.
+pub type CompletionHook =
+    Box<dyn FnOnce(&StreamProgress, ProgressSnapshot, StreamEnd) + Send + Sync>;
+
+struct ProgressStream {
+    inner: SendableStream,
+    progress: Arc<StreamProgress>,
+    // counted locally and added to `progress` once per batch
+    rows: u64,
+    bytes: u64,
+    on_complete: Option<CompletionHook>,
+}
+
+impl ProgressStream {
+    fn flush(&mut self) {
+        self.progress.add(self.rows, self.bytes);
+        self.rows = 0;
+        self.bytes = 0;
+    }
+
+    fn complete(&mut self, end: StreamEnd) {
+        if let Some(on_complete) = self.on_complete.take() {
+            self.flush();
+            on_complete(&self.progress, self.progress.snapshot(), end);
+        }
+    }
+}
+
+impl Stream for ProgressStream {
+    type Item = PgWireResult<Record>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let polled = self.inner.as_mut().poll_next(cx);
+        match &polled {
+            Poll::Ready(Some(Ok(record))) => {
+                self.rows += 1;
+                self.bytes += record.heap_size() as u64;
+                if self.rows >= PROGRESS_BATCH_ROWS {
+                    self.flush();
+                }
+            }
+            Poll::Ready(Some(Err(_))) => self.complete(StreamEnd::Failed),
+            Poll::Ready(None) => self.complete(StreamEnd::Finished),
+            // waiting on the peer, so the rows so far are all there is for now
+            Poll::Pending if self.rows > 0 => self.flush(),
+            Poll::Pending => {}
+        }
+        polled
+    }
+}
+
+impl RecordStream for ProgressStream {
+    fn schema(&self) -> Schema {
+        self.inner.schema()
+    }
+}
+
+impl Drop for ProgressStream {
+    fn drop(&mut self) {
+        self.complete(StreamEnd::Dropped);
+    }
+}
+
+/// Counts the rows and bytes going through `stream` into `progress`, and
+/// calls `on_complete` with the totals once the stream is done.
+pub fn observe(
+    stream: SendableStream,
+    progress: Arc<StreamProgress>,
+    on_complete: CompletionHook,
+) -> SendableStream {
+    Box::pin(ProgressStream {
+        inner: stream,
+        progress,
+        rows: 0,
+        bytes: 0,
+        on_complete: Some(on_complete),
+    })
+}
+
+/// The streams currently being sent, across all sessions.
+#[derive(Default)]
+pub struct ProgressRegistry {
+    next_id: AtomicU64,
+    streams: DashMap<u64, Arc<StreamProgress>>,
+}
+
+impl ProgressRegistry {
+    /// Observes `stream` like `observe`, listing it until it completes.
+    pub fn register(
+        self: &Arc<Self>,
+        stream: SendableStream,
+        progress: StreamProgress,
+        on_complete: CompletionHook,
+    ) -> SendableStream {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let progress = Arc::new(progress);
+        self.streams.insert(id, progress.clone());
+
+        let registry = self.clone();
+        observe(
+            stream,
+            progress,
+            Box::new(move |progress, totals, end| {
+                registry.streams.remove(&id);
+                on_complete(progress, totals, end);
+            }),
+        )
+    }
+
+    /// The streams being sent, oldest first.
+    pub fn streams(&self) -> Vec<Arc<StreamProgress>> {
+        let mut streams = self
+            .streams
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect::<Vec<_>>();
+        streams.sort_by_key(|(id, _)| *id);
+        streams.into_iter().map(|(_, progress)| progress).collect()
+    }
+
+    /// The streams that have not moved for at least `threshold`.
+    pub fn stalled(&self, threshold: Duration) -> Vec<Arc<StreamProgress>> {
+        let mut streams = self.streams();
+        streams.retain(|progress| progress.snapshot().idle >= threshold);
+        streams
+    }
+}
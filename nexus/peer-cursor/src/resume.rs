@@ -0,0 +1,170 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Mutex,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::Stream;
+use pgwire::error::{PgWireError, PgWireResult};
+use value::Value;
+
+use crate::{Record, RecordStream, Schema, SendableStream};
+
+pub type OpenFuture = Pin<Box<dyn Future<Output = PgWireResult<SendableStream>> + Send>>;
+
+/// Opens the source of a `ResumableRecordStream`, with the rows after the
+/// given watermark, or all of them for `None`, in watermark order.
+pub type OpenSource = Box<dyn Fn(Option<Value>) -> OpenFuture + Send + Sync>;
+
+/// Reads the watermark of a record, the value of the column its source is
+/// ordered on.
+pub type RecordWatermark = Box<dyn Fn(&Record) -> Value + Send + Sync>;
+
+/// How many times, and after how long, a `ResumableRecordStream` reopens its
+/// source after losing its connection.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Reopens in a row without a record read in between before giving up.
+    pub max_retries: u32,
+    /// Wait before the first reopen, which grows by as much with each
+    /// retry in a row.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+/// A stream that, when its source fails with a connection error, reopens
+/// it from the watermark of the last record read and carries on, so that a
+/// long read over a flaky connection neither fails nor repeats or skips
+/// rows. This holds only for sources that are ordered on a unique
+/// watermark and read the same rows when opened again, like QRep snapshots.
+/// None of the read paths of nexus go through it yet, it is there for those
+/// that can provide such a source.
+pub struct ResumableRecordStream {
+    open: OpenSource,
+    watermark: RecordWatermark,
+    policy: RetryPolicy,
+    schema: Schema,
+    inner: Option<SendableStream>,
+    last: Option<Value>,
+    retries: u32,
+    // behind a mutex only to make the stream Sync, it is never contended
+    reopening: Option<Mutex<OpenFuture>>,
+}
+
+impl ResumableRecordStream {
+    /// Opens the source, retrying connection errors like later reopens.
+    pub async fn open(
+        open: OpenSource,
+        watermark: RecordWatermark,
+        policy: RetryPolicy,
+    ) -> PgWireResult<Self> {
+        let mut retries = 0;
+        let inner = loop {
+            match open(None).await {
+                Ok(inner) => break inner,
+                Err(err) if is_connection_error(&err) && retries < policy.max_retries => {
+                    retries += 1;
+                    tracing::warn!("retrying to open stream ({}): {}", retries, err);
+                    tokio::time::sleep(policy.backoff * retries).await;
+                }
+                Err(err) => return Err(err),
+            }
+        };
+        Ok(Self {
+            open,
+            watermark,
+            policy,
+            schema: inner.schema(),
+            inner: Some(inner),
+            last: None,
+            retries: 0,
+            reopening: None,
+        })
+    }
+
+    fn reopen(&mut self) {
+        self.inner = None;
+        self.retries += 1;
+        let backoff = self.policy.backoff * self.retries;
+        let opened = (self.open)(self.last.clone());
+        self.reopening = Some(Mutex::new(Box::pin(async move {
+            tokio::time::sleep(backoff).await;
+            opened.await
+        })));
+    }
+
+    fn can_retry(&self, err: &PgWireError) -> bool {
+        is_connection_error(err) && self.retries < self.policy.max_retries
+    }
+}
+
+// errors of the connection exception class, 08, like
+// `sqlstate::CONNECTION_FAILURE` that lost connections are reported as
+fn is_connection_error(err: &PgWireError) -> bool {
+    match err {
+        PgWireError::UserError(info) => info.code().starts_with("08"),
+        _ => false,
+    }
+}
+
+impl Stream for ResumableRecordStream {
+    type Item = PgWireResult<Record>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(reopening) = self.reopening.as_mut() {
+                let opened = match reopening
+                    .get_mut()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .as_mut()
+                    .poll(cx)
+                {
+                    Poll::Ready(opened) => opened,
+                    Poll::Pending => return Poll::Pending,
+                };
+                self.reopening = None;
+                match opened {
+                    Ok(inner) => self.inner = Some(inner),
+                    Err(err) if self.can_retry(&err) => {
+                        tracing::warn!("retrying to reopen stream ({}): {}", self.retries, err);
+                        self.reopen();
+                        continue;
+                    }
+                    Err(err) => return Poll::Ready(Some(Err(err))),
+                }
+            }
+
+            let Some(inner) = self.inner.as_mut() else {
+                return Poll::Ready(None);
+            };
+            match inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(record))) => {
+                    self.last = Some((self.watermark)(&record));
+                    self.retries = 0;
+                    return Poll::Ready(Some(Ok(record)));
+                }
+                Poll::Ready(Some(Err(err))) if self.can_retry(&err) => {
+                    tracing::warn!("reopening stream after a connection error: {}", err);
+                    self.reopen();
+                }
+                other => return other,
+            }
+        }
+    }
+}
+
+impl RecordStream for ResumableRecordStream {
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+}
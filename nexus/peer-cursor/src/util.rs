@@ -1,17 +1,553 @@
-use futures::{stream, StreamExt};
+use std::{
+    fmt::Write,
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
+use futures::{stream, Stream, StreamExt};
+use pgerror::{sqlstate, PgError};
 use pgwire::{
-    api::results::{DataRowEncoder, QueryResponse, Response},
+    api::{
+        results::{DataRowEncoder, FieldFormat, FieldInfo, QueryResponse, Response},
+        Type,
+    },
     error::{PgWireError, PgWireResult},
+    messages::data::DataRow,
 };
-use value::Value;
+use value::{array::ArrayValue, interval::Interval, kind::pg_type_to_value_kind, TypeTag, Value};
+
+use crate::{Record, RecordStream, Records, Schema, SendableStream};
+
+/// Output style for intervals, mirroring the session's `IntervalStyle`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IntervalStyle {
+    #[default]
+    Postgres,
+    PostgresVerbose,
+    Iso8601,
+    SqlStandard,
+}
+
+impl FromStr for IntervalStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "postgres" | "default" => Ok(IntervalStyle::Postgres),
+            "postgres_verbose" => Ok(IntervalStyle::PostgresVerbose),
+            "iso_8601" => Ok(IntervalStyle::Iso8601),
+            "sql_standard" => Ok(IntervalStyle::SqlStandard),
+            _ => anyhow::bail!("invalid value for IntervalStyle: {}", s),
+        }
+    }
+}
+
+/// Case of the column names sent to the client, for tools that compare them
+/// case sensitively.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColumnCase {
+    /// The names as the peer sent them.
+    #[default]
+    AsIs,
+    Lower,
+    Upper,
+}
+
+impl ColumnCase {
+    pub fn apply(self, name: &str) -> String {
+        match self {
+            ColumnCase::AsIs => name.to_string(),
+            ColumnCase::Lower => name.to_lowercase(),
+            ColumnCase::Upper => name.to_uppercase(),
+        }
+    }
+}
+
+impl FromStr for ColumnCase {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "as_is" => Ok(ColumnCase::AsIs),
+            "lower" => Ok(ColumnCase::Lower),
+            "upper" => Ok(ColumnCase::Upper),
+            _ => anyhow::bail!("invalid value for peerdb.column_case: {}", s),
+        }
+    }
+}
+
+/// Output format of dates and timestamps, the first half of the session's
+/// `DateStyle`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DateFormat {
+    #[default]
+    Iso,
+    Sql,
+    Postgres,
+    German,
+}
+
+/// Order of day and month, the second half of the session's `DateStyle`.
+/// Only the SQL and Postgres formats write dates in it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DateOrder {
+    #[default]
+    Mdy,
+    Dmy,
+    Ymd,
+}
+
+/// Output style for dates and timestamps, mirroring the session's
+/// `DateStyle`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DateStyle {
+    pub format: DateFormat,
+    pub order: DateOrder,
+}
+
+impl DateStyle {
+    /// Applies a `SET datestyle` value such as `German`, `sql, dmy` or
+    /// `ISO, MDY` on top of this style. As in postgres, a part left out keeps
+    /// its current value, except that `German` alone also sets DMY order.
+    pub fn with_setting(self, setting: &str) -> anyhow::Result<Self> {
+        let mut format = None;
+        let mut order = None;
+        for part in setting
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|part| !part.is_empty())
+        {
+            match part.to_lowercase().as_str() {
+                "iso" => format = Some(DateFormat::Iso),
+                "sql" => format = Some(DateFormat::Sql),
+                "postgres" => format = Some(DateFormat::Postgres),
+                "german" => format = Some(DateFormat::German),
+                "mdy" | "us" | "noneuropean" => order = Some(DateOrder::Mdy),
+                "dmy" | "euro" | "european" => order = Some(DateOrder::Dmy),
+                "ymd" => order = Some(DateOrder::Ymd),
+                "default" => {
+                    format = Some(DateFormat::Iso);
+                    order = Some(DateOrder::Mdy);
+                }
+                _ => anyhow::bail!("invalid value for DateStyle: {}", setting),
+            }
+        }
+        if format.is_none() && order.is_none() {
+            anyhow::bail!("invalid value for DateStyle: {}", setting);
+        }
+        if format == Some(DateFormat::German) && order.is_none() {
+            order = Some(DateOrder::Dmy);
+        }
+        Ok(DateStyle {
+            format: format.unwrap_or(self.format),
+            order: order.unwrap_or(self.order),
+        })
+    }
+}
+
+/// Session settings that affect how values are written to the client.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EncodeOptions {
+    pub interval_style: IntervalStyle,
+    pub date_style: DateStyle,
+    /// Yield to the runtime each time buffered records add up to this many
+    /// bytes, so the rows already encoded can be written out first. `None`
+    /// encodes record by record without yielding.
+    pub batch_bytes: Option<usize>,
+    pub column_case: ColumnCase,
+}
+
+fn push_fraction(out: &mut String, fraction: u64) {
+    if fraction != 0 {
+        let digits = format!("{:06}", fraction);
+        out.push('.');
+        out.push_str(digits.trim_end_matches('0'));
+    }
+}
+
+/// Formats an interval in the given style, e.g. `1 year 2 mons 3 days
+/// 04:05:06.5` in the postgres style.
+pub fn format_interval(interval: Interval, style: IntervalStyle) -> String {
+    let Interval {
+        months,
+        days,
+        microseconds,
+    } = interval;
+    let (years, months) = (months / 12, months % 12);
+    let sign = if microseconds < 0 { "-" } else { "" };
+    let abs = microseconds.unsigned_abs();
+    let hours = abs / 3_600_000_000;
+    let minutes = abs / 60_000_000 % 60;
+    let seconds = abs / 1_000_000 % 60;
+    let fraction = abs % 1_000_000;
+
+    let mut out = String::new();
+    match style {
+        IntervalStyle::Postgres => {
+            // a part following a negative one has its sign written out
+            let mut after_negative = false;
+            for (value, unit) in [(years, "year"), (months, "mon"), (days, "day")] {
+                if value != 0 {
+                    if !out.is_empty() {
+                        out.push(' ');
+                    }
+                    let plus = if after_negative && value > 0 { "+" } else { "" };
+                    let plural = if value != 1 { "s" } else { "" };
+                    write!(out, "{}{} {}{}", plus, value, unit, plural).ok();
+                    after_negative = value < 0;
+                }
+            }
+            if out.is_empty() || microseconds != 0 {
+                if !out.is_empty() {
+                    out.push(' ');
+                }
+                let sign = if after_negative && microseconds > 0 {
+                    "+"
+                } else {
+                    sign
+                };
+                write!(out, "{}{:02}:{:02}:{:02}", sign, hours, minutes, seconds).ok();
+                push_fraction(&mut out, fraction);
+            }
+        }
+        IntervalStyle::PostgresVerbose => {
+            // `@ 1 year 2 mons 4 hours`, the parts following the sign of the
+            // first one and an `ago` for a negative one
+            let signed = |value: i64| if microseconds < 0 { -value } else { value };
+            let parts = [
+                (years as i64, "year"),
+                (months as i64, "mon"),
+                (days as i64, "day"),
+                (signed(hours as i64), "hour"),
+                (signed(minutes as i64), "min"),
+            ];
+            let mut before = None;
+            out.push('@');
+            for (value, unit) in parts {
+                if value != 0 {
+                    let value = match before {
+                        None => {
+                            before = Some(value < 0);
+                            value.abs()
+                        }
+                        Some(true) => -value,
+                        Some(false) => value,
+                    };
+                    let plural = if value != 1 { "s" } else { "" };
+                    write!(out, " {} {}{}", value, unit, plural).ok();
+                }
+            }
+            if seconds != 0 || fraction != 0 {
+                let negative = microseconds < 0;
+                let minus = match before {
+                    None => {
+                        before = Some(negative);
+                        false
+                    }
+                    Some(before) => before != negative,
+                };
+                write!(out, " {}{}", if minus { "-" } else { "" }, seconds).ok();
+                push_fraction(&mut out, fraction);
+                let plural = if seconds != 1 || fraction != 0 {
+                    "s"
+                } else {
+                    ""
+                };
+                write!(out, " sec{}", plural).ok();
+            }
+            match before {
+                None => out.push_str(" 0"),
+                Some(true) => out.push_str(" ago"),
+                Some(false) => {}
+            }
+        }
+        IntervalStyle::SqlStandard => {
+            let parts = [years as i64, months as i64, days as i64, microseconds];
+            let negative = parts.iter().any(|part| *part < 0);
+            let positive = parts.iter().any(|part| *part > 0);
+            let year_month = years != 0 || months != 0;
+            let day_time = days != 0 || microseconds != 0;
+            if !negative && !positive {
+                return "0".to_string();
+            }
+            if (negative && positive) || (year_month && day_time) {
+                // not a value of the standard, so every part is written with
+                // its sign
+                let signed = |negative: bool| if negative { '-' } else { '+' };
+                write!(
+                    out,
+                    "{}{}-{} {}{} {}{}:{:02}:{:02}",
+                    signed(years < 0 || months < 0),
+                    years.unsigned_abs(),
+                    months.unsigned_abs(),
+                    signed(days < 0),
+                    days.unsigned_abs(),
+                    signed(microseconds < 0),
+                    hours,
+                    minutes,
+                    seconds
+                )
+                .ok();
+            } else {
+                let sign = if negative { "-" } else { "" };
+                if year_month {
+                    let (years, months) = (years.unsigned_abs(), months.unsigned_abs());
+                    write!(out, "{}{}-{}", sign, years, months).ok();
+                } else if days != 0 {
+                    let days = days.unsigned_abs();
+                    write!(
+                        out,
+                        "{}{} {}:{:02}:{:02}",
+                        sign, days, hours, minutes, seconds
+                    )
+                    .ok();
+                } else {
+                    write!(out, "{}{}:{:02}:{:02}", sign, hours, minutes, seconds).ok();
+                }
+            }
+            push_fraction(&mut out, fraction);
+        }
+        IntervalStyle::Iso8601 => {
+            out.push('P');
+            if years == 0 && months == 0 && days == 0 && microseconds == 0 {
+                out.push_str("T0S");
+                return out;
+            }
+            for (value, unit) in [(years, 'Y'), (months, 'M'), (days, 'D')] {
+                if value != 0 {
+                    write!(out, "{}{}", value, unit).ok();
+                }
+            }
+            if microseconds != 0 {
+                out.push('T');
+            }
+            if hours != 0 {
+                write!(out, "{}{}H", sign, hours).ok();
+            }
+            if minutes != 0 {
+                write!(out, "{}{}M", sign, minutes).ok();
+            }
+            if seconds != 0 || fraction != 0 {
+                write!(out, "{}{}", sign, seconds).ok();
+                push_fraction(&mut out, fraction);
+                out.push('S');
+            }
+        }
+    }
+    out
+}
+
+/// Formats a date in the given style, e.g. `1997-12-17` in ISO and
+/// `17.12.1997` in German.
+pub fn format_date(date: NaiveDate, style: DateStyle) -> String {
+    let (year, month, day) = (date.year(), date.month(), date.day());
+    let day_first = style.order == DateOrder::Dmy;
+    match style.format {
+        DateFormat::Iso => date.format("%Y-%m-%d").to_string(),
+        DateFormat::Sql if day_first => format!("{:02}/{:02}/{:04}", day, month, year),
+        DateFormat::Sql => format!("{:02}/{:02}/{:04}", month, day, year),
+        DateFormat::Postgres if day_first => format!("{:02}-{:02}-{:04}", day, month, year),
+        DateFormat::Postgres => format!("{:02}-{:02}-{:04}", month, day, year),
+        DateFormat::German => format!("{:02}.{:02}.{:04}", day, month, year),
+    }
+}
+
+/// Formats a timestamp in the given style, followed by `zone` for a
+/// timestamp with time zone, e.g. `1997-12-17 07:37:16+00` in ISO and
+/// `Wed Dec 17 07:37:16 1997 UTC` in Postgres.
+pub fn format_timestamp(ts: NaiveDateTime, zone: Option<&str>, style: DateStyle) -> String {
+    let mut time = ts.format("%H:%M:%S").to_string();
+    push_fraction(&mut time, (ts.nanosecond() % 1_000_000_000 / 1000) as u64);
+
+    let mut out = match style.format {
+        DateFormat::Postgres if style.order == DateOrder::Dmy => {
+            format!("{} {} {:04}", ts.format("%a %d %b"), time, ts.year())
+        }
+        DateFormat::Postgres => format!("{} {} {:04}", ts.format("%a %b %d"), time, ts.year()),
+        _ => format!("{} {}", format_date(ts.date(), style), time),
+    };
+    match (zone, style.format) {
+        (None, _) => {}
+        // ISO writes the offset, which for values decoded into UTC is +00
+        (Some(_), DateFormat::Iso) => out.push_str("+00"),
+        (Some(zone), _) => {
+            out.push(' ');
+            out.push_str(zone);
+        }
+    }
+    out
+}
+
+/// Formats an array from the text of its elements as postgres writes it,
+/// e.g. `{1.5,NULL,"1 day"}`. Elements that are empty, read as NULL, or hold
+/// whitespace, quotes, backslashes, braces or the delimiter are quoted.
+pub fn format_array<I>(elements: I) -> String
+where
+    I: IntoIterator<Item = Option<String>>,
+{
+    let mut out = String::from("{");
+    for (i, element) in elements.into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let Some(element) = element else {
+            out.push_str("NULL");
+            continue;
+        };
+        let quote = element.is_empty()
+            || element.eq_ignore_ascii_case("NULL")
+            || element
+                .chars()
+                .any(|c| c.is_whitespace() || matches!(c, '"' | '\\' | '{' | '}' | ','));
+        if !quote {
+            out.push_str(&element);
+            continue;
+        }
+        out.push('"');
+        for c in element.chars() {
+            if matches!(c, '"' | '\\') {
+                out.push('\\');
+            }
+            out.push(c);
+        }
+        out.push('"');
+    }
+    out.push('}');
+    out
+}
+
+/// The byte a character stands for in a `"char"` column. Characters up to
+/// U+00FF are the byte of the same value, as `"char"` bytes are read, and
+/// wider ones their first UTF-8 byte, as postgres takes them.
+pub fn char_byte(c: char) -> u8 {
+    match u8::try_from(c) {
+        Ok(b) => b,
+        Err(_) => c.encode_utf8(&mut [0; 4]).as_bytes()[0],
+    }
+}
+
+/// Formats a character of a `"char"` column as postgres writes it: bytes
+/// with the high bit set as a `\ooo` octal escape and the zero byte as an
+/// empty string.
+pub fn format_char(c: char) -> String {
+    match char_byte(c) {
+        0 => String::new(),
+        b if b >= 0x80 => format!("\\{:03o}", b),
+        b => char::from(b).to_string(),
+    }
+}
+
+/// Whether values of `ty` can be written in binary format, as opposed to the
+/// text format every type supports.
+pub fn supports_binary_format(ty: &Type) -> bool {
+    matches!(
+        *ty,
+        Type::BOOL
+            | Type::CHAR
+            | Type::INT2
+            | Type::INT4
+            | Type::INT8
+            | Type::OID
+            | Type::FLOAT4
+            | Type::FLOAT8
+            | Type::TEXT
+            | Type::VARCHAR
+            | Type::BPCHAR
+            | Type::NAME
+            | Type::BYTEA
+            | Type::DATE
+            | Type::TIME
+            | Type::TIMESTAMP
+            | Type::TIMESTAMPTZ
+    )
+}
+
+/// The fields of `schema` with their names in `case`, for describing a
+/// result the way its response will name the columns.
+pub fn with_column_case(schema: Schema, case: ColumnCase) -> Schema {
+    if case == ColumnCase::AsIs {
+        return schema;
+    }
+    let fields = schema
+        .iter()
+        .map(|field| {
+            FieldInfo::new(
+                case.apply(field.name()),
+                *field.table_id(),
+                *field.column_id(),
+                field.datatype().clone(),
+                *field.format(),
+            )
+        })
+        .collect();
+    Arc::new(fields)
+}
+
+// the fields of `schema` in the formats asked for, text for those without one
+fn with_formats(schema: &Schema, formats: &[FieldFormat]) -> PgWireResult<Schema> {
+    let fields = schema
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let format = formats.get(i).copied().unwrap_or(FieldFormat::Text);
+            if format == FieldFormat::Binary && !supports_binary_format(field.datatype()) {
+                return Err(PgError::new(
+                    sqlstate::FEATURE_NOT_SUPPORTED,
+                    format!(
+                        "binary format is not supported for column \"{}\" of type {}",
+                        field.name(),
+                        field.datatype()
+                    ),
+                )
+                .into());
+            }
+            Ok(FieldInfo::new(
+                field.name().clone(),
+                *field.table_id(),
+                *field.column_id(),
+                field.datatype().clone(),
+                format,
+            ))
+        })
+        .collect::<PgWireResult<Vec<_>>>()?;
+    Ok(Arc::new(fields))
+}
 
-use crate::{Records, Schema, SendableStream};
+fn encode_record(record: &Record, schema: &Schema, opts: &EncodeOptions) -> PgWireResult<DataRow> {
+    let mut encoder = DataRowEncoder::new(schema.clone());
+    for (i, value) in record.values.iter().enumerate() {
+        let field = schema.get(i);
+        let binary = field.is_some_and(|field| *field.format() == FieldFormat::Binary);
+        encode_value(
+            value,
+            field.map(|field| field.datatype()),
+            &mut encoder,
+            opts,
+            binary,
+        )?;
+    }
+    encoder.finish()
+}
 
-fn encode_value(value: &Value, builder: &mut DataRowEncoder) -> PgWireResult<()> {
+/// Writes `value` to the next column of `builder`, of type `ty` when the
+/// schema has it, in binary when `binary` and otherwise as text in the
+/// session's styles.
+fn encode_value(
+    value: &Value,
+    ty: Option<&Type>,
+    builder: &mut DataRowEncoder,
+    opts: &EncodeOptions,
+    binary: bool,
+) -> PgWireResult<()> {
+    // binary dates and times have a single form
+    let iso = binary || opts.date_style.format == DateFormat::Iso;
     match value {
-        Value::Null => builder.encode_field(&None::<&i8>),
+        // the column's type comes from the schema either way
+        Value::Null | Value::NullTyped(_) => builder.encode_field(&None::<&i8>),
         Value::Bool(v) => builder.encode_field(v),
-        Value::Oid(o) => builder.encode_field(o),
+        Value::Oid(o) | Value::LargeObject(o) => builder.encode_field(o),
         Value::TinyInt(v) => builder.encode_field(v),
         Value::SmallInt(v) => builder.encode_field(v),
         Value::Integer(v) => builder.encode_field(v),
@@ -19,9 +555,35 @@ fn encode_value(value: &Value, builder: &mut DataRowEncoder) -> PgWireResult<()>
         Value::Float(v) => builder.encode_field(v),
         Value::Double(v) => builder.encode_field(v),
         Value::Numeric(v) => builder.encode_field(&v.to_string()),
+        // a "char" is a single byte, unlike the characters of char(n)
+        Value::Char(c) if ty == Some(&Type::CHAR) && binary => {
+            builder.encode_field(&(char_byte(*c) as i8))
+        }
+        Value::Char(c) if ty == Some(&Type::CHAR) => builder.encode_field(&format_char(*c)),
         Value::Char(v) => builder.encode_field(&v.to_string()),
         Value::VarChar(v) => builder.encode_field(v),
+        // timestamps past the years chrono holds are read as their text,
+        // which has no binary form
+        Value::Text(v)
+            if binary && matches!(ty, Some(&Type::TIMESTAMP) | Some(&Type::TIMESTAMPTZ)) =>
+        {
+            Err(PgError::new(
+                sqlstate::DATETIME_FIELD_OVERFLOW,
+                format!("timestamp out of range for binary format: \"{}\"", v),
+            )
+            .into())
+        }
         Value::Text(v) => builder.encode_field(v),
+        Value::TextBytes(b) => {
+            let s = std::str::from_utf8(b).map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+            builder.encode_field(&s)
+        }
+        // written as the peer sent it, without parsing it, so with postgres'
+        // own spacing, {"a": 1} where parsed json is written as {"a":1}
+        Value::JsonText(b) | Value::JsonBText(b) => {
+            let s = std::str::from_utf8(b).map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+            builder.encode_field(&s)
+        }
         Value::Binary(b) => {
             let bytes: &[u8] = b.as_ref();
             builder.encode_field(&bytes)
@@ -30,67 +592,305 @@ fn encode_value(value: &Value, builder: &mut DataRowEncoder) -> PgWireResult<()>
             let bytes: &[u8] = b.as_ref();
             builder.encode_field(&bytes)
         }
-        Value::Date(d) => builder.encode_field(d),
+        // ISO is what the encoder writes by itself
+        Value::Date(d) if iso => builder.encode_field(d),
+        Value::Date(d) => builder.encode_field(&format_date(*d, opts.date_style)),
         Value::Time(t) => builder.encode_field(t),
         Value::TimeWithTimeZone(t) => builder.encode_field(t),
-        Value::Timestamp(ts) => builder.encode_field(ts),
-        Value::PostgresTimestamp(pgts) => builder.encode_field(pgts),
-        Value::TimestampWithTimeZone(ts) => builder.encode_field(ts),
+        Value::Timestamp(ts) if iso => builder.encode_field(ts),
+        Value::Timestamp(ts) => {
+            builder.encode_field(&format_timestamp(ts.naive_utc(), None, opts.date_style))
+        }
+        Value::PostgresTimestamp(pgts) if iso => builder.encode_field(pgts),
+        Value::PostgresTimestamp(pgts) => {
+            builder.encode_field(&format_timestamp(*pgts, None, opts.date_style))
+        }
+        Value::TimestampWithTimeZone(ts) if iso => builder.encode_field(ts),
+        Value::TimestampWithTimeZone(ts) => builder.encode_field(&format_timestamp(
+            ts.naive_utc(),
+            Some("UTC"),
+            opts.date_style,
+        )),
         Value::IpAddr(ip) => builder.encode_field(&ip.to_string()),
-        Value::Interval(i) => builder.encode_field(i),
+        Value::Interval(i) => builder.encode_field(&format_interval(*i, opts.interval_style)),
+        // written here rather than by the array, as intervals are written in
+        // the session's style
+        Value::Array(ArrayValue::Interval(arr)) => builder.encode_field(&format_array(
+            arr.iter()
+                .map(|iv| iv.map(|iv| format_interval(iv, opts.interval_style))),
+        )),
+        Value::Array(ArrayValue::Numeric(arr)) => {
+            builder.encode_field(&format_array(arr.iter().cloned().map(Some)))
+        }
         Value::Array(a) => builder.encode_field(a),
+        Value::Range(r) => builder.encode_field(&r.to_string()),
         Value::Json(j) => builder.encode_field(&j.to_string()),
         Value::JsonB(j) => builder.encode_field(&j.to_string()),
         Value::Uuid(u) => {
             let s = u.to_string();
             builder.encode_field(&s)
         }
-        Value::Enum(_) | Value::Hstore(_) => Err(PgWireError::ApiError(
+        Value::Enum(_) | Value::Hstore(_) => Err(PgError::new(
+            sqlstate::FEATURE_NOT_SUPPORTED,
             format!(
-                "cannot write value {:?} in postgres protocol: unimplemented",
-                &value
-            )
-            .into(),
-        )),
+                "cannot write value of type {} in postgres protocol: unimplemented",
+                value.type_name()
+            ),
+        )
+        .into()),
     }
 }
 
 pub fn sendable_stream_to_query_response<'a>(
     schema: Schema,
     record_stream: SendableStream,
+    opts: EncodeOptions,
 ) -> PgWireResult<Response<'a>> {
+    let schema = with_column_case(schema, opts.column_case);
     let schema_copy = schema.clone();
 
-    let data_row_stream = record_stream
-        .map(move |record_result| {
-            record_result.and_then(|record| {
-                let mut encoder = DataRowEncoder::new(schema_copy.clone());
-                for value in record.values.iter() {
-                    encode_value(value, &mut encoder)?;
-                }
-                encoder.finish()
+    let encode = move |record_result: PgWireResult<Record>| {
+        record_result.and_then(|record| encode_record(&record, &schema_copy, &opts))
+    };
+
+    let data_row_stream = match opts.batch_bytes {
+        None => record_stream.map(encode).boxed(),
+        Some(max_bytes) => yield_by_size(record_stream, max_bytes).map(encode).boxed(),
+    };
+
+    Ok(Response::Query(QueryResponse::new(schema, data_row_stream)))
+}
+
+/// Passes `records` along, yielding to the runtime before each record that
+/// would take the records passed since the last yield past `max_bytes` in
+/// memory, the streamed counterpart of `batch_by_size`.
+pub fn yield_by_size<S>(records: S, max_bytes: usize) -> impl Stream<Item = PgWireResult<Record>>
+where
+    S: Stream<Item = PgWireResult<Record>>,
+{
+    let mut batch_bytes = 0;
+    records.then(move |record_result| {
+        let size = record_result.as_ref().map_or(0, Record::heap_size);
+        let full = batch_bytes > 0 && batch_bytes + size > max_bytes;
+        if full {
+            batch_bytes = 0;
+        }
+        batch_bytes += size;
+        async move {
+            if full {
+                tokio::task::yield_now().await;
+            }
+            record_result
+        }
+    })
+}
+
+/// Splits `records` into consecutive batches taking at most `max_bytes` in
+/// memory each. A record larger than `max_bytes` gets a batch of its own.
+pub fn batch_by_size(records: Vec<Record>, max_bytes: usize) -> Vec<Vec<Record>> {
+    let mut batches = Vec::new();
+    let mut batch = Vec::new();
+    let mut batch_bytes = 0;
+    for record in records {
+        let size = record.heap_size();
+        if !batch.is_empty() && batch_bytes + size > max_bytes {
+            batches.push(std::mem::take(&mut batch));
+            batch_bytes = 0;
+        }
+        batch_bytes += size;
+        batch.push(record);
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
+}
+
+/// Writes `records` as a query response, each column in the format of the
+/// same index in `formats` and in text past its end, so an empty `formats`
+/// writes all of them as text, and named in `opts.column_case`. Fails if a
+/// column asks for binary and its type can't be written in binary, see
+/// `supports_binary_format`.
+pub fn records_to_query_response<'a>(
+    records: Records,
+    opts: EncodeOptions,
+    formats: Vec<FieldFormat>,
+) -> PgWireResult<Response<'a>> {
+    let schema = with_column_case(with_formats(&records.schema, &formats)?, opts.column_case);
+    let schema_copy = schema.clone();
+
+    let encode = move |record: Record| encode_record(&record, &schema_copy, &opts);
+
+    let data_row_stream = match opts.batch_bytes {
+        None => stream::iter(records.records).map(encode).boxed(),
+        Some(max_bytes) => stream::iter(batch_by_size(records.records, max_bytes))
+            .then(|batch| async move {
+                tokio::task::yield_now().await;
+                stream::iter(batch)
             })
-        })
-        .boxed();
+            .flatten()
+            .map(encode)
+            .boxed(),
+    };
 
     Ok(Response::Query(QueryResponse::new(schema, data_row_stream)))
 }
 
-pub fn records_to_query_response<'a>(records: Records) -> PgWireResult<Response<'a>> {
-    let schema_copy = records.schema.clone();
+struct DedupAdjacent {
+    inner: SendableStream,
+    prev: Option<Vec<Value>>,
+}
 
-    let data_row_stream = stream::iter(records.records)
-        .map(move |record| {
-            let mut encoder = DataRowEncoder::new(schema_copy.clone());
-            for value in record.values.iter() {
-                encode_value(value, &mut encoder)?;
+impl Stream for DedupAdjacent {
+    type Item = PgWireResult<Record>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let record = match self.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(record))) => record,
+                other => return other,
+            };
+            let duplicate = self.prev.as_ref().is_some_and(|prev| {
+                prev.len() == record.values.len()
+                    && prev.iter().zip(&record.values).all(|(a, b)| a.value_eq(b))
+            });
+            if !duplicate {
+                self.prev = Some(record.values.clone());
+                return Poll::Ready(Some(Ok(record)));
             }
-            encoder.finish()
+        }
+    }
+}
+
+impl RecordStream for DedupAdjacent {
+    fn schema(&self) -> Schema {
+        self.inner.schema()
+    }
+}
+
+/// Drops each record whose values equal those of the record right before
+/// it, so runs of identical rows come out once. Rows repeated further apart
+/// are all kept.
+pub fn dedup_adjacent(stream: SendableStream) -> SendableStream {
+    Box::pin(DedupAdjacent {
+        inner: stream,
+        prev: None,
+    })
+}
+
+struct EnforceSchema {
+    inner: SendableStream,
+    schema: Schema,
+    kinds: Vec<TypeTag>,
+}
+
+impl EnforceSchema {
+    fn coerce(&self, record: Record) -> PgWireResult<Record> {
+        if record.values.len() != self.schema.len() {
+            return Err(PgError::new(
+                sqlstate::DATATYPE_MISMATCH,
+                format!(
+                    "record has {} values, expected {}",
+                    record.values.len(),
+                    self.schema.len()
+                ),
+            )
+            .into());
+        }
+        let values = record
+            .values
+            .into_iter()
+            .zip(self.schema.iter().zip(&self.kinds))
+            .map(|(value, (field, kind))| {
+                value.coerce_to(*kind).map_err(|err| {
+                    PgError::new(
+                        sqlstate::DATATYPE_MISMATCH,
+                        format!(
+                            "column \"{}\" of type {}: {:#}",
+                            field.name(),
+                            field.datatype(),
+                            err
+                        ),
+                    )
+                    .into()
+                })
+            })
+            .collect::<PgWireResult<Vec<_>>>()?;
+        Ok(Record {
+            values,
+            schema: self.schema.clone(),
         })
-        .boxed();
+    }
+}
+
+impl Stream for EnforceSchema {
+    type Item = PgWireResult<Record>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.inner.as_mut().poll_next(cx) {
+            Poll::Ready(Some(Ok(record))) => Poll::Ready(Some(self.coerce(record))),
+            other => other,
+        }
+    }
+}
+
+impl RecordStream for EnforceSchema {
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+}
+
+/// Coerces the values of each record of `stream` to the types of the
+/// columns of `schema`, see `Value::coerce_to`, for results of loosely typed
+/// sources going to typed destinations. A record fails on its first value
+/// that can't be coerced, naming its column.
+pub fn enforce_schema(stream: SendableStream, schema: Schema) -> SendableStream {
+    let kinds = schema
+        .iter()
+        .map(|field| pg_type_to_value_kind(field.datatype()))
+        .collect();
+    Box::pin(EnforceSchema {
+        inner: stream,
+        schema,
+        kinds,
+    })
+}
+
+struct Inspect<F> {
+    inner: SendableStream,
+    tap: F,
+}
+
+impl<F> Stream for Inspect<F>
+where
+    F: Fn(&PgWireResult<Record>) + Unpin,
+{
+    type Item = PgWireResult<Record>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = self.inner.as_mut().poll_next(cx);
+        if let Poll::Ready(Some(item)) = &poll {
+            (self.tap)(item);
+        }
+        poll
+    }
+}
+
+impl<F> RecordStream for Inspect<F>
+where
+    F: Fn(&PgWireResult<Record>) + Unpin,
+{
+    fn schema(&self) -> Schema {
+        self.inner.schema()
+    }
+}
 
-    Ok(Response::Query(QueryResponse::new(
-        records.schema,
-        data_row_stream,
-    )))
+/// Calls `tap` on each item of `stream`, errors included, and passes the
+/// item on unchanged, to log or count what goes through a stream while
+/// debugging without changing what consumes it.
+pub fn inspect<F>(stream: SendableStream, tap: F) -> SendableStream
+where
+    F: Fn(&PgWireResult<Record>) + Send + Sync + Unpin + 'static,
+{
+    Box::pin(Inspect { inner: stream, tap })
 }
@@ -0,0 +1,1126 @@
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+use chrono::NaiveDate;
+use futures::{stream, Stream, StreamExt};
+use peer_cursor::{
+    copy::{copy_in_to_records, CopyFormat, CopyInParser},
+    profile::{profile_stream, sample_stream},
+    progress::{ProgressRegistry, ProgressSnapshot, StreamEnd, StreamProgress},
+    resume::{OpenFuture, OpenSource, ResumableRecordStream, RetryPolicy},
+    util::{
+        batch_by_size, char_byte, dedup_adjacent, enforce_schema, format_array, format_char,
+        format_date, format_interval, format_timestamp, inspect, records_to_query_response,
+        yield_by_size, ColumnCase, DateFormat, DateOrder, DateStyle, EncodeOptions, IntervalStyle,
+    },
+    Record, RecordStream, Records, Schema, SendableStream,
+};
+use pgerror::{sqlstate, PgError};
+use pgwire::{
+    api::{
+        results::{FieldFormat, FieldInfo, Response},
+        Type,
+    },
+    error::{PgWireError, PgWireResult},
+    messages::Message,
+};
+use value::{array::ArrayValue, interval::Interval, Value};
+
+// 1 day, 2 hours, 3 minutes and 4.5 seconds
+const INTERVAL_MICROS: i64 = 93_784_500_000;
+
+#[test]
+fn interval_postgres_style() {
+    assert_eq!(
+        format_interval(
+            Interval::from_micros(INTERVAL_MICROS),
+            IntervalStyle::Postgres
+        ),
+        "26:03:04.5"
+    );
+    assert_eq!(
+        format_interval(
+            Interval::from_micros(-INTERVAL_MICROS),
+            IntervalStyle::Postgres
+        ),
+        "-26:03:04.5"
+    );
+}
+
+#[test]
+fn interval_iso_8601_style() {
+    assert_eq!(
+        format_interval(
+            Interval::from_micros(INTERVAL_MICROS),
+            IntervalStyle::Iso8601
+        ),
+        "PT26H3M4.5S"
+    );
+    assert_eq!(
+        format_interval(
+            Interval::from_micros(-INTERVAL_MICROS),
+            IntervalStyle::Iso8601
+        ),
+        "PT-26H-3M-4.5S"
+    );
+    assert_eq!(
+        format_interval(Interval::default(), IntervalStyle::Iso8601),
+        "PT0S"
+    );
+}
+
+#[test]
+fn interval_with_months_and_days_in_each_style() {
+    // 1 year, 2 months, 3 days and 4:05:06.5
+    let interval = Interval {
+        months: 14,
+        days: 3,
+        microseconds: 14_706_500_000,
+    };
+    let mixed = Interval {
+        months: -14,
+        days: 3,
+        microseconds: -14_706_500_000,
+    };
+    for (interval, style, expected) in [
+        (
+            interval,
+            IntervalStyle::Postgres,
+            "1 year 2 mons 3 days 04:05:06.5",
+        ),
+        (
+            interval,
+            IntervalStyle::PostgresVerbose,
+            "@ 1 year 2 mons 3 days 4 hours 5 mins 6.5 secs",
+        ),
+        (interval, IntervalStyle::SqlStandard, "+1-2 +3 +4:05:06.5"),
+        (interval, IntervalStyle::Iso8601, "P1Y2M3DT4H5M6.5S"),
+        (
+            mixed,
+            IntervalStyle::Postgres,
+            "-1 years -2 mons +3 days -04:05:06.5",
+        ),
+        (
+            mixed,
+            IntervalStyle::PostgresVerbose,
+            "@ 1 year 2 mons -3 days 4 hours 5 mins 6.5 secs ago",
+        ),
+        (mixed, IntervalStyle::SqlStandard, "-1-2 +3 -4:05:06.5"),
+        (mixed, IntervalStyle::Iso8601, "P-1Y-2M3DT-4H-5M-6.5S"),
+    ] {
+        assert_eq!(format_interval(interval, style), expected);
+    }
+}
+
+#[test]
+fn interval_postgres_verbose_style() {
+    assert_eq!(
+        format_interval(
+            Interval::from_micros(-INTERVAL_MICROS),
+            IntervalStyle::PostgresVerbose
+        ),
+        "@ 26 hours 3 mins 4.5 secs ago"
+    );
+    assert_eq!(
+        format_interval(
+            Interval::from_micros(1_000_000),
+            IntervalStyle::PostgresVerbose
+        ),
+        "@ 1 sec"
+    );
+    assert_eq!(
+        format_interval(Interval::default(), IntervalStyle::PostgresVerbose),
+        "@ 0"
+    );
+}
+
+#[test]
+fn interval_style_settings() {
+    for (setting, style) in [
+        ("postgres", IntervalStyle::Postgres),
+        ("Postgres_Verbose", IntervalStyle::PostgresVerbose),
+        ("sql_standard", IntervalStyle::SqlStandard),
+        ("iso_8601", IntervalStyle::Iso8601),
+        // `SET intervalstyle TO DEFAULT`, and `RESET intervalstyle`
+        ("DEFAULT", IntervalStyle::Postgres),
+    ] {
+        assert_eq!(setting.parse::<IntervalStyle>().unwrap(), style);
+    }
+    assert!("german".parse::<IntervalStyle>().is_err());
+}
+
+#[test]
+fn interval_sql_standard_style() {
+    let year_month = Interval {
+        months: 14,
+        ..Interval::default()
+    };
+    assert_eq!(
+        format_interval(year_month, IntervalStyle::SqlStandard),
+        "1-2"
+    );
+    let day_time = Interval {
+        days: -3,
+        microseconds: -14_706_500_000,
+        ..Interval::default()
+    };
+    assert_eq!(
+        format_interval(day_time, IntervalStyle::SqlStandard),
+        "-3 4:05:06.5"
+    );
+    assert_eq!(
+        format_interval(Interval::default(), IntervalStyle::SqlStandard),
+        "0"
+    );
+}
+
+#[test]
+fn interval_of_ten_thousand_years() {
+    let interval = Interval {
+        months: 120_000,
+        days: 0,
+        microseconds: 500_001,
+    };
+    assert_eq!(
+        format_interval(interval, IntervalStyle::Postgres),
+        "10000 years 00:00:00.500001"
+    );
+    assert_eq!(
+        format_interval(interval, IntervalStyle::Iso8601),
+        "P10000YT0.500001S"
+    );
+}
+
+#[test]
+fn interval_style_from_setting() {
+    assert_eq!(
+        "ISO_8601".parse::<IntervalStyle>().unwrap(),
+        IntervalStyle::Iso8601
+    );
+    assert!("iso".parse::<IntervalStyle>().is_err());
+}
+
+fn date_style(setting: &str) -> DateStyle {
+    DateStyle::default().with_setting(setting).unwrap()
+}
+
+#[test]
+fn date_in_iso_and_german_style() {
+    let date = NaiveDate::from_ymd_opt(1997, 12, 7).unwrap();
+    assert_eq!(format_date(date, DateStyle::default()), "1997-12-07");
+    assert_eq!(format_date(date, date_style("German")), "07.12.1997");
+    assert_eq!(format_date(date, date_style("SQL, MDY")), "12/07/1997");
+    assert_eq!(format_date(date, date_style("Postgres, DMY")), "07-12-1997");
+}
+
+#[test]
+fn timestamp_in_iso_and_german_style() {
+    let ts = NaiveDate::from_ymd_opt(1997, 12, 7)
+        .unwrap()
+        .and_hms_micro_opt(7, 37, 16, 500_000)
+        .unwrap();
+    assert_eq!(
+        format_timestamp(ts, None, DateStyle::default()),
+        "1997-12-07 07:37:16.5"
+    );
+    assert_eq!(
+        format_timestamp(ts, Some("UTC"), date_style("German")),
+        "07.12.1997 07:37:16.5 UTC"
+    );
+    assert_eq!(
+        format_timestamp(ts, None, date_style("Postgres")),
+        "Sun Dec 07 07:37:16.5 1997"
+    );
+}
+
+#[test]
+fn date_style_from_setting() {
+    // German alone switches to day-first order, as in postgres
+    assert_eq!(
+        date_style("german"),
+        DateStyle {
+            format: DateFormat::German,
+            order: DateOrder::Dmy,
+        }
+    );
+    // a part left out keeps its current value
+    let sql = date_style("SQL, DMY").with_setting("ISO").unwrap();
+    assert_eq!(sql.format, DateFormat::Iso);
+    assert_eq!(sql.order, DateOrder::Dmy);
+    assert!(DateStyle::default().with_setting("swatch").is_err());
+    assert!(DateStyle::default().with_setting("").is_err());
+}
+
+fn wide_record(bytes: usize) -> Record {
+    Record {
+        values: vec![Value::Integer(1), Value::Text("x".repeat(bytes))],
+        schema: Arc::new(vec![]),
+    }
+}
+
+#[test]
+fn batches_respect_byte_threshold() {
+    const MAX_BYTES: usize = 1 << 20;
+    let sizes = [
+        300_000,
+        300_000,
+        300_000,
+        300_000,
+        2 * MAX_BYTES,
+        10,
+        500_000,
+    ];
+    let records: Vec<Record> = sizes.iter().map(|&n| wide_record(n)).collect();
+
+    let batches = batch_by_size(records, MAX_BYTES);
+    let batch_lens: Vec<usize> = batches.iter().map(Vec::len).collect();
+    assert_eq!(batch_lens, vec![3, 1, 1, 2]);
+    for batch in &batches {
+        let total: usize = batch.iter().map(Record::heap_size).sum();
+        assert!(batch.len() == 1 || total <= MAX_BYTES);
+    }
+}
+
+#[tokio::test]
+async fn streamed_records_yield_at_byte_threshold() {
+    const MAX_BYTES: usize = 1 << 20;
+    let sizes = [
+        300_000,
+        300_000,
+        300_000,
+        300_000,
+        2 * MAX_BYTES,
+        10,
+        500_000,
+    ];
+    let records = sizes.iter().map(|&n| Ok(wide_record(n)));
+    let mut stream = Box::pin(yield_by_size(stream::iter(records), MAX_BYTES));
+
+    // the records read between two yields
+    let mut batch_lens = vec![0];
+    loop {
+        match futures::poll!(stream.next()) {
+            Poll::Ready(Some(record)) => {
+                assert!(record.is_ok());
+                *batch_lens.last_mut().unwrap() += 1;
+            }
+            Poll::Ready(None) => break,
+            Poll::Pending => batch_lens.push(0),
+        }
+    }
+    assert_eq!(batch_lens, vec![3, 1, 1, 2]);
+}
+
+fn records_of(columns: &[(&str, Type)], values: Vec<Value>) -> Records {
+    let schema: Schema = Arc::new(
+        columns
+            .iter()
+            .map(|(name, ty)| {
+                FieldInfo::new(name.to_string(), None, None, ty.clone(), FieldFormat::Text)
+            })
+            .collect(),
+    );
+    Records {
+        records: vec![Record {
+            values,
+            schema: schema.clone(),
+        }],
+        schema,
+    }
+}
+
+// the cells of the rows of a response as a client reads them off the wire,
+// `None` for NULL
+async fn data_row_cells(response: PgWireResult<Response<'_>>) -> Vec<Vec<Option<Vec<u8>>>> {
+    let Ok(Response::Query(mut query)) = response else {
+        panic!("expected a query response");
+    };
+    let mut rows = Vec::new();
+    while let Some(row) = query.data_rows().next().await {
+        let mut buf = BytesMut::new();
+        row.unwrap().encode(&mut buf).unwrap();
+        // past the message tag, its length and the column count
+        let mut data = &buf[7..];
+        let mut cells = Vec::new();
+        while data.has_remaining() {
+            let len = data.get_i32();
+            cells.push((len >= 0).then(|| {
+                let cell = data[..len as usize].to_vec();
+                data.advance(len as usize);
+                cell
+            }));
+        }
+        rows.push(cells);
+    }
+    rows
+}
+
+#[test]
+fn response_mixes_text_and_binary_columns() {
+    let records = records_of(
+        &[("id", Type::INT8), ("name", Type::TEXT), ("at", Type::DATE)],
+        vec![
+            Value::BigInt(1),
+            Value::Text("a".into()),
+            Value::Date(NaiveDate::from_ymd_opt(2024, 1, 2).unwrap()),
+        ],
+    );
+    // the date is past the given formats, so text
+    let formats = vec![FieldFormat::Binary, FieldFormat::Text];
+    let Ok(Response::Query(query)) =
+        records_to_query_response(records, EncodeOptions::default(), formats)
+    else {
+        panic!("expected a query response");
+    };
+    let formats: Vec<FieldFormat> = query.row_schema().iter().map(|f| *f.format()).collect();
+    assert_eq!(
+        formats,
+        vec![FieldFormat::Binary, FieldFormat::Text, FieldFormat::Text]
+    );
+}
+
+#[test]
+fn numeric_and_interval_arrays_keep_their_element_types() {
+    let interval = Interval {
+        months: 0,
+        days: 1,
+        microseconds: 7_200_000_000,
+    };
+    let records = records_of(
+        &[("n", Type::NUMERIC_ARRAY), ("iv", Type::INTERVAL_ARRAY)],
+        vec![
+            Value::Array(ArrayValue::Numeric(vec!["1.50".into(), "NaN".into()])),
+            Value::Array(ArrayValue::Interval(vec![Some(interval), None])),
+        ],
+    );
+    let Ok(Response::Query(query)) =
+        records_to_query_response(records, EncodeOptions::default(), vec![])
+    else {
+        panic!("expected a query response");
+    };
+    let types: Vec<&Type> = query.row_schema().iter().map(|f| f.datatype()).collect();
+    assert_eq!(types, [&Type::NUMERIC_ARRAY, &Type::INTERVAL_ARRAY]);
+
+    // the text the encoder writes for each of them
+    assert_eq!(
+        format_array(["1.50", "NaN"].map(|n| Some(n.to_string()))),
+        "{1.50,NaN}"
+    );
+    let intervals = [Some(interval), None];
+    for (style, expected) in [
+        (IntervalStyle::Postgres, r#"{"1 day 02:00:00",NULL}"#),
+        (IntervalStyle::Iso8601, "{P1DT2H,NULL}"),
+    ] {
+        let elements = intervals.map(|iv| iv.map(|iv| format_interval(iv, style)));
+        assert_eq!(format_array(elements), expected);
+    }
+}
+
+#[tokio::test]
+async fn char_column_round_trips_every_byte() {
+    for b in 0..=u8::MAX {
+        // the character the byte of a "char" column is decoded into
+        assert_eq!(char_byte(char::from(b)), b);
+    }
+    assert_eq!(char_byte('€'), 0xe2);
+
+    assert_eq!(format_char('a'), "a");
+    assert_eq!(format_char(char::from(0xc3)), "\\303");
+    assert_eq!(format_char('\0'), "");
+
+    let records = records_of(
+        &[("c", Type::CHAR), ("t", Type::CHAR), ("s", Type::BPCHAR)],
+        vec![
+            Value::Char(char::from(0xc3)),
+            Value::Char(char::from(0xc3)),
+            Value::Char('x'),
+        ],
+    );
+    let formats = vec![FieldFormat::Binary, FieldFormat::Text, FieldFormat::Text];
+    let response = records_to_query_response(records, EncodeOptions::default(), formats);
+    assert_eq!(
+        data_row_cells(response).await,
+        [[
+            Some(vec![0xc3]),
+            Some(b"\\303".to_vec()),
+            Some(b"x".to_vec())
+        ]]
+    );
+}
+
+#[tokio::test]
+async fn out_of_range_timestamps_have_no_binary_form() {
+    let far = || Value::Text("294276-12-31 23:59:59".to_string());
+    let records = records_of(&[("at", Type::TIMESTAMP)], vec![far()]);
+    let response = records_to_query_response(records, EncodeOptions::default(), vec![]);
+    assert_eq!(
+        data_row_cells(response).await,
+        [[Some(b"294276-12-31 23:59:59".to_vec())]]
+    );
+
+    let records = records_of(&[("at", Type::TIMESTAMP)], vec![far()]);
+    let formats = vec![FieldFormat::Binary];
+    let Ok(Response::Query(mut query)) =
+        records_to_query_response(records, EncodeOptions::default(), formats)
+    else {
+        panic!("expected a query response");
+    };
+    match query.data_rows().next().await {
+        Some(Err(PgWireError::UserError(info))) => assert_eq!(info.code(), "22008"),
+        _ => panic!("expected the timestamp to be rejected"),
+    }
+}
+
+#[tokio::test]
+async fn json_keys_keep_their_order() {
+    // parsed like a json cell of the peer, and written back
+    let json: serde_json::Value =
+        serde_json::from_str(r#"{"b": 1, "a": {"d": [2], "c": null}}"#).unwrap();
+    let records = records_of(
+        &[("j", Type::JSON), ("jb", Type::JSONB)],
+        vec![Value::Json(json.clone()), Value::JsonB(json)],
+    );
+    let response = records_to_query_response(records, EncodeOptions::default(), vec![]);
+    let written = br#"{"b":1,"a":{"d":[2],"c":null}}"#.to_vec();
+    assert_eq!(
+        data_row_cells(response).await,
+        [[Some(written.clone()), Some(written)]]
+    );
+}
+
+#[tokio::test]
+async fn json_text_is_written_as_the_peer_sent_it() {
+    let json = br#"{"b": [1, 2], "a": 1.50}"#;
+    let jsonb = br#"{"a": 1, "b": "\u00e9"}"#;
+    let records = records_of(
+        &[("j", Type::JSON), ("jb", Type::JSONB)],
+        vec![
+            Value::JsonText(Bytes::from_static(json)),
+            Value::JsonBText(Bytes::from_static(jsonb)),
+        ],
+    );
+    let response = records_to_query_response(records, EncodeOptions::default(), vec![]);
+    assert_eq!(
+        data_row_cells(response).await,
+        [[Some(json.to_vec()), Some(jsonb.to_vec())]]
+    );
+}
+
+#[test]
+fn array_elements_are_quoted_when_needed() {
+    let elements = [
+        "plain",
+        "",
+        "null",
+        "a,b",
+        "say \"hi\"",
+        "back\\slash",
+        "{x}",
+    ]
+    .map(|e| Some(e.to_string()));
+    assert_eq!(
+        format_array(elements),
+        r#"{plain,"","null","a,b","say \"hi\"","back\\slash","{x}"}"#
+    );
+}
+
+#[test]
+fn response_rejects_binary_for_unsupported_types() {
+    let records = records_of(
+        &[("id", Type::INT4), ("doc", Type::JSONB)],
+        vec![Value::Integer(1), Value::JsonB(serde_json::json!({}))],
+    );
+    let formats = vec![FieldFormat::Binary, FieldFormat::Binary];
+    match records_to_query_response(records, EncodeOptions::default(), formats) {
+        Err(PgWireError::UserError(info)) => {
+            assert_eq!(info.code(), "0A000");
+            assert!(info.message().contains("\"doc\""), "{}", info.message());
+        }
+        _ => panic!("expected binary jsonb to be rejected"),
+    }
+}
+
+#[test]
+fn response_names_columns_in_the_column_case() {
+    for (case, expected) in [
+        (ColumnCase::AsIs, ["userId", "Name", "created_at"]),
+        (ColumnCase::Lower, ["userid", "name", "created_at"]),
+        (ColumnCase::Upper, ["USERID", "NAME", "CREATED_AT"]),
+    ] {
+        let records = records_of(
+            &[
+                ("userId", Type::INT4),
+                ("Name", Type::TEXT),
+                ("created_at", Type::DATE),
+            ],
+            vec![
+                Value::Integer(1),
+                Value::Text("a".into()),
+                Value::Date(NaiveDate::from_ymd_opt(2024, 1, 2).unwrap()),
+            ],
+        );
+        let opts = EncodeOptions {
+            column_case: case,
+            ..EncodeOptions::default()
+        };
+        let Ok(Response::Query(query)) = records_to_query_response(records, opts, Vec::new())
+        else {
+            panic!("expected a query response");
+        };
+        let names: Vec<&str> = query
+            .row_schema()
+            .iter()
+            .map(|f| f.name().as_str())
+            .collect();
+        assert_eq!(names, expected, "{:?}", case);
+    }
+}
+
+#[test]
+fn column_case_from_setting() {
+    assert_eq!("UPPER".parse::<ColumnCase>().unwrap(), ColumnCase::Upper);
+    assert_eq!("as_is".parse::<ColumnCase>().unwrap(), ColumnCase::AsIs);
+    assert!("title".parse::<ColumnCase>().is_err());
+}
+
+struct VecStream {
+    schema: Schema,
+    records: stream::Iter<std::vec::IntoIter<PgWireResult<Record>>>,
+}
+
+impl Stream for VecStream {
+    type Item = PgWireResult<Record>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.records).poll_next(cx)
+    }
+}
+
+impl RecordStream for VecStream {
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+}
+
+fn stream_of(columns: &[(&str, Type)], rows: Vec<Vec<Value>>) -> SendableStream {
+    let schema: Schema = Arc::new(
+        columns
+            .iter()
+            .map(|(name, ty)| {
+                FieldInfo::new(name.to_string(), None, None, ty.clone(), FieldFormat::Text)
+            })
+            .collect(),
+    );
+    let records = rows
+        .into_iter()
+        .map(|values| {
+            Ok(Record {
+                values,
+                schema: schema.clone(),
+            })
+        })
+        .collect::<Vec<_>>();
+    Box::pin(VecStream {
+        schema,
+        records: stream::iter(records),
+    })
+}
+
+#[tokio::test]
+async fn profile_counts_nulls_and_tracks_min_max() {
+    let stream = stream_of(
+        &[
+            ("id", Type::INT4),
+            ("name", Type::TEXT),
+            ("doc", Type::JSON),
+        ],
+        vec![
+            vec![
+                Value::Integer(3),
+                Value::Text("carol".into()),
+                Value::Json(serde_json::json!({})),
+            ],
+            vec![Value::Integer(-1), Value::Null, Value::Null],
+            vec![
+                Value::Integer(7),
+                Value::Text("al".into()),
+                Value::Json(serde_json::json!([1])),
+            ],
+            vec![Value::Null, Value::Text("al".into()), Value::Null],
+        ],
+    );
+
+    let profile = profile_stream(stream).await.unwrap();
+    assert_eq!(profile.row_count, 4);
+
+    let [id, name, doc] = &profile.columns[..] else {
+        panic!("expected three columns");
+    };
+    assert_eq!(id.name, "id");
+    assert_eq!(id.null_count, 1);
+    assert_eq!(id.distinct, 3);
+    assert_eq!(id.min, Some(Value::Integer(-1)));
+    assert_eq!(id.max, Some(Value::Integer(7)));
+    assert_eq!(id.max_byte_width, 4);
+
+    assert_eq!(name.null_count, 1);
+    assert_eq!(name.distinct, 2);
+    assert_eq!(name.min, Some(Value::Text("al".into())));
+    assert_eq!(name.max, Some(Value::Text("carol".into())));
+    assert_eq!(name.max_byte_width, 5);
+
+    // json isn't orderable
+    assert_eq!(doc.null_count, 2);
+    assert_eq!(doc.min, None);
+    assert_eq!(doc.max, None);
+}
+
+async fn sampled_ids(total: i32, k: usize) -> Vec<i32> {
+    let stream = stream_of(
+        &[("id", Type::INT4)],
+        (0..total).map(|id| vec![Value::Integer(id)]).collect(),
+    );
+    sample_stream(stream, k)
+        .map(|record| match record.unwrap().values[..] {
+            [Value::Integer(id)] => id,
+            _ => panic!("expected a single int4"),
+        })
+        .collect()
+        .await
+}
+
+#[tokio::test]
+async fn sample_keeps_all_of_a_short_stream() {
+    let mut ids = sampled_ids(3, 5).await;
+    ids.sort();
+    assert_eq!(ids, vec![0, 1, 2]);
+    assert!(sampled_ids(0, 5).await.is_empty());
+}
+
+#[tokio::test]
+async fn sample_yields_k_distinct_records_of_a_long_stream() {
+    let mut ids = sampled_ids(1000, 10).await;
+    assert_eq!(ids.len(), 10);
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), 10);
+    assert!(ids.iter().all(|id| (0..1000).contains(id)));
+    assert!(sampled_ids(1000, 0).await.is_empty());
+}
+
+async fn dedup_ids(rows: &[i32]) -> Vec<i32> {
+    let stream = stream_of(
+        &[("id", Type::INT4)],
+        rows.iter().map(|&id| vec![Value::Integer(id)]).collect(),
+    );
+    let deduped = dedup_adjacent(stream);
+    assert_eq!(deduped.schema()[0].name(), "id");
+    deduped
+        .map(|record| match record.unwrap().values[..] {
+            [Value::Integer(id)] => id,
+            ref other => panic!("unexpected values {:?}", other),
+        })
+        .collect::<Vec<_>>()
+        .await
+}
+
+#[tokio::test]
+async fn dedup_adjacent_collapses_runs() {
+    assert_eq!(dedup_ids(&[1, 1, 1, 2, 2, 3]).await, [1, 2, 3]);
+    assert_eq!(dedup_ids(&[]).await, Vec::<i32>::new());
+}
+
+#[tokio::test]
+async fn dedup_adjacent_keeps_distinct_rows() {
+    assert_eq!(dedup_ids(&[1, 2, 3]).await, [1, 2, 3]);
+}
+
+#[tokio::test]
+async fn dedup_adjacent_keeps_separated_duplicates() {
+    assert_eq!(dedup_ids(&[1, 2, 1, 1, 2]).await, [1, 2, 1, 2]);
+
+    let stream = stream_of(
+        &[("x", Type::FLOAT8), ("name", Type::TEXT)],
+        vec![
+            vec![Value::Double(f64::NAN), Value::Null],
+            vec![Value::Double(f64::NAN), Value::Null],
+            vec![Value::Double(f64::NAN), Value::Text("a".into())],
+        ],
+    );
+    assert_eq!(dedup_adjacent(stream).count().await, 2);
+}
+
+#[tokio::test]
+async fn enforce_schema_coerces_values() {
+    let stream = stream_of(
+        &[
+            ("id", Type::TEXT),
+            ("day", Type::TEXT),
+            ("label", Type::INT8),
+        ],
+        vec![
+            vec![
+                Value::Text("42".into()),
+                Value::Text("2024-01-02".into()),
+                Value::BigInt(7),
+            ],
+            vec![Value::Null, Value::Null, Value::Null],
+        ],
+    );
+    let schema = stream_of(
+        &[
+            ("id", Type::INT4),
+            ("day", Type::DATE),
+            ("label", Type::TEXT),
+        ],
+        vec![],
+    )
+    .schema();
+
+    let enforced = enforce_schema(stream, schema);
+    assert_eq!(enforced.schema()[0].datatype(), &Type::INT4);
+    let records: Vec<_> = enforced.map(Result::unwrap).collect().await;
+    assert_eq!(
+        records[0].values,
+        [
+            Value::Integer(42),
+            Value::Date(NaiveDate::from_ymd_opt(2024, 1, 2).unwrap()),
+            Value::Text("7".into()),
+        ]
+    );
+    assert_eq!(records[0].schema[1].datatype(), &Type::DATE);
+    assert_eq!(records[1].values, [Value::Null, Value::Null, Value::Null]);
+}
+
+#[tokio::test]
+async fn enforce_schema_names_the_mismatched_column() {
+    let stream = stream_of(
+        &[("id", Type::INT8), ("qty", Type::TEXT)],
+        vec![
+            vec![Value::BigInt(1), Value::Text("3".into())],
+            vec![Value::BigInt(2), Value::Text("three".into())],
+        ],
+    );
+    let schema = stream_of(&[("id", Type::INT4), ("qty", Type::INT2)], vec![]).schema();
+
+    let mut enforced = enforce_schema(stream, schema);
+    assert!(enforced.next().await.unwrap().is_ok());
+    match enforced.next().await.unwrap() {
+        Err(PgWireError::UserError(info)) => {
+            assert_eq!(info.code(), "42804");
+            assert!(info.message().contains("\"qty\""), "{}", info.message());
+            assert!(info.message().contains("three"), "{}", info.message());
+        }
+        _ => panic!("expected \"three\" not to coerce to a smallint"),
+    }
+}
+
+#[tokio::test]
+async fn inspect_sees_every_item_and_passes_it_on() {
+    let schema = stream_of(&[("id", Type::INT4)], vec![]).schema();
+    let record = |id| -> PgWireResult<Record> {
+        Ok(Record {
+            values: vec![Value::Integer(id)],
+            schema: schema.clone(),
+        })
+    };
+    let items = vec![
+        record(1),
+        Err(PgWireError::ApiError("lost the connection".into())),
+        record(2),
+    ];
+    let stream: SendableStream = Box::pin(VecStream {
+        schema: schema.clone(),
+        records: stream::iter(items),
+    });
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let tap = {
+        let seen = seen.clone();
+        move |item: &PgWireResult<Record>| {
+            seen.lock().unwrap().push(match item {
+                Ok(record) => Some(record.values.clone()),
+                Err(_) => None,
+            })
+        }
+    };
+    let items: Vec<_> = inspect(stream, tap).collect().await;
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        [
+            Some(vec![Value::Integer(1)]),
+            None,
+            Some(vec![Value::Integer(2)])
+        ]
+    );
+    assert_eq!(items.len(), 3);
+    assert_eq!(items[0].as_ref().unwrap().values, [Value::Integer(1)]);
+    assert!(items[1].is_err());
+    assert_eq!(items[2].as_ref().unwrap().values, [Value::Integer(2)]);
+}
+
+// a source of the ids 1 to 6, ordered on them, whose first `failures` opens
+// fail with `code` after `fail_after` rows; the watermark of each open is
+// kept in `opens`
+fn flaky_source(
+    opens: Arc<Mutex<Vec<Option<Value>>>>,
+    failures: usize,
+    fail_after: usize,
+    code: &'static str,
+) -> OpenSource {
+    Box::new(move |after: Option<Value>| -> OpenFuture {
+        let mut opens = opens.lock().unwrap();
+        let attempt = opens.len();
+        opens.push(after.clone());
+        let from = match after {
+            None => 0,
+            Some(Value::Integer(id)) => id,
+            Some(other) => panic!("unexpected watermark {:?}", other),
+        };
+        let schema = stream_of(&[("id", Type::INT4)], vec![]).schema();
+        let mut items = (from + 1..=6)
+            .map(|id| {
+                Ok(Record {
+                    values: vec![Value::Integer(id)],
+                    schema: schema.clone(),
+                })
+            })
+            .collect::<Vec<PgWireResult<Record>>>();
+        if attempt < failures {
+            items.truncate(fail_after);
+            items.push(Err(PgError::new(code, "lost the connection").into()));
+        }
+        let stream: SendableStream = Box::pin(VecStream {
+            schema,
+            records: stream::iter(items),
+        });
+        Box::pin(async move { Ok(stream) })
+    })
+}
+
+async fn resumable_ids(source: OpenSource, max_retries: u32) -> Vec<Result<i32, String>> {
+    let policy = RetryPolicy {
+        max_retries,
+        backoff: Duration::ZERO,
+    };
+    let watermark = Box::new(|record: &Record| record.values[0].clone());
+    let stream = ResumableRecordStream::open(source, watermark, policy)
+        .await
+        .unwrap();
+    stream
+        .map(|record| match record {
+            Ok(record) => match record.values[..] {
+                [Value::Integer(id)] => Ok(id),
+                ref other => panic!("unexpected values {:?}", other),
+            },
+            Err(PgWireError::UserError(info)) => Err(info.code().to_string()),
+            Err(err) => panic!("unexpected error {}", err),
+        })
+        .collect()
+        .await
+}
+
+#[tokio::test]
+async fn resumable_stream_picks_up_after_the_last_watermark() {
+    let opens = Arc::new(Mutex::new(Vec::new()));
+    let source = flaky_source(opens.clone(), 1, 3, sqlstate::CONNECTION_FAILURE);
+
+    let ids = resumable_ids(source, 2).await;
+    assert_eq!(ids, [Ok(1), Ok(2), Ok(3), Ok(4), Ok(5), Ok(6)]);
+    assert_eq!(*opens.lock().unwrap(), [None, Some(Value::Integer(3))]);
+}
+
+#[tokio::test]
+async fn resumable_stream_gives_up_on_other_errors_and_repeated_failures() {
+    // only connection errors are retried
+    let opens = Arc::new(Mutex::new(Vec::new()));
+    let source = flaky_source(opens.clone(), 1, 2, sqlstate::INTERNAL_ERROR);
+    let ids = resumable_ids(source, 2).await;
+    assert_eq!(ids, [Ok(1), Ok(2), Err("XX000".to_string())]);
+    assert_eq!(opens.lock().unwrap().len(), 1);
+
+    // reopens that fail before reading a row count against the limit
+    let opens = Arc::new(Mutex::new(Vec::new()));
+    let source = flaky_source(opens.clone(), 10, 0, sqlstate::CONNECTION_FAILURE);
+    let ids = resumable_ids(source, 2).await;
+    assert_eq!(ids, [Err("08006".to_string())]);
+    assert_eq!(*opens.lock().unwrap(), [None, None, None]);
+}
+
+#[test]
+fn key_hash_covers_listed_columns_in_order() {
+    let record = |values: Vec<Value>| Record {
+        values,
+        schema: Arc::new(vec![]),
+    };
+    let a = record(vec![
+        Value::Integer(1),
+        Value::Text("x".into()),
+        Value::Integer(2),
+    ]);
+    let b = record(vec![
+        Value::BigInt(1),
+        Value::Text("y".into()),
+        Value::Integer(2),
+    ]);
+
+    assert_eq!(a.key_hash(&[0, 2]), b.key_hash(&[0, 2]));
+    assert_ne!(a.key_hash(&[0, 1]), b.key_hash(&[0, 1]));
+    assert_ne!(a.key_hash(&[0, 2]), a.key_hash(&[2, 0]));
+}
+
+type Completions = Arc<Mutex<Vec<(ProgressSnapshot, StreamEnd)>>>;
+
+// registers a stream of `n` text rows, with the totals it completed with
+fn registered_stream(registry: &Arc<ProgressRegistry>, n: usize) -> (SendableStream, Completions) {
+    let rows = (0..n).map(|i| vec![Value::Text(i.to_string())]).collect();
+    let stream = stream_of(&[("name", Type::TEXT)], rows);
+    let completions = Arc::new(Mutex::new(vec![]));
+    let hook_completions = completions.clone();
+    let stream = registry.register(
+        stream,
+        StreamProgress::new("q1".into(), Some("pg".into()), "SELECT name".into()),
+        Box::new(move |_, totals, end| hook_completions.lock().unwrap().push((totals, end))),
+    );
+    (stream, completions)
+}
+
+#[tokio::test]
+async fn progress_counts_rows_until_completion() {
+    let registry = Arc::new(ProgressRegistry::default());
+    let (mut stream, completions) = registered_stream(&registry, 3);
+    assert_eq!(registry.streams().len(), 1);
+    assert_eq!(registry.streams()[0].query_id, "q1");
+
+    while stream.next().await.is_some() {}
+    assert!(registry.streams().is_empty());
+    let totals = completions.lock().unwrap().clone();
+    assert_eq!(totals.len(), 1);
+    assert_eq!(totals[0].0.rows, 3);
+    assert!(totals[0].0.bytes > 0);
+    assert_eq!(totals[0].1, StreamEnd::Finished);
+
+    // the hook has already run, dropping doesn't run it again
+    drop(stream);
+    assert_eq!(completions.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn dropped_stream_completes_with_rows_sent() {
+    let registry = Arc::new(ProgressRegistry::default());
+    let (mut stream, completions) = registered_stream(&registry, 3);
+    stream.next().await;
+    drop(stream);
+
+    assert!(registry.streams().is_empty());
+    let completions = completions.lock().unwrap();
+    assert_eq!(completions.len(), 1);
+    assert_eq!(completions[0].0.rows, 1);
+    assert_eq!(completions[0].1, StreamEnd::Dropped);
+}
+
+fn copy_schema() -> Schema {
+    stream_of(
+        &[
+            ("id", Type::INT4),
+            ("name", Type::TEXT),
+            ("day", Type::DATE),
+        ],
+        vec![],
+    )
+    .schema()
+}
+
+fn copy_values(frames: &[&str], format: CopyFormat) -> Vec<Vec<Value>> {
+    copy_in_to_records(frames, copy_schema(), format)
+        .unwrap()
+        .records
+        .into_iter()
+        .map(|record| record.values)
+        .collect()
+}
+
+#[test]
+fn copy_in_csv_keeps_quoted_delimiters_and_newlines() {
+    let day = Value::Date(NaiveDate::from_ymd_opt(2024, 1, 2).unwrap());
+    // frames split inside a quoted field and inside a row
+    let frames = [
+        "1,\"a, \"\"quoted\"\"\n",
+        "name\",2024-01-02\n2,",
+        ",\n3,\"\",2024-01-02",
+    ];
+    assert_eq!(
+        copy_values(&frames, CopyFormat::Csv),
+        [
+            vec![
+                Value::Integer(1),
+                Value::Text("a, \"quoted\"\nname".into()),
+                day.clone(),
+            ],
+            vec![Value::Integer(2), Value::Null, Value::Null],
+            // a quoted empty field is an empty string rather than NULL
+            vec![Value::Integer(3), Value::Text(String::new()), day],
+        ]
+    );
+}
+
+#[test]
+fn copy_in_text_reads_escapes_and_nulls() {
+    let frames = ["1\ttab\\there\\nnew\t\\N\n2\t\\\\N\t2024-01-02\n\\.\n3\tignored\t\\N\n"];
+    assert_eq!(
+        copy_values(&frames, CopyFormat::Text),
+        [
+            vec![
+                Value::Integer(1),
+                Value::Text("tab\there\nnew".into()),
+                Value::Null,
+            ],
+            // an escaped backslash before N is text, not NULL
+            vec![
+                Value::Integer(2),
+                Value::Text("\\N".into()),
+                Value::Date(NaiveDate::from_ymd_opt(2024, 1, 2).unwrap()),
+            ],
+        ]
+    );
+}
+
+#[test]
+fn copy_in_rejects_bad_rows() {
+    let error = |data: &str, format| match copy_in_to_records([data], copy_schema(), format) {
+        Err(PgWireError::UserError(info)) => (info.code().to_string(), info.message().to_string()),
+        _ => panic!("expected {:?} to be rejected", data),
+    };
+
+    let (code, message) = error("1,a\n", CopyFormat::Csv);
+    assert_eq!(code, "22P04");
+    assert!(message.contains("\"day\""), "{}", message);
+
+    let (code, _) = error("1,\"a,2024-01-02\n", CopyFormat::Csv);
+    assert_eq!(code, "22P04");
+
+    let (code, message) = error("x\ta\t\\N\n", CopyFormat::Text);
+    assert_eq!(code, "22P02");
+    assert!(message.contains("\"id\""), "{}", message);
+}
+
+#[test]
+fn copy_in_parser_takes_postgres_options() {
+    let mut parser = CopyInParser::new(copy_schema(), CopyFormat::Csv)
+        .with_delimiter(b'|')
+        .with_null("NULL")
+        .with_header(true);
+    assert!(parser.push(b"id|name|day\r\n1|").unwrap().is_empty());
+    let records = parser.push(b"|NULL\r\n").unwrap();
+    assert_eq!(
+        records[0].values,
+        [Value::Integer(1), Value::Text(String::new()), Value::Null]
+    );
+    assert!(parser.finish().unwrap().is_empty());
+}
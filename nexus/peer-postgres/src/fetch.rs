@@ -0,0 +1,203 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+use peer_cursor::{Record, RecordStream, Schema, SendableStream};
+use pgerror::{sqlstate, PgError, QueryContext};
+use pgwire::error::PgWireResult;
+use pt::peerdb_peers::PostgresConfig;
+use sqlparser::ast::{Expr, Query, Value};
+use tokio_postgres::Client;
+
+use crate::{ast::PostgresAst, schema_from_query, stream::PgRecordStream, with_text_output};
+
+const CURSOR_NAME: &str = "peerdb_fetch";
+
+pub type FetchFuture = Pin<Box<dyn Future<Output = PgWireResult<SendableStream>> + Send>>;
+
+/// Fetches the next chunk of rows of a cursor.
+pub type FetchChunk = Box<dyn Fn() -> FetchFuture + Send + Sync>;
+
+/// Whether `query` is worth fetching `fetch_size` rows at a time, which is not
+/// the case for queries limited to at most one chunk of rows.
+pub fn fetches_in_chunks(query: &Query, fetch_size: u32) -> bool {
+    if fetch_size == 0 {
+        return false;
+    }
+    match &query.limit {
+        Some(Expr::Value(Value::Number(n, _))) => {
+            n.parse::<u64>().map_or(true, |n| n > fetch_size as u64)
+        }
+        _ => true,
+    }
+}
+
+/// Streams the rows of a cursor, fetching the next `fetch_size` rows only
+/// once the previous ones have been handed out.
+pub struct ChunkedRecordStream {
+    // for a cursor on a peer, keeps the cursor's connection open
+    fetch: FetchChunk,
+    schema: Schema,
+    fetch_size: u32,
+    chunk: Option<SendableStream>,
+    chunk_rows: u32,
+    // behind a mutex only to make the stream Sync, it is never contended
+    pending: Option<Mutex<FetchFuture>>,
+}
+
+impl ChunkedRecordStream {
+    /// Streams `first`, then the chunks `fetch` returns until one has fewer
+    /// than `fetch_size` rows.
+    pub fn new(schema: Schema, fetch_size: u32, first: SendableStream, fetch: FetchChunk) -> Self {
+        Self {
+            fetch,
+            schema,
+            fetch_size,
+            chunk: Some(first),
+            chunk_rows: 0,
+            pending: None,
+        }
+    }
+}
+
+impl Stream for ChunkedRecordStream {
+    type Item = PgWireResult<Record>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(pending) = self.pending.as_mut() {
+                let fetched = match pending
+                    .get_mut()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .as_mut()
+                    .poll(cx)
+                {
+                    Poll::Ready(fetched) => fetched,
+                    Poll::Pending => return Poll::Pending,
+                };
+                self.pending = None;
+                match fetched {
+                    Ok(chunk) => {
+                        self.chunk = Some(chunk);
+                        self.chunk_rows = 0;
+                    }
+                    Err(e) => return Poll::Ready(Some(Err(e))),
+                }
+            }
+
+            let Some(chunk) = self.chunk.as_mut() else {
+                return Poll::Ready(None);
+            };
+            match chunk.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(record))) => {
+                    self.chunk_rows += 1;
+                    return Poll::Ready(Some(Ok(record)));
+                }
+                Poll::Ready(Some(Err(e))) => {
+                    self.chunk = None;
+                    return Poll::Ready(Some(Err(e)));
+                }
+                Poll::Ready(None) => {
+                    self.chunk = None;
+                    // a short chunk means the cursor is exhausted
+                    if self.chunk_rows == self.fetch_size {
+                        self.pending = Some(Mutex::new((self.fetch)()));
+                    }
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl RecordStream for ChunkedRecordStream {
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+}
+
+// fetches the next chunk of the cursor declared by `pg_query_chunked`
+fn fetch_from_cursor(
+    client: Arc<Client>,
+    schema: Schema,
+    ctx: QueryContext,
+    fetch_size: u32,
+) -> FetchFuture {
+    let sql = format!("FETCH FORWARD {} FROM {}", fetch_size, CURSOR_NAME);
+    Box::pin(async move {
+        let rows = client
+            .query_raw(&sql, std::iter::empty::<&str>())
+            .await
+            .map_err(|e| {
+                tracing::error!("error fetching from cursor: {}", e);
+                PgError::from_postgres(&e, "error fetching from cursor")
+            })?;
+        Ok(Box::pin(PgRecordStream::new(rows, schema, ctx)) as SendableStream)
+    })
+}
+
+/// Runs `query` through a cursor on a connection of its own, fetching
+/// `fetch_size` rows at a time so that neither the peer nor nexus hold more
+/// than a chunk of the result. The cursor's transaction is never committed,
+/// it ends with the connection once the stream is dropped.
+pub async fn pg_query_chunked(
+    peername: &str,
+    config: &PostgresConfig,
+    query: &Query,
+    fetch_size: u32,
+) -> PgWireResult<SendableStream> {
+    let mut query = query.clone();
+    PostgresAst {
+        peername: Some(peername.to_string()),
+    }
+    .rewrite_query(&mut query);
+    let query = query.to_string();
+
+    let client = postgres_connection::connect_postgres(config)
+        .await
+        .map_err(|e| {
+            tracing::error!("error connecting for chunked query: {}", e);
+            PgError::new(
+                sqlstate::CONNECTION_FAILURE,
+                format!("error connecting for chunked query: {}", e),
+            )
+        })?;
+    let schema = schema_from_query(&client, &query).await.map_err(|e| {
+        tracing::error!("error getting schema: {}", e);
+        PgError::from_postgres(&e, "error getting schema")
+    })?;
+
+    let query = with_text_output(&query, &schema);
+    tracing::info!(
+        "[peer-postgres] fetching {} rows at a time: {}",
+        fetch_size,
+        query
+    );
+    client
+        .batch_execute(&format!(
+            "BEGIN; DECLARE {} NO SCROLL CURSOR FOR {}",
+            CURSOR_NAME, query
+        ))
+        .await
+        .map_err(|e| {
+            tracing::error!("error declaring cursor: {}", e);
+            PgError::from_postgres(&e, "error declaring cursor")
+        })?;
+
+    // the first chunk is fetched right away, so errors are raised before
+    // any row is sent
+    let client = Arc::new(client);
+    let ctx = QueryContext::current(Some(peername));
+    let first = fetch_from_cursor(client.clone(), schema.clone(), ctx.clone(), fetch_size).await?;
+    let fetch: FetchChunk = {
+        let schema = schema.clone();
+        Box::new(move || fetch_from_cursor(client.clone(), schema.clone(), ctx.clone(), fetch_size))
+    };
+    Ok(Box::pin(ChunkedRecordStream::new(
+        schema, fetch_size, first, fetch,
+    )))
+}
@@ -1,16 +1,20 @@
 use std::sync::Arc;
 
 use peer_cursor::{QueryExecutor, QueryOutput, Schema};
+use pgerror::{PgError, QueryContext};
 use pgwire::{
     api::results::{FieldFormat, FieldInfo},
     error::{PgWireError, PgWireResult},
 };
 use pt::peerdb_peers::PostgresConfig;
-use sqlparser::ast::Statement;
-use tokio_postgres::Client;
+use sqlparser::ast::{Ident, Statement};
+use tokio_postgres::{types::Type, Client};
 
 pub mod ast;
+pub mod fetch;
+pub mod parallel;
 pub mod stream;
+pub mod types;
 
 // PostgresQueryExecutor is a QueryExecutor that uses a Postgres database as its
 // backing store.
@@ -29,7 +33,10 @@ impl PostgresQueryExecutor {
     }
 }
 
-async fn schema_from_query(client: &Client, query: &str) -> anyhow::Result<Schema> {
+pub(crate) async fn schema_from_query(
+    client: &Client,
+    query: &str,
+) -> Result<Schema, tokio_postgres::Error> {
     let prepared = client.prepare_typed(query, &[]).await?;
 
     let fields: Vec<FieldInfo> = prepared
@@ -44,6 +51,67 @@ async fn schema_from_query(client: &Client, query: &str) -> anyhow::Result<Schem
     Ok(Arc::new(fields))
 }
 
+/// Whether values of `ty` have to come back in the server's text output.
+/// Binary results carry only the OID of `reg*` values, not the name that
+/// postgres itself would show: the role of a `regrole`, the schema of a
+/// `regnamespace`, and for a `regclass` the relation name, qualified with its
+/// schema unless that is on the peer's search_path.
+fn needs_text_output(ty: &Type) -> bool {
+    matches!(
+        *ty,
+        Type::REGPROC
+            | Type::REGPROCEDURE
+            | Type::REGOPER
+            | Type::REGOPERATOR
+            | Type::REGCLASS
+            | Type::REGTYPE
+            | Type::REGCONFIG
+            | Type::REGDICTIONARY
+            | Type::REGNAMESPACE
+            | Type::REGROLE
+            | Type::REGCOLLATION
+            | Type::REGPROC_ARRAY
+            | Type::REGPROCEDURE_ARRAY
+            | Type::REGOPER_ARRAY
+            | Type::REGOPERATOR_ARRAY
+            | Type::REGCLASS_ARRAY
+            | Type::REGTYPE_ARRAY
+            | Type::REGCONFIG_ARRAY
+            | Type::REGDICTIONARY_ARRAY
+            | Type::REGNAMESPACE_ARRAY
+            | Type::REGROLE_ARRAY
+            | Type::REGCOLLATION_ARRAY
+    )
+}
+
+/// Wraps `query` so that the server casts the columns that need it to text,
+/// keeping the column names of the schema. Other queries are left alone.
+pub(crate) fn with_text_output(query: &str, schema: &Schema) -> String {
+    if !schema.iter().any(|f| needs_text_output(f.datatype())) {
+        return query.to_string();
+    }
+
+    let aliases: Vec<String> = (1..=schema.len()).map(|i| format!("c{}", i)).collect();
+    let columns: Vec<String> = schema
+        .iter()
+        .zip(&aliases)
+        .map(|(field, alias)| {
+            let name = Ident::with_quote('"', field.name().clone());
+            if needs_text_output(field.datatype()) {
+                format!("{}::text AS {}", alias, name)
+            } else {
+                format!("{} AS {}", alias, name)
+            }
+        })
+        .collect();
+    format!(
+        "SELECT {} FROM ({}) AS peerdb_text_output({})",
+        columns.join(", "),
+        query,
+        aliases.join(", ")
+    )
+}
+
 pub async fn pg_execute(
     client: &Client,
     ast: ast::PostgresAst,
@@ -65,9 +133,10 @@ pub async fn pg_execute(
                 .await
                 .map_err(|e| {
                     tracing::error!("error getting schema: {}", e);
-                    PgWireError::ApiError(format!("error getting schema: {}", e).into())
+                    PgError::from_postgres(&e, "error getting schema")
                 })?;
 
+            let rewritten_query = with_text_output(&rewritten_query, &schema);
             tracing::info!("[peer-postgres] rewritten query: {}", rewritten_query);
             // given that there could be a lot of rows returned, we
             // need to use a cursor to stream the rows back to the
@@ -77,26 +146,27 @@ pub async fn pg_execute(
                 .await
                 .map_err(|e| {
                     tracing::error!("error executing query: {}", e);
-                    PgWireError::ApiError(format!("error executing query: {}", e).into())
+                    PgError::from_postgres(&e, "error executing query")
                 })?;
 
             // log that raw query execution has completed
             tracing::info!("[peer-postgres] raw query execution completed");
 
-            let cursor = stream::PgRecordStream::new(stream, schema);
+            let ctx = QueryContext::current(ast.peername.as_deref());
+            let cursor = stream::PgRecordStream::new(stream, schema, ctx);
             Ok(QueryOutput::Stream(Box::pin(cursor)))
         }
         _ => {
             let mut rewritten_stmt = stmt.clone();
             ast.rewrite_statement(&mut rewritten_stmt).map_err(|e| {
                 tracing::error!("error rewriting statement: {}", e);
-                PgWireError::ApiError(format!("error rewriting statement: {}", e).into())
+                PgError::internal(format!("error rewriting statement: {}", e))
             })?;
             let rewritten_query = rewritten_stmt.to_string();
             tracing::info!("[peer-postgres] rewritten statement: {}", rewritten_query);
             let rows_affected = client.execute(&rewritten_query, &[]).await.map_err(|e| {
                 tracing::error!("error executing query: {}", e);
-                PgWireError::ApiError(format!("error executing query: {}", e).into())
+                PgError::from_postgres(&e, "error executing query")
             })?;
             Ok(QueryOutput::AffectedRows(rows_affected as usize))
         }
@@ -110,7 +180,7 @@ pub async fn pg_describe(client: &Client, stmt: &Statement) -> PgWireResult<Opti
                 .await
                 .map_err(|e| {
                     tracing::error!("error getting schema: {}", e);
-                    PgWireError::ApiError(format!("error getting schema: {}", e).into())
+                    PgError::from_postgres(&e, "error getting schema")
                 })?;
             Ok(Some(schema))
         }
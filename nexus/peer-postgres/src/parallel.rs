@@ -0,0 +1,353 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{future::try_join_all, Stream};
+use peer_cursor::{Record, RecordStream, Schema, SendableStream};
+use pgerror::{sqlstate, PgError, QueryContext};
+use pgwire::error::PgWireResult;
+use pt::peerdb_peers::PostgresConfig;
+use sqlparser::ast::{Expr, Ident, Query};
+use tokio_postgres::Client;
+
+use crate::{ast::PostgresAst, schema_from_query, stream::PgRecordStream, with_text_output};
+
+/// Settings for fetching a query's rows as several range partitions, each
+/// on its own connection to the peer.
+#[derive(Debug, Clone)]
+pub struct ParallelFetch {
+    /// Integer column the rows are partitioned on, disabled when empty.
+    pub partition_column: String,
+    /// Number of partitions, and so of concurrent connections.
+    pub parallelism: usize,
+    /// Emit rows in partition column order instead of as they arrive.
+    pub ordered: bool,
+}
+
+impl Default for ParallelFetch {
+    fn default() -> Self {
+        Self {
+            partition_column: String::new(),
+            parallelism: 4,
+            ordered: false,
+        }
+    }
+}
+
+impl ParallelFetch {
+    pub fn is_enabled(&self) -> bool {
+        !self.partition_column.is_empty() && self.parallelism > 1
+    }
+}
+
+/// Splits `[min, max]` into at most `parallelism` inclusive ranges of
+/// (nearly) equal width.
+pub fn partition_ranges(min: i64, max: i64, parallelism: usize) -> Vec<(i64, i64)> {
+    if min > max || parallelism == 0 {
+        return Vec::new();
+    }
+    let span = max as i128 - min as i128 + 1;
+    let step = (span + parallelism as i128 - 1) / parallelism as i128;
+
+    let mut ranges = Vec::with_capacity(parallelism);
+    let mut lo = min as i128;
+    while lo <= max as i128 {
+        let hi = (lo + step - 1).min(max as i128);
+        ranges.push((lo as i64, hi as i64));
+        lo = hi + 1;
+    }
+    ranges
+}
+
+/// Whether `query` can be split into partitions with `fetch` that together
+/// hold the same rows, in the same order. Queries with a LIMIT, OFFSET or
+/// FETCH can't: every partition runs the query on its own, and unless its
+/// order is total the limit picks different rows each time. Nor can ordered
+/// queries, whose order merging the partitions loses, unless they are in
+/// ascending order of the partition column alone and the merge is ordered.
+pub fn can_partition(query: &Query, fetch: &ParallelFetch) -> bool {
+    if query.limit.is_some() || query.offset.is_some() || query.fetch.is_some() {
+        return false;
+    }
+    match query.order_by.as_slice() {
+        [] => true,
+        // ordered merges put the NULL partition last, as ascending orders do
+        [order] => {
+            fetch.ordered
+                && order.asc != Some(false)
+                && order.nulls_first != Some(true)
+                && is_column(&order.expr, &fetch.partition_column)
+        }
+        _ => false,
+    }
+}
+
+// whether `expr` names `column`, which unquoted names do in any case
+fn is_column(expr: &Expr, column: &str) -> bool {
+    let ident = match expr {
+        Expr::Identifier(ident) => ident,
+        Expr::CompoundIdentifier(idents) => match idents.last() {
+            Some(ident) => ident,
+            None => return false,
+        },
+        _ => return false,
+    };
+    match ident.quote_style {
+        Some(_) => ident.value == column,
+        None => ident.value.to_lowercase() == column,
+    }
+}
+
+/// The conditions on `column` selecting the rows of each partition, in
+/// `column` order: ranges splitting `bounds`, the smallest and largest value
+/// of the column or `None` when it has none, and last the rows where it is
+/// NULL, which no range holds. The first and last range are open ended, so
+/// that every other row is in one whatever the bounds.
+pub fn partition_predicates(
+    column: &str,
+    bounds: Option<(i64, i64)>,
+    parallelism: usize,
+) -> Vec<String> {
+    let column = Ident::with_quote('"', column).to_string();
+    let ranges = bounds.map_or_else(Vec::new, |(min, max)| {
+        partition_ranges(min, max, parallelism)
+    });
+    let last = ranges.len().saturating_sub(1);
+    ranges
+        .into_iter()
+        .enumerate()
+        .map(|(i, (lo, hi))| match (i == 0, i == last) {
+            (true, true) => format!("{column} IS NOT NULL"),
+            (true, false) => format!("{column} <= {hi}"),
+            (false, true) => format!("{column} >= {lo}"),
+            (false, false) => format!("{column} BETWEEN {lo} AND {hi}"),
+        })
+        .chain(std::iter::once(format!("{column} IS NULL")))
+        .collect()
+}
+
+struct Partition {
+    // held so the connection lives as long as its rows are being read
+    client: Client,
+    stream: PgRecordStream,
+}
+
+/// Merges the record streams of all partitions of a query. Unordered merges
+/// hand out rows from whichever partition has one ready, ordered merges
+/// drain partitions one after the other in range order.
+pub struct PartitionedRecordStream {
+    schema: Schema,
+    partitions: Vec<Partition>,
+    ordered: bool,
+    next: usize,
+    failed: bool,
+}
+
+impl PartitionedRecordStream {
+    // cancel the queries of the remaining partitions, after one of them failed
+    fn cancel_all(&mut self) {
+        for partition in self.partitions.drain(..) {
+            let token = partition.client.cancel_token();
+            tokio::spawn(async move {
+                if let Err(e) = token
+                    .cancel_query(postgres_connection::tls_connector())
+                    .await
+                {
+                    tracing::warn!("failed to cancel partition query: {}", e);
+                }
+            });
+        }
+        self.failed = true;
+    }
+}
+
+impl Stream for PartitionedRecordStream {
+    type Item = PgWireResult<Record>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.failed {
+            return Poll::Ready(None);
+        }
+
+        let mut polled = 0;
+        while polled < self.partitions.len() {
+            let idx = if self.ordered {
+                0
+            } else {
+                (self.next + polled) % self.partitions.len()
+            };
+            match Pin::new(&mut self.partitions[idx].stream).poll_next(cx) {
+                Poll::Ready(Some(Ok(record))) => {
+                    self.next = idx + 1;
+                    return Poll::Ready(Some(Ok(record)));
+                }
+                Poll::Ready(Some(Err(e))) => {
+                    self.cancel_all();
+                    return Poll::Ready(Some(Err(e)));
+                }
+                Poll::Ready(None) => {
+                    self.partitions.remove(idx);
+                }
+                Poll::Pending if self.ordered => return Poll::Pending,
+                Poll::Pending => polled += 1,
+            }
+        }
+
+        if self.partitions.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl RecordStream for PartitionedRecordStream {
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+}
+
+async fn connect(config: &PostgresConfig) -> PgWireResult<Client> {
+    postgres_connection::connect_postgres(config)
+        .await
+        .map_err(|e| {
+            tracing::error!("error connecting for partitioned query: {}", e);
+            PgError::new(
+                sqlstate::CONNECTION_FAILURE,
+                format!("error connecting for partitioned query: {}", e),
+            )
+            .into()
+        })
+}
+
+// runs `query` on `client` as one of the partitions of a query
+async fn run_partition(
+    client: Client,
+    query: &str,
+    schema: Schema,
+    ctx: QueryContext,
+) -> PgWireResult<Partition> {
+    let query = with_text_output(query, &schema);
+    let rows = client
+        .query_raw(&query, std::iter::empty::<&str>())
+        .await
+        .map_err(|e| {
+            tracing::error!("error executing partition query: {}", e);
+            PgError::from_postgres(&e, "error executing partition query")
+        })?;
+    Ok(Partition {
+        client,
+        stream: PgRecordStream::new(rows, schema, ctx),
+    })
+}
+
+/// Runs `query` against the peer as `fetch.parallelism` range partitions on
+/// `fetch.partition_column`, and one for its NULLs, each on a separate
+/// connection, and merges the results into a single stream. All partitions
+/// read the same snapshot, exported by the connection that takes the
+/// bounds, so together they hold the rows of a single run of the query.
+/// Queries that can't be partitioned, see `can_partition`, run as they are.
+pub async fn pg_query_partitioned(
+    peername: &str,
+    config: &PostgresConfig,
+    query: &Query,
+    fetch: &ParallelFetch,
+) -> PgWireResult<SendableStream> {
+    let mut query = query.clone();
+    PostgresAst {
+        peername: Some(peername.to_string()),
+    }
+    .rewrite_query(&mut query);
+    let partitionable = can_partition(&query, fetch);
+    let query = query.to_string();
+
+    let client = connect(config).await?;
+    let schema = schema_from_query(&client, &query).await.map_err(|e| {
+        tracing::error!("error getting schema: {}", e);
+        PgError::from_postgres(&e, "error getting schema")
+    })?;
+
+    if !partitionable {
+        tracing::info!("[peer-postgres] not partitioning a limited or ordered query");
+        let ctx = QueryContext::current(Some(peername));
+        let partition = run_partition(client, &query, schema.clone(), ctx).await?;
+        return Ok(Box::pin(PartitionedRecordStream {
+            schema,
+            partitions: vec![partition],
+            ordered: fetch.ordered,
+            next: 0,
+            failed: false,
+        }));
+    }
+
+    let export = async {
+        client
+            .batch_execute("BEGIN ISOLATION LEVEL REPEATABLE READ")
+            .await?;
+        client.query_one("SELECT pg_export_snapshot()", &[]).await
+    };
+    let snapshot: String = export
+        .await
+        .map_err(|e| {
+            tracing::error!("error exporting snapshot: {}", e);
+            PgError::from_postgres(&e, "error exporting snapshot")
+        })?
+        .get(0);
+
+    let column = Ident::with_quote('"', fetch.partition_column.clone()).to_string();
+    let bounds_query = format!(
+        "SELECT min({column})::bigint, max({column})::bigint FROM ({query}) AS peerdb_partition_bounds"
+    );
+    let bounds = client.query_one(&bounds_query, &[]).await.map_err(|e| {
+        tracing::error!("error getting partition bounds: {}", e);
+        PgError::from_postgres(&e, "error getting partition bounds")
+    })?;
+    let bounds = match (
+        bounds.get::<_, Option<i64>>(0),
+        bounds.get::<_, Option<i64>>(1),
+    ) {
+        (Some(min), Some(max)) => Some((min, max)),
+        _ => None,
+    };
+    let predicates = partition_predicates(&fetch.partition_column, bounds, fetch.parallelism);
+    tracing::info!(
+        "[peer-postgres] fetching {} partitions on {}",
+        predicates.len(),
+        column
+    );
+
+    let order_by = if fetch.ordered {
+        format!(" ORDER BY {column}")
+    } else {
+        String::new()
+    };
+    let import =
+        format!("BEGIN ISOLATION LEVEL REPEATABLE READ; SET TRANSACTION SNAPSHOT '{snapshot}'");
+    let partitions = try_join_all(predicates.into_iter().map(|predicate| {
+        let partition_query =
+            format!("SELECT * FROM ({query}) AS peerdb_partition WHERE {predicate}{order_by}");
+        let schema = schema.clone();
+        let ctx = QueryContext::current(Some(peername));
+        let import = &import;
+        async move {
+            let client = connect(config).await?;
+            client.batch_execute(import).await.map_err(|e| {
+                tracing::error!("error importing snapshot: {}", e);
+                PgError::from_postgres(&e, "error importing snapshot")
+            })?;
+            run_partition(client, &partition_query, schema, ctx).await
+        }
+    }))
+    .await?;
+    // the snapshot only has to be exported until every partition imported it
+    drop(client);
+
+    Ok(Box::pin(PartitionedRecordStream {
+        schema,
+        partitions,
+        ordered: fetch.ordered,
+        next: 0,
+        failed: false,
+    }))
+}
@@ -1,256 +1,752 @@
-use bytes::Bytes;
-use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use bytes::{Buf, Bytes, BytesMut};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use futures::Stream;
 use peer_cursor::{Record, RecordStream, Schema};
-use pgwire::error::{PgWireError, PgWireResult};
+use pgerror::{sqlstate, with_query_context, PgError, QueryContext};
+use pgwire::{
+    api::results::FieldInfo,
+    error::{PgWireError, PgWireResult},
+};
 use postgres_inet::MaskedIpAddr;
 use rust_decimal::Decimal;
 use std::{
+    error::Error,
+    ops::Range,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
 };
-use tokio_postgres::{types::Type, Row, RowStream};
+use tokio_postgres::{
+    types::{FromSql, Kind, Type},
+    Column, Row, RowStream,
+};
 use uuid::Uuid;
-use value::{array::ArrayValue, Value};
+use value::{
+    array::ArrayValue,
+    geometry::{Geometry, Point},
+    interval::Interval,
+    kind::pg_type_to_value_kind,
+    range,
+    timetz::TimeTz,
+    TypeTag, Value,
+};
+
+use crate::types::{ColumnTypeResolver, TypeCache, TypeMetadata, TypeResolver};
+
+/// Tells, from its name, whether an oid column references large objects.
+pub type LargeObjectColumns = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
 pub struct PgRecordStream {
     row_stream: Pin<Box<RowStream>>,
     schema: Schema,
+    plan: Option<ConversionPlan>,
+    types: TypeCache,
+    circles_as_json: bool,
+    large_objects: Option<LargeObjectColumns>,
+    ctx: QueryContext,
 }
 
 impl PgRecordStream {
-    pub fn new(row_stream: RowStream, schema: Schema) -> Self {
+    /// `ctx` identifies the statement in errors raised while streaming, as
+    /// those are sent after the statement's own handling has returned.
+    pub fn new(row_stream: RowStream, schema: Schema, ctx: QueryContext) -> Self {
         Self {
             row_stream: Box::pin(row_stream),
             schema,
+            plan: None,
+            types: TypeCache::default(),
+            circles_as_json: false,
+            large_objects: None,
+            ctx,
         }
     }
+
+    /// Decodes circles into JSON rather than their text, see
+    /// `ConversionPlan::with_circles_as_json`. The schema describes those
+    /// columns as json.
+    pub fn with_circles_as_json(mut self) -> Self {
+        self.circles_as_json = true;
+        let fields = self
+            .schema
+            .iter()
+            .map(|field| match *field.datatype() {
+                Type::CIRCLE => FieldInfo::new(
+                    field.name().clone(),
+                    *field.table_id(),
+                    *field.column_id(),
+                    Type::JSON,
+                    *field.format(),
+                ),
+                _ => field.clone(),
+            })
+            .collect();
+        self.schema = Arc::new(fields);
+        self
+    }
+
+    /// Decodes the oid columns `is_large_object` picks as large objects, see
+    /// `ConversionPlan::with_large_objects`.
+    pub fn with_large_objects(mut self, is_large_object: LargeObjectColumns) -> Self {
+        self.large_objects = Some(is_large_object);
+        self
+    }
+
+    fn stream_error(&self, err: PgWireError) -> PgWireError {
+        tracing::error!(
+            query_id = %self.ctx.query_id,
+            peer = ?self.ctx.peer,
+            "error streaming rows: {}",
+            err
+        );
+        with_query_context(err, &self.ctx)
+    }
 }
 
-fn values_from_row(row: &Row) -> Vec<Value> {
-    (0..row.len())
-        .map(|i| {
-            let col_type = row.columns()[i].type_();
-            match col_type {
-                &Type::BOOL => row
-                    .get::<_, Option<bool>>(i)
-                    .map(Value::Bool)
-                    .unwrap_or(Value::Null),
-                &Type::CHAR => {
-                    let ch: Option<i8> = row.get(i);
-                    ch.map(|c| char::from_u32(c as u32).unwrap_or('\0'))
-                        .map(Value::Char)
-                        .unwrap_or(Value::Null)
-                }
-                &Type::VARCHAR | &Type::TEXT | &Type::BPCHAR => {
-                    let s: Option<String> = row.get(i);
-                    s.map(Value::Text).unwrap_or(Value::Null)
-                }
-                &Type::VARCHAR_ARRAY | &Type::BPCHAR_ARRAY => {
-                    let s: Option<Vec<String>> = row.get(i);
-                    s.map(ArrayValue::VarChar)
-                        .map(Value::Array)
-                        .unwrap_or(Value::Null)
-                }
-                &Type::NAME
-                | &Type::REGNAMESPACE
-                | &Type::REGPROC
-                | &Type::REGPROCEDURE
-                | &Type::REGOPER
-                | &Type::REGOPERATOR
-                | &Type::REGCLASS
-                | &Type::REGTYPE
-                | &Type::REGCONFIG
-                | &Type::REGDICTIONARY
-                | &Type::REGROLE
-                | &Type::REGCOLLATION => {
-                    let s: Option<String> = row.get(i);
-                    s.map(Value::Text).unwrap_or(Value::Null)
-                }
-                &Type::NAME_ARRAY
-                | &Type::REGNAMESPACE_ARRAY
-                | &Type::REGPROCEDURE_ARRAY
-                | &Type::REGOPER_ARRAY
-                | &Type::REGOPERATOR_ARRAY
-                | &Type::REGCLASS_ARRAY
-                | &Type::REGTYPE_ARRAY
-                | &Type::REGCONFIG_ARRAY
-                | &Type::REGDICTIONARY_ARRAY
-                | &Type::REGROLE_ARRAY
-                | &Type::REGCOLLATION_ARRAY => {
-                    let s: Option<Vec<String>> = row.get(i);
-                    s.map(ArrayValue::VarChar)
-                        .map(Value::Array)
-                        .unwrap_or(Value::Null)
-                }
-                &Type::INT2 => {
-                    let int: Option<i16> = row.get(i);
-                    int.map(Value::SmallInt).unwrap_or(Value::Null)
-                }
-                &Type::INT2_ARRAY => {
-                    let int: Option<Vec<i16>> = row.get(i);
-                    int.map(ArrayValue::SmallInt)
-                        .map(Value::Array)
-                        .unwrap_or(Value::Null)
-                }
-                &Type::INT4
-                | &Type::TID
-                | &Type::XID
-                | &Type::CID
-                | &Type::PG_NDISTINCT
-                | &Type::PG_DEPENDENCIES => {
-                    let int: Option<i32> = row.get(i);
-                    int.map(Value::Integer).unwrap_or(Value::Null)
-                }
-                &Type::INT4_ARRAY
-                | &Type::TID_ARRAY
-                | &Type::XID_ARRAY
-                | &Type::CID_ARRAY
-                | &Type::OID_VECTOR
-                | &Type::OID_VECTOR_ARRAY => {
-                    let int: Option<Vec<i32>> = row.get(i);
-                    int.map(ArrayValue::Integer)
-                        .map(Value::Array)
-                        .unwrap_or(Value::Null)
-                }
-                &Type::INT8 => {
-                    let big_int: Option<i64> = row.get(i);
-                    big_int.map(Value::BigInt).unwrap_or(Value::Null)
-                }
-                &Type::INT8_ARRAY => {
-                    let big_int: Option<Vec<i64>> = row.get(i);
-                    big_int
-                        .map(ArrayValue::BigInt)
-                        .map(Value::Array)
-                        .unwrap_or(Value::Null)
-                }
-                &Type::OID => {
-                    let oid: Option<u32> = row.get(i);
-                    oid.map(Value::Oid).unwrap_or(Value::Null)
-                }
-                &Type::FLOAT4 => {
-                    let float: Option<f32> = row.get(i);
-                    float.map(Value::Float).unwrap_or(Value::Null)
-                }
-                &Type::FLOAT4_ARRAY => {
-                    let float: Option<Vec<f32>> = row.get(i);
-                    float
-                        .map(ArrayValue::Float)
-                        .map(Value::Array)
-                        .unwrap_or(Value::Null)
-                }
-                &Type::FLOAT8 => {
-                    let float: Option<f64> = row.get(i);
-                    float.map(Value::Double).unwrap_or(Value::Null)
-                }
-                &Type::FLOAT8_ARRAY => {
-                    let float: Option<Vec<f64>> = row.get(i);
-                    float
-                        .map(ArrayValue::Double)
-                        .map(Value::Array)
-                        .unwrap_or(Value::Null)
-                }
-                &Type::NUMERIC => {
-                    let numeric: Option<Decimal> = row.get(i);
-                    numeric.map(Value::Numeric).unwrap_or(Value::Null)
-                }
-                &Type::NUMERIC_ARRAY => {
-                    let numeric: Option<Vec<String>> = row.get(i);
-                    numeric
-                        .map(ArrayValue::Numeric)
-                        .map(Value::Array)
-                        .unwrap_or(Value::Null)
-                }
-                &Type::BYTEA => {
-                    let bytes: Option<&[u8]> = row.get(i);
-                    let bytes = bytes.map(Bytes::copy_from_slice);
-                    bytes.map(Value::VarBinary).unwrap_or(Value::Null)
-                }
-                &Type::BYTEA_ARRAY => {
-                    let bytes: Option<Vec<&[u8]>> = row.get(i);
-                    let bytes = bytes.map(|bytes| {
-                        bytes
-                            .iter()
-                            .map(|bytes| Bytes::copy_from_slice(bytes))
-                            .collect()
-                    });
+/// Text of a text-like or json column borrowed straight from the row buffer,
+/// without decoding it into a `String` or a `serde_json::Value`.
+struct RawText<'a>(&'a [u8]);
+
+impl<'a> FromSql<'a> for RawText<'a> {
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        // binary jsonb is the json text prefixed with a version byte
+        let raw = if *ty == Type::JSONB {
+            match raw.split_first() {
+                Some((&1, rest)) => rest,
+                _ => return Err("unsupported JSONB encoding version".into()),
+            }
+        } else {
+            raw
+        };
+        std::str::from_utf8(raw)?;
+        Ok(RawText(raw))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <&str as FromSql>::accepts(ty)
+            || matches!(*ty, Type::JSON | Type::JSONB | Type::PG_NODE_TREE)
+    }
+}
+
+/// Label of a value of an enum defined on the peer, which binary output sends
+/// as its text.
+struct EnumLabel(String);
+
+impl<'a> FromSql<'a> for EnumLabel {
+    fn from_sql(_: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(EnumLabel(std::str::from_utf8(raw)?.to_string()))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(ty.kind(), Kind::Enum(_))
+    }
+}
+
+/// Result of a function returning `void`, which postgres sends as an empty
+/// value rather than a NULL.
+struct Void;
+
+impl<'a> FromSql<'a> for Void {
+    fn from_sql(_: &Type, _: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(Void)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::VOID
+    }
+}
+
+/// A circle in binary output: the x and y of its center, then its radius.
+struct Circle(Geometry);
+
+impl<'a> FromSql<'a> for Circle {
+    fn from_sql(_: &Type, mut raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        if raw.len() != 24 {
+            return Err("invalid circle length".into());
+        }
+        let center = Point {
+            x: raw.get_f64(),
+            y: raw.get_f64(),
+        };
+        Ok(Circle(Geometry::Circle {
+            center,
+            radius: raw.get_f64(),
+        }))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::CIRCLE
+    }
+}
+
+/// A timestamp as postgres sends it, in microseconds from 2000-01-01, which
+/// reaches past the years chrono holds: postgres goes up to 294276 and has
+/// infinite timestamps.
+struct RawTimestamp(i64);
+
+// microseconds from the unix epoch to 2000-01-01
+const PG_EPOCH_MICROS: i64 = 946_684_800_000_000;
+const MICROS_PER_DAY: i64 = 86_400_000_000;
+
+impl RawTimestamp {
+    fn to_utc(&self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp_micros(self.0.checked_add(PG_EPOCH_MICROS)?)
+    }
+
+    /// The timestamp as postgres writes it in the ISO style, with a `+00`
+    /// offset when `with_zone`.
+    fn text(&self, with_zone: bool) -> String {
+        match self.0 {
+            i64::MAX => return "infinity".to_string(),
+            i64::MIN => return "-infinity".to_string(),
+            _ => {}
+        }
+        let (year, month, day) =
+            civil_from_days(self.0.div_euclid(MICROS_PER_DAY) + PG_EPOCH_MICROS / MICROS_PER_DAY);
+        let micros = self.0.rem_euclid(MICROS_PER_DAY);
+        let mut out = format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            if year > 0 { year } else { 1 - year },
+            month,
+            day,
+            micros / 3_600_000_000,
+            micros / 60_000_000 % 60,
+            micros / 1_000_000 % 60,
+        );
+        let fraction = micros % 1_000_000;
+        if fraction != 0 {
+            let digits = format!(".{:06}", fraction);
+            out.push_str(digits.trim_end_matches('0'));
+        }
+        if with_zone {
+            out.push_str("+00");
+        }
+        if year <= 0 {
+            out.push_str(" BC");
+        }
+        out
+    }
+}
+
+// the proleptic gregorian year, month and day of a count of days from the
+// unix epoch
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+impl<'a> FromSql<'a> for RawTimestamp {
+    fn from_sql(_: &Type, mut raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        if raw.len() != 8 {
+            return Err("invalid timestamp length".into());
+        }
+        Ok(RawTimestamp(raw.get_i64()))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(*ty, Type::TIMESTAMP | Type::TIMESTAMPTZ)
+    }
+}
+
+/// What a cell read as text becomes once its row is decoded.
+#[derive(Clone, Copy)]
+enum TextCell {
+    Text,
+    Json,
+    JsonB,
+}
+
+/// Decodes the columns of a single row. Text-like cells are copied into a
+/// single buffer per row and handed out as slices of it once the row is done,
+/// instead of allocating a String for every cell.
+struct RowDecoder<'r> {
+    row: &'r Row,
+    text_buf: BytesMut,
+    text_cells: Vec<(usize, Range<usize>, TextCell)>,
+    circles_as_json: bool,
+}
+
+impl<'r> RowDecoder<'r> {
+    fn new(row: &'r Row, circles_as_json: bool) -> Self {
+        Self {
+            row,
+            text_buf: BytesMut::new(),
+            text_cells: Vec::new(),
+            circles_as_json,
+        }
+    }
+
+    fn read_text(&mut self, i: usize, cell: TextCell) -> Result<Value, tokio_postgres::Error> {
+        let raw: Option<RawText> = self.row.try_get(i)?;
+        if let Some(RawText(raw)) = raw {
+            let start = self.text_buf.len();
+            self.text_buf.extend_from_slice(raw);
+            self.text_cells.push((i, start..self.text_buf.len(), cell));
+        }
+        // placeholder, replaced once the row buffer is frozen
+        Ok(Value::Null)
+    }
+
+    fn decode(
+        &mut self,
+        i: usize,
+        col_type: &Type,
+        metadata: &TypeMetadata,
+    ) -> Result<Value, tokio_postgres::Error> {
+        match metadata.kind {
+            Kind::Enum(_) => Ok(self
+                .row
+                .try_get::<_, Option<EnumLabel>>(i)?
+                .map(|EnumLabel(label)| Value::Enum(label))
+                .unwrap_or(Value::Null)),
+            _ => self.decode_column(i, col_type),
+        }
+    }
+
+    fn decode_column(&mut self, i: usize, col_type: &Type) -> Result<Value, tokio_postgres::Error> {
+        let row = self.row;
+        Ok(match pg_type_to_value_kind(col_type) {
+            TypeTag::Bool => row
+                .try_get::<_, Option<bool>>(i)?
+                .map(Value::Bool)
+                .unwrap_or(Value::Null),
+            TypeTag::Char => {
+                let ch: Option<i8> = row.try_get(i)?;
+                // the byte as is, which for bytes past ASCII is the
+                // character of the same value
+                ch.map(|c| char::from(c as u8))
+                    .map(Value::Char)
+                    .unwrap_or(Value::Null)
+            }
+            TypeTag::SmallInt => {
+                let int: Option<i16> = row.try_get(i)?;
+                int.map(Value::SmallInt).unwrap_or(Value::Null)
+            }
+            TypeTag::Integer => {
+                let int: Option<i32> = row.try_get(i)?;
+                int.map(Value::Integer).unwrap_or(Value::Null)
+            }
+            TypeTag::BigInt => {
+                let big_int: Option<i64> = row.try_get(i)?;
+                big_int.map(Value::BigInt).unwrap_or(Value::Null)
+            }
+            TypeTag::Oid => {
+                let oid: Option<u32> = row.try_get(i)?;
+                oid.map(Value::Oid).unwrap_or(Value::Null)
+            }
+            TypeTag::Float => {
+                let float: Option<f32> = row.try_get(i)?;
+                float.map(Value::Float).unwrap_or(Value::Null)
+            }
+            TypeTag::Double => {
+                let float: Option<f64> = row.try_get(i)?;
+                float.map(Value::Double).unwrap_or(Value::Null)
+            }
+            TypeTag::Numeric => {
+                let numeric: Option<Decimal> = row.try_get(i)?;
+                numeric.map(Value::Numeric).unwrap_or(Value::Null)
+            }
+            TypeTag::VarBinary => {
+                let bytes: Option<&[u8]> = row.try_get(i)?;
+                let bytes = bytes.map(Bytes::copy_from_slice);
+                bytes.map(Value::VarBinary).unwrap_or(Value::Null)
+            }
+            TypeTag::Json => self.read_text(i, TextCell::Json)?,
+            TypeTag::JsonB => self.read_text(i, TextCell::JsonB)?,
+            TypeTag::Uuid => {
+                let uuid: Option<Uuid> = row.try_get(i)?;
+                uuid.map(Value::Uuid).unwrap_or(Value::Null)
+            }
+            TypeTag::IpAddr => {
+                let s: Option<MaskedIpAddr> = row.try_get(i)?;
+                s.map(Value::IpAddr).unwrap_or(Value::Null)
+            }
+            // timestamps past the years chrono holds are kept as their text,
+            // like values of types nexus doesn't decode, rather than failing
+            // the row
+            TypeTag::PostgresTimestamp => {
+                row.try_get::<_, Option<RawTimestamp>>(i)?
+                    .map_or(Value::Null, |ts| match ts.to_utc() {
+                        Some(dt) => Value::postgres_timestamp(dt.naive_utc()),
+                        None => Value::Text(ts.text(false)),
+                    })
+            }
+            TypeTag::TimestampWithTimeZone => {
+                row.try_get::<_, Option<RawTimestamp>>(i)?
+                    .map_or(Value::Null, |ts| match ts.to_utc() {
+                        Some(dt) => Value::TimestampWithTimeZone(dt),
+                        None => Value::Text(ts.text(true)),
+                    })
+            }
+            TypeTag::Date => {
+                let t: Option<NaiveDate> = row.try_get(i)?;
+                t.map(Value::Date).unwrap_or(Value::Null)
+            }
+            TypeTag::Time => {
+                let t: Option<NaiveTime> = row.try_get(i)?;
+                t.map(Value::Time).unwrap_or(Value::Null)
+            }
+            TypeTag::TimeWithTimeZone => {
+                let t: Option<NaiveTime> = row.try_get(i)?;
+                t.map(Value::TimeWithTimeZone).unwrap_or(Value::Null)
+            }
+            TypeTag::Interval => {
+                let iv: Option<Interval> = row.try_get(i)?;
+                iv.map(Value::Interval).unwrap_or(Value::Null)
+            }
+            TypeTag::Array => self.decode_array(i, col_type)?,
+            TypeTag::Range => row
+                .try_get::<_, Option<range::Range>>(i)?
+                .map_or(Value::Null, Value::range),
+            // enums are read by `decode`, which knows the peer's types
+            _ => self.decode_text(i, col_type)?,
+        })
+    }
+
+    /// Reads a column whose values are kept as text.
+    fn decode_text(&mut self, i: usize, col_type: &Type) -> Result<Value, tokio_postgres::Error> {
+        let row = self.row;
+        Ok(match col_type {
+            // pg_node_tree is among these, as the serialized tree is sent as
+            // text in either format
+            ty if uses_text_buffer(ty) => self.read_text(i, TextCell::Text)?,
+            // queries run by `pg_execute` get these as text, see
+            // `with_text_output`, as their binary form is just the OID
+            &Type::REGNAMESPACE
+            | &Type::REGPROC
+            | &Type::REGPROCEDURE
+            | &Type::REGOPER
+            | &Type::REGOPERATOR
+            | &Type::REGCLASS
+            | &Type::REGTYPE
+            | &Type::REGCONFIG
+            | &Type::REGDICTIONARY
+            | &Type::REGROLE
+            | &Type::REGCOLLATION => {
+                let s: Option<String> = row.try_get(i)?;
+                s.map(Value::Text).unwrap_or(Value::Null)
+            }
+            // ahead of the geometric types, which take circles as text
+            &Type::CIRCLE if self.circles_as_json => row
+                .try_get::<_, Option<Circle>>(i)?
+                .and_then(|Circle(circle)| circle.to_json())
+                .map_or(Value::Null, Value::Json),
+            &Type::POINT
+            | &Type::POINT_ARRAY
+            | &Type::LINE
+            | &Type::LINE_ARRAY
+            | &Type::LSEG
+            | &Type::LSEG_ARRAY
+            | &Type::BOX
+            | &Type::BOX_ARRAY
+            | &Type::POLYGON
+            | &Type::POLYGON_ARRAY
+            | &Type::CIRCLE
+            | &Type::CIRCLE_ARRAY => Value::Text(row.try_get(i)?),
+            &Type::ANY => Value::Text(row.try_get(i)?),
+            // empty text, as postgres writes it, under the void type of the
+            // column
+            &Type::VOID => row
+                .try_get::<_, Option<Void>>(i)?
+                .map_or(Value::Null, |Void| Value::Text(String::new())),
+            _ => read_unsupported(row, i, col_type),
+        })
+    }
+
+    fn decode_array(&mut self, i: usize, col_type: &Type) -> Result<Value, tokio_postgres::Error> {
+        let row = self.row;
+        Ok(match col_type {
+            &Type::VARCHAR_ARRAY
+            | &Type::BPCHAR_ARRAY
+            | &Type::NAME_ARRAY
+            | &Type::REGNAMESPACE_ARRAY
+            | &Type::REGPROCEDURE_ARRAY
+            | &Type::REGOPER_ARRAY
+            | &Type::REGOPERATOR_ARRAY
+            | &Type::REGCLASS_ARRAY
+            | &Type::REGTYPE_ARRAY
+            | &Type::REGCONFIG_ARRAY
+            | &Type::REGDICTIONARY_ARRAY
+            | &Type::REGROLE_ARRAY
+            | &Type::REGCOLLATION_ARRAY
+            | &Type::ANYARRAY => {
+                let s: Option<Vec<String>> = row.try_get(i)?;
+                s.map(ArrayValue::VarChar)
+                    .map(Value::Array)
+                    .unwrap_or(Value::Null)
+            }
+            &Type::BOOL_ARRAY => {
+                let b: Option<Vec<Option<bool>>> = row.try_get(i)?;
+                b.map(ArrayValue::Bool)
+                    .map(Value::Array)
+                    .unwrap_or(Value::Null)
+            }
+            &Type::INT2_ARRAY => {
+                let int: Option<Vec<i16>> = row.try_get(i)?;
+                int.map(ArrayValue::SmallInt)
+                    .map(Value::Array)
+                    .unwrap_or(Value::Null)
+            }
+            &Type::INT4_ARRAY
+            | &Type::TID_ARRAY
+            | &Type::XID_ARRAY
+            | &Type::CID_ARRAY
+            | &Type::OID_VECTOR_ARRAY => {
+                let int: Option<Vec<i32>> = row.try_get(i)?;
+                int.map(ArrayValue::Integer)
+                    .map(Value::Array)
+                    .unwrap_or(Value::Null)
+            }
+            &Type::OID_ARRAY | &Type::OID_VECTOR => {
+                let oids: Option<Vec<u32>> = row.try_get(i)?;
+                oids.map(ArrayValue::Oid)
+                    .map(Value::Array)
+                    .unwrap_or(Value::Null)
+            }
+            &Type::INT8_ARRAY => {
+                let big_int: Option<Vec<i64>> = row.try_get(i)?;
+                big_int
+                    .map(ArrayValue::BigInt)
+                    .map(Value::Array)
+                    .unwrap_or(Value::Null)
+            }
+            &Type::FLOAT4_ARRAY => {
+                let float: Option<Vec<f32>> = row.try_get(i)?;
+                float
+                    .map(ArrayValue::Float)
+                    .map(Value::Array)
+                    .unwrap_or(Value::Null)
+            }
+            &Type::FLOAT8_ARRAY => {
+                let float: Option<Vec<f64>> = row.try_get(i)?;
+                float
+                    .map(ArrayValue::Double)
+                    .map(Value::Array)
+                    .unwrap_or(Value::Null)
+            }
+            &Type::NUMERIC_ARRAY => {
+                let numeric: Option<Vec<String>> = row.try_get(i)?;
+                numeric
+                    .map(ArrayValue::Numeric)
+                    .map(Value::Array)
+                    .unwrap_or(Value::Null)
+            }
+            &Type::BYTEA_ARRAY => {
+                let bytes: Option<Vec<&[u8]>> = row.try_get(i)?;
+                let bytes = bytes.map(|bytes| {
                     bytes
-                        .map(ArrayValue::VarBinary)
-                        .map(Value::Array)
-                        .unwrap_or(Value::Null)
-                }
-                &Type::JSON | &Type::JSONB => {
-                    let jsonb: Option<serde_json::Value> = row.get(i);
-                    jsonb.map(Value::JsonB).unwrap_or(Value::Null)
-                }
-                &Type::UUID => {
-                    let uuid: Option<Uuid> = row.get(i);
-                    uuid.map(Value::Uuid).unwrap_or(Value::Null)
-                }
-                &Type::INET | &Type::CIDR => {
-                    let s: Option<MaskedIpAddr> = row.get(i);
-                    s.map(Value::IpAddr).unwrap_or(Value::Null)
-                }
-                &Type::POINT
-                | &Type::POINT_ARRAY
-                | &Type::LINE
-                | &Type::LINE_ARRAY
-                | &Type::LSEG
-                | &Type::LSEG_ARRAY
-                | &Type::BOX
-                | &Type::BOX_ARRAY
-                | &Type::POLYGON
-                | &Type::POLYGON_ARRAY
-                | &Type::CIRCLE
-                | &Type::CIRCLE_ARRAY => Value::Text(row.get(i)),
-
-                &Type::TIMESTAMP => {
-                    let dt_utc: Option<NaiveDateTime> = row.get(i);
-                    dt_utc.map(Value::postgres_timestamp).unwrap_or(Value::Null)
-                }
-                &Type::TIMESTAMPTZ => {
-                    let dt_utc: Option<DateTime<Utc>> = row.get(i);
-                    dt_utc
-                        .map(Value::TimestampWithTimeZone)
-                        .unwrap_or(Value::Null)
-                }
-                &Type::DATE => {
-                    let t: Option<NaiveDate> = row.get(i);
-                    t.map(Value::Date).unwrap_or(Value::Null)
-                }
-                &Type::TIME => {
-                    let t: Option<NaiveTime> = row.get(i);
-                    t.map(Value::Time).unwrap_or(Value::Null)
-                }
-                &Type::TIMETZ => {
-                    let t: Option<NaiveTime> = row.get(i);
-                    t.map(Value::TimeWithTimeZone).unwrap_or(Value::Null)
-                }
-                &Type::INTERVAL => {
-                    let iv: Option<String> = row.get(i);
-                    iv.map(Value::Text).unwrap_or(Value::Null)
-                }
-                &Type::ANY => Value::Text(row.get(i)),
-                &Type::ANYARRAY => {
-                    let s: Option<Vec<String>> = row.get(i);
-                    s.map(ArrayValue::VarChar)
-                        .map(Value::Array)
-                        .unwrap_or(Value::Null)
-                }
-                &Type::VOID => Value::Null,
-                _ => {
-                    tracing::warn!("unsupported type: {:?}, casting as string", col_type);
-                    let s: Result<Option<String>, tokio_postgres::Error> = row.try_get(i);
-                    match s {
-                        Ok(s) => s.map(Value::Text).unwrap_or(Value::Null),
-                        Err(e) => {
-                            tracing::warn!("failed to read column as string: {}", e);
-                            Value::Null
-                        }
-                    }
-                }
+                        .iter()
+                        .map(|bytes| Bytes::copy_from_slice(bytes))
+                        .collect()
+                });
+                bytes
+                    .map(ArrayValue::VarBinary)
+                    .map(Value::Array)
+                    .unwrap_or(Value::Null)
+            }
+            &Type::TIME_ARRAY => {
+                let t: Option<Vec<Option<NaiveTime>>> = row.try_get(i)?;
+                t.map(ArrayValue::Time)
+                    .map(Value::Array)
+                    .unwrap_or(Value::Null)
+            }
+            &Type::INTERVAL_ARRAY => {
+                let iv: Option<Vec<Option<Interval>>> = row.try_get(i)?;
+                iv.map(ArrayValue::Interval)
+                    .map(Value::Array)
+                    .unwrap_or(Value::Null)
+            }
+            &Type::TIMETZ_ARRAY => {
+                let t: Option<Vec<Option<TimeTz>>> = row.try_get(i)?;
+                t.map(ArrayValue::TimeTz)
+                    .map(Value::Array)
+                    .unwrap_or(Value::Null)
             }
+            _ => read_unsupported(row, i, col_type),
         })
-        .collect()
+    }
+
+    fn finish(self, mut values: Vec<Value>) -> Vec<Value> {
+        let text_buf = self.text_buf.freeze();
+        for (i, range, cell) in self.text_cells {
+            let bytes = text_buf.slice(range);
+            values[i] = match cell {
+                TextCell::Text => Value::TextBytes(bytes),
+                // a JSON null, unlike SQL NULL which has no cell at all
+                TextCell::Json if is_json_null(&bytes) => Value::Json(serde_json::Value::Null),
+                TextCell::JsonB if is_json_null(&bytes) => Value::JsonB(serde_json::Value::Null),
+                TextCell::Json => Value::JsonText(bytes),
+                TextCell::JsonB => Value::JsonBText(bytes),
+            };
+        }
+        values
+    }
+}
+
+/// Whether cells of `ty` are read into the row's text buffer, see
+/// `RowDecoder::read_text`, which leaves them for `RowDecoder::finish`.
+fn uses_text_buffer(ty: &Type) -> bool {
+    matches!(
+        *ty,
+        Type::VARCHAR | Type::TEXT | Type::BPCHAR | Type::NAME | Type::PG_NODE_TREE
+    ) || matches!(pg_type_to_value_kind(ty), TypeTag::Json | TypeTag::JsonB)
+}
+
+fn is_json_null(text: &[u8]) -> bool {
+    std::str::from_utf8(text).map(str::trim) == Ok("null")
+}
+
+/// Reads a column of a type nexus does not decode yet as its text, or NULL
+/// if that fails too.
+fn read_unsupported(row: &Row, i: usize, col_type: &Type) -> Value {
+    tracing::warn!("unsupported type: {:?}, casting as string", col_type);
+    let s: Result<Option<String>, tokio_postgres::Error> = row.try_get(i);
+    match s {
+        Ok(s) => s.map(Value::Text).unwrap_or(Value::Null),
+        Err(e) => {
+            tracing::warn!("failed to read column as string: {}", e);
+            Value::Null
+        }
+    }
+}
+
+/// Column types of a result set, resolved once and shared by every row that
+/// is converted with it.
+pub struct ConversionPlan {
+    types: Vec<Type>,
+    metadata: Vec<Arc<TypeMetadata>>,
+    column_major: bool,
+    circles_as_json: bool,
+    // whether each column holds large objects, empty when none do
+    large_objects: Vec<bool>,
+}
+
+impl ConversionPlan {
+    pub fn new(columns: &[Column]) -> Self {
+        Self::with_type_cache(columns, &mut TypeCache::default())
+    }
+
+    /// Plans the conversion of `columns`, resolving their types through
+    /// `types`.
+    pub fn with_type_cache(columns: &[Column], types: &mut TypeCache) -> Self {
+        let metadata = columns.iter().map(|c| types.get(c.type_())).collect();
+        let types: Vec<Type> = columns.iter().map(|c| c.type_().clone()).collect();
+        // text-like cells go through a buffer per row, so only tables without
+        // them can be converted a column at a time.
+        let column_major = types.len() > 1 && !types.iter().any(uses_text_buffer);
+        Self {
+            types,
+            metadata,
+            column_major,
+            circles_as_json: false,
+            large_objects: Vec::new(),
+        }
+    }
+
+    /// Decodes circles into `Value::Json` objects like
+    /// `{"center": {"x": 1, "y": 2}, "radius": 3}` instead of their text.
+    /// Nexus itself never turns this on, so that its clients get circles as
+    /// postgres writes them; it is for callers converting rows into records
+    /// for destinations without a circle type.
+    pub fn with_circles_as_json(mut self) -> Self {
+        self.circles_as_json = true;
+        self
+    }
+
+    /// Decodes the oid columns among `columns` whose name `is_large_object`
+    /// accepts into `Value::LargeObject` instead of `Value::Oid`. Columns of
+    /// other types are left alone whatever their name.
+    pub fn with_large_objects(
+        mut self,
+        columns: &[Column],
+        is_large_object: impl Fn(&str) -> bool,
+    ) -> Self {
+        self.large_objects = columns
+            .iter()
+            .map(|c| *c.type_() == Type::OID && is_large_object(c.name()))
+            .collect();
+        self
+    }
+
+    // the value of column `i` as the plan wants it
+    fn finish_value(&self, i: usize, value: Value) -> Value {
+        match value {
+            Value::Oid(oid) if self.large_objects.get(i) == Some(&true) => Value::LargeObject(oid),
+            value => value,
+        }
+    }
+}
+
+/// Converts a batch of rows sharing `plan` into their values. This is the
+/// one conversion path for rows read from a postgres peer.
+pub fn values_from_rows(rows: &[Row], plan: &ConversionPlan) -> PgWireResult<Vec<Vec<Value>>> {
+    let width = plan.types.len();
+    let mut out: Vec<Vec<Value>> = Vec::with_capacity(rows.len());
+
+    let res = if plan.column_major {
+        out.extend(rows.iter().map(|_| Vec::with_capacity(width)));
+        plan.types.iter().enumerate().try_for_each(|(i, ty)| {
+            rows.iter()
+                .zip(out.iter_mut())
+                .try_for_each(|(row, values)| {
+                    let value = RowDecoder::new(row, plan.circles_as_json).decode(
+                        i,
+                        ty,
+                        &plan.metadata[i],
+                    )?;
+                    values.push(plan.finish_value(i, value));
+                    Ok(())
+                })
+        })
+    } else {
+        rows.iter().try_for_each(|row| {
+            let mut decoder = RowDecoder::new(row, plan.circles_as_json);
+            let mut values = Vec::with_capacity(width);
+            for (i, ty) in plan.types.iter().enumerate() {
+                let value = decoder.decode(i, ty, &plan.metadata[i])?;
+                values.push(plan.finish_value(i, value));
+            }
+            out.push(decoder.finish(values));
+            Ok(())
+        })
+    };
+
+    // a column the decoder cannot read is a type nexus does not support yet
+    res.map_err(|e: tokio_postgres::Error| {
+        PgError::new(
+            sqlstate::FEATURE_NOT_SUPPORTED,
+            format!("error decoding row: {}", e),
+        )
+    })?;
+    Ok(out)
+}
+
+/// Converts a single row like `values_from_rows`, but a column that fails to
+/// decode is replaced with `Value::Null` instead of failing the whole row. The
+/// index and error of each nulled column are returned alongside the values.
+pub fn values_from_row_lenient(row: &Row) -> (Vec<Value>, Vec<(usize, tokio_postgres::Error)>) {
+    let mut decoder = RowDecoder::new(row, false);
+    let mut errors = Vec::new();
+    let values = (0..row.len())
+        .map(|i| {
+            let ty = row.columns()[i].type_();
+            decoder
+                .decode(i, ty, &ColumnTypeResolver.resolve(ty))
+                .unwrap_or_else(|e| {
+                    errors.push((i, e));
+                    Value::Null
+                })
+        })
+        .collect();
+    (decoder.finish(values), errors)
 }
 
 impl Stream for PgRecordStream {
@@ -262,12 +758,27 @@ impl Stream for PgRecordStream {
 
         match Pin::new(row_stream).poll_next(cx) {
             Poll::Ready(Some(Ok(row))) => {
-                let values = values_from_row(&row);
-                let record = Record { values, schema };
-                Poll::Ready(Some(Ok(record)))
+                let this = &mut *self;
+                let plan = this.plan.get_or_insert_with(|| {
+                    let mut plan = ConversionPlan::with_type_cache(row.columns(), &mut this.types);
+                    if this.circles_as_json {
+                        plan = plan.with_circles_as_json();
+                    }
+                    if let Some(is_large_object) = &this.large_objects {
+                        plan = plan.with_large_objects(row.columns(), is_large_object.as_ref());
+                    }
+                    plan
+                });
+                let record = values_from_rows(std::slice::from_ref(&row), plan)
+                    .map(|mut values| Record {
+                        values: values.pop().unwrap_or_default(),
+                        schema,
+                    })
+                    .map_err(|err| self.stream_error(err));
+                Poll::Ready(Some(record))
             }
             Poll::Ready(Some(Err(e))) => {
-                let err = PgWireError::ApiError(Box::new(e));
+                let err = self.stream_error(PgError::from_postgres(&e, "error reading row").into());
                 Poll::Ready(Some(Err(err)))
             }
             Poll::Ready(None) => Poll::Ready(None),
@@ -0,0 +1,95 @@
+use std::{collections::HashMap, sync::Arc};
+
+use tokio_postgres::types::{Kind, Type};
+
+/// Column types a stream remembers at most, see `TypeCache`.
+pub const TYPE_CACHE_SIZE: usize = 256;
+
+/// What decoding a column needs to know about its type beyond the OID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeMetadata {
+    pub kind: Kind,
+    /// Element type of an array or a domain's base type.
+    pub element: Option<Type>,
+}
+
+/// Looks up the metadata of a type, which for types defined on the peer
+/// (enums, composites, domains) may take a catalog query.
+pub trait TypeResolver: Send + Sync {
+    fn resolve(&self, ty: &Type) -> TypeMetadata;
+}
+
+/// Resolves types from what the row description already carries, which
+/// tokio-postgres fills in for types defined on the peer.
+pub struct ColumnTypeResolver;
+
+impl TypeResolver for ColumnTypeResolver {
+    fn resolve(&self, ty: &Type) -> TypeMetadata {
+        let element = match ty.kind() {
+            Kind::Array(element) | Kind::Domain(element) => Some(element.clone()),
+            _ => None,
+        };
+        TypeMetadata {
+            kind: ty.kind().clone(),
+            element,
+        }
+    }
+}
+
+/// Metadata of the types a stream has seen, keyed by OID so that rows of the
+/// same types don't resolve them again. Bounded to `capacity` types, evicting
+/// the least recently used one.
+pub struct TypeCache {
+    resolver: Box<dyn TypeResolver>,
+    capacity: usize,
+    // OID to metadata and the tick it was last used at
+    entries: HashMap<u32, (Arc<TypeMetadata>, u64)>,
+    tick: u64,
+}
+
+impl TypeCache {
+    pub fn new(resolver: Box<dyn TypeResolver>, capacity: usize) -> Self {
+        Self {
+            resolver,
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub fn get(&mut self, ty: &Type) -> Arc<TypeMetadata> {
+        self.tick += 1;
+        if let Some((metadata, used)) = self.entries.get_mut(&ty.oid()) {
+            *used = self.tick;
+            return metadata.clone();
+        }
+
+        if self.entries.len() >= self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(oid, _)| *oid);
+            if let Some(oid) = oldest {
+                self.entries.remove(&oid);
+            }
+        }
+        let metadata = Arc::new(self.resolver.resolve(ty));
+        self.entries.insert(ty.oid(), (metadata.clone(), self.tick));
+        metadata
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Default for TypeCache {
+    fn default() -> Self {
+        Self::new(Box::new(ColumnTypeResolver), TYPE_CACHE_SIZE)
+    }
+}
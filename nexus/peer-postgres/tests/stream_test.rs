@@ -0,0 +1,887 @@
+use std::{
+    env,
+    ops::Range,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use futures::{stream, Stream, StreamExt};
+use peer_cursor::{QueryOutput, Record, RecordStream, Schema, SendableStream};
+use peer_postgres::{
+    ast::PostgresAst,
+    fetch::{fetches_in_chunks, pg_query_chunked, ChunkedRecordStream, FetchChunk},
+    parallel::{can_partition, partition_predicates, pg_query_partitioned, ParallelFetch},
+    pg_execute,
+    stream::{values_from_row_lenient, values_from_rows, ConversionPlan},
+    types::{ColumnTypeResolver, TypeCache, TypeMetadata, TypeResolver},
+};
+use pgerror::{sqlstate, PgError};
+use pgwire::{
+    api::results::{FieldFormat, FieldInfo},
+    error::PgWireResult,
+};
+use pt::peerdb_peers::PostgresConfig;
+use sqlparser::{ast::Statement, dialect::PostgreSqlDialect, parser::Parser};
+use tokio_postgres::{types::Type, Client, NoTls};
+use value::{array::ArrayValue, interval::Interval, timetz::TimeTz, Value};
+
+async fn connect() -> Client {
+    let var = |name: &str| env::var(name).unwrap_or_else(|_| panic!("{} not set", name));
+    let conn_str = format!(
+        "postgresql://{}:{}@{}:{}/{}",
+        var("PEERDB_CATALOG_USER"),
+        var("PEERDB_CATALOG_PASSWORD"),
+        var("PEERDB_CATALOG_HOST"),
+        var("PEERDB_CATALOG_PORT"),
+        var("PEERDB_CATALOG_DATABASE"),
+    );
+    let (client, connection) = tokio_postgres::connect(&conn_str, NoTls)
+        .await
+        .expect("failed to connect to postgres");
+    tokio::spawn(connection);
+    client
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn lenient_nulls_only_failing_columns() {
+    let client = connect().await;
+    // point is not decodable as a String, which the decoder still attempts
+    let row = client
+        .query_one("SELECT 42::int4 AS ok, point(1, 2) AS bad", &[])
+        .await
+        .unwrap();
+
+    let (values, errors) = values_from_row_lenient(&row);
+    assert_eq!(values, vec![Value::Integer(42), Value::Null]);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].0, 1);
+}
+
+// one expression per type over `g`, NULL on every seventh row
+const TYPE_GRID: &[&str] = &[
+    "(g % 2 = 0)",
+    "g::int2",
+    "(g * 1000)::int4",
+    "(g * 1000000)::int8",
+    "(g / 3.0)::float4",
+    "(g / 7.0)::float8",
+    "(g / 3.0)::numeric(12, 4)",
+    "g::oid",
+    "md5(g::text)",
+    "md5(g::text)::varchar",
+    "json_build_object('g', g)",
+    "jsonb_build_object('g', g, 'a', 1)",
+    "decode(md5(g::text), 'hex')",
+    "md5(g::text)::uuid",
+    "date '2024-01-01' + g",
+    "timestamp '2024-01-01' + g * interval '1 hour'",
+    "timestamptz '2024-01-01 00:00:00+00' + g * interval '1 hour'",
+    "ARRAY[g, g + 1]::int4[]",
+    "ARRAY[md5(g::text)]",
+];
+
+fn grid_query(exprs: &[&str]) -> String {
+    let columns: Vec<String> = exprs
+        .iter()
+        .map(|e| format!("CASE WHEN g % 7 = 0 THEN NULL ELSE {} END", e))
+        .collect();
+    format!(
+        "SELECT {} FROM generate_series(0, 199) AS g",
+        columns.join(", ")
+    )
+}
+
+async fn assert_batch_matches_per_row(client: &Client, exprs: &[&str]) {
+    let rows = client.query(&grid_query(exprs), &[]).await.unwrap();
+    let plan = ConversionPlan::new(rows[0].columns());
+
+    let batch = values_from_rows(&rows, &plan).unwrap();
+    assert_eq!(batch.len(), rows.len());
+    for (row, values) in rows.iter().zip(batch) {
+        let (expected, errors) = values_from_row_lenient(row);
+        assert!(errors.is_empty(), "{:?}: {:?}", exprs, errors);
+        assert_eq!(values, expected, "{:?}", exprs);
+    }
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn batch_conversion_matches_per_row() {
+    let client = connect().await;
+
+    for expr in TYPE_GRID {
+        assert_batch_matches_per_row(&client, &[*expr]).await;
+    }
+    // without text-like columns the batch is converted column-major
+    assert_batch_matches_per_row(&client, &TYPE_GRID[..8]).await;
+    assert_batch_matches_per_row(&client, TYPE_GRID).await;
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn regconfig_is_returned_by_name() {
+    let client = connect().await;
+    let sql = "SELECT 'english'::regconfig AS cfg, 1 AS n";
+    let stmt = Parser::parse_sql(&PostgreSqlDialect {}, sql)
+        .unwrap()
+        .remove(0);
+
+    let output = pg_execute(&client, PostgresAst { peername: None }, &stmt)
+        .await
+        .unwrap();
+    let QueryOutput::Stream(mut stream) = output else {
+        panic!("expected a stream for a query");
+    };
+    let record = stream.next().await.unwrap().unwrap();
+    assert_eq!(
+        record.values,
+        vec![
+            Value::text_bytes(Bytes::from_static(b"english")),
+            Value::Integer(1)
+        ]
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn regdictionary_is_returned_by_name() {
+    let client = connect().await;
+    let sql = "SELECT 'english_stem'::regdictionary AS dict, \
+        ARRAY['simple'::regdictionary, 'english_stem'] AS dicts";
+    let stmt = Parser::parse_sql(&PostgreSqlDialect {}, sql)
+        .unwrap()
+        .remove(0);
+
+    let output = pg_execute(&client, PostgresAst { peername: None }, &stmt)
+        .await
+        .unwrap();
+    let QueryOutput::Stream(mut stream) = output else {
+        panic!("expected a stream for a query");
+    };
+    let record = stream.next().await.unwrap().unwrap();
+    assert_eq!(
+        record.values,
+        vec![
+            Value::text_bytes(Bytes::from_static(b"english_stem")),
+            Value::text_bytes(Bytes::from_static(b"{simple,english_stem}")),
+        ]
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn reg_types_are_returned_by_name() {
+    let client = connect().await;
+    client
+        .batch_execute(
+            "CREATE SCHEMA IF NOT EXISTS peerdb_reg_test;
+            CREATE TABLE IF NOT EXISTS peerdb_reg_test.reg_table(id int);",
+        )
+        .await
+        .unwrap();
+    let user: String = client
+        .query_one("SELECT current_user::text", &[])
+        .await
+        .unwrap()
+        .get(0);
+
+    // a relation outside the search_path keeps its schema
+    let sql = "SELECT 'peerdb_reg_test.reg_table'::regclass AS rel, 'pg_class'::regclass AS cls,
+        current_user::regrole AS role, 'peerdb_reg_test'::regnamespace AS ns";
+    let stmt = Parser::parse_sql(&PostgreSqlDialect {}, sql)
+        .unwrap()
+        .remove(0);
+    let output = pg_execute(&client, PostgresAst { peername: None }, &stmt)
+        .await
+        .unwrap();
+    let QueryOutput::Stream(mut stream) = output else {
+        panic!("expected a stream for a query");
+    };
+    let record = stream.next().await.unwrap().unwrap();
+    assert_eq!(
+        record.values,
+        vec![
+            Value::text_bytes(Bytes::from_static(b"peerdb_reg_test.reg_table")),
+            Value::text_bytes(Bytes::from_static(b"pg_class")),
+            Value::text_bytes(Bytes::from(user)),
+            Value::text_bytes(Bytes::from_static(b"peerdb_reg_test")),
+        ]
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn name_array_decodes_to_varchar_array() {
+    let client = connect().await;
+    let row = client
+        .query_one(
+            "SELECT array_agg(attname ORDER BY attnum) FROM pg_attribute
+            WHERE attrelid = 'pg_namespace'::regclass AND attnum > 0",
+            &[],
+        )
+        .await
+        .unwrap();
+    assert_eq!(row.columns()[0].type_(), &Type::NAME_ARRAY);
+
+    let plan = ConversionPlan::new(row.columns());
+    let values = values_from_rows(std::slice::from_ref(&row), &plan).unwrap();
+    assert_eq!(
+        values,
+        vec![vec![Value::Array(ArrayValue::VarChar(vec![
+            "oid".to_string(),
+            "nspname".to_string(),
+            "nspowner".to_string(),
+            "nspacl".to_string(),
+        ]))]]
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn void_decodes_to_empty_text() {
+    let client = connect().await;
+    let row = client
+        .query_one("SELECT pg_sleep(0) AS slept, NULL::void AS nothing", &[])
+        .await
+        .unwrap();
+    assert_eq!(row.columns()[0].type_(), &Type::VOID);
+
+    let (values, errors) = values_from_row_lenient(&row);
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(values, vec![Value::Text(String::new()), Value::Null]);
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn pg_node_tree_decodes_to_text() {
+    let client = connect().await;
+    client
+        .batch_execute(
+            "CREATE TEMP TABLE node_tree_t (name text);
+             CREATE INDEX node_tree_lower_idx ON node_tree_t (lower(name));",
+        )
+        .await
+        .unwrap();
+    let row = client
+        .query_one(
+            "SELECT indexprs FROM pg_index WHERE indexrelid = 'node_tree_lower_idx'::regclass",
+            &[],
+        )
+        .await
+        .unwrap();
+    assert_eq!(row.columns()[0].type_(), &Type::PG_NODE_TREE);
+
+    let (values, errors) = values_from_row_lenient(&row);
+    assert!(errors.is_empty(), "{:?}", errors);
+    match &values[..] {
+        [Value::TextBytes(tree)] => assert!(tree.starts_with(b"({FUNCEXPR"), "{:?}", tree),
+        _ => panic!("expected the index expression as text, got {:?}", values),
+    }
+
+    // next to a column that isn't text, which alone would convert the rows a
+    // column at a time
+    let rows = client
+        .query(
+            "SELECT indexprs, indnatts FROM pg_index
+             WHERE indexrelid = 'node_tree_lower_idx'::regclass",
+            &[],
+        )
+        .await
+        .unwrap();
+    let values = values_from_rows(&rows, &ConversionPlan::new(rows[0].columns())).unwrap();
+    match &values[0][..] {
+        [Value::TextBytes(tree), Value::SmallInt(1)] => assert!(tree.starts_with(b"({FUNCEXPR")),
+        _ => panic!("expected the index expression as text, got {:?}", values),
+    }
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn circle_decodes_to_json_when_asked() {
+    let client = connect().await;
+    let rows = client
+        .query("SELECT circle '<(-1.5,-2),3>' AS c", &[])
+        .await
+        .unwrap();
+
+    let plan = ConversionPlan::new(rows[0].columns()).with_circles_as_json();
+    let values = values_from_rows(&rows, &plan).unwrap();
+    assert_eq!(
+        values,
+        vec![vec![Value::Json(serde_json::json!({
+            "center": {"x": -1.5, "y": -2.0},
+            "radius": 3.0,
+        }))]]
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn oid_columns_can_be_large_objects() {
+    let client = connect().await;
+    let rows = client
+        .query(
+            "SELECT 16385::oid AS report_lo, 23::oid AS type_oid, 'x_lo' AS text_lo",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    let plan = ConversionPlan::new(rows[0].columns());
+    let values = values_from_rows(&rows, &plan).unwrap();
+    assert_eq!(
+        values,
+        vec![vec![
+            Value::Oid(16385),
+            Value::Oid(23),
+            Value::TextBytes(Bytes::from_static(b"x_lo")),
+        ]]
+    );
+
+    // only oid columns are large objects, whatever the name
+    let plan = ConversionPlan::new(rows[0].columns())
+        .with_large_objects(rows[0].columns(), |name| name.ends_with("_lo"));
+    let values = values_from_rows(&rows, &plan).unwrap();
+    assert_eq!(
+        values,
+        vec![vec![
+            Value::LargeObject(16385),
+            Value::Oid(23),
+            Value::TextBytes(Bytes::from_static(b"x_lo")),
+        ]]
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn json_null_is_not_sql_null() {
+    let client = connect().await;
+    let rows = client
+        .query(
+            "SELECT NULL::jsonb, 'null'::jsonb, 'null'::json, '{}'::jsonb",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    let plan = ConversionPlan::new(rows[0].columns());
+    let values = values_from_rows(&rows, &plan).unwrap();
+    assert_eq!(
+        values,
+        vec![vec![
+            Value::Null,
+            Value::JsonB(serde_json::Value::Null),
+            Value::Json(serde_json::Value::Null),
+            Value::JsonBText(Bytes::from_static(b"{}")),
+        ]]
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn interval_keeps_months_days_and_microseconds() {
+    let client = connect().await;
+    let rows = client
+        .query(
+            "SELECT interval '10000 years 0.000001 seconds' AS long, \
+                    interval '-1 mon 2 days' AS mixed",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    let plan = ConversionPlan::new(rows[0].columns());
+    let values = values_from_rows(&rows, &plan).unwrap();
+    assert_eq!(
+        values,
+        vec![vec![
+            Value::Interval(Interval {
+                months: 120_000,
+                days: 0,
+                microseconds: 1,
+            }),
+            Value::Interval(Interval {
+                months: -1,
+                days: 2,
+                microseconds: 0,
+            }),
+        ]]
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn oidvector_decodes_to_oid_array() {
+    let client = connect().await;
+    let row = client
+        .query_one(
+            "SELECT proargtypes FROM pg_proc WHERE proname = 'int4pl'",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    let (values, errors) = values_from_row_lenient(&row);
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(values, vec![Value::Array(ArrayValue::Oid(vec![23, 23]))]);
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn time_array_keeps_null_elements() {
+    use chrono::NaiveTime;
+
+    let client = connect().await;
+    let row = client
+        .query_one("SELECT ARRAY['09:00'::time, NULL]", &[])
+        .await
+        .unwrap();
+
+    let (values, errors) = values_from_row_lenient(&row);
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(
+        values,
+        vec![Value::Array(ArrayValue::Time(vec![
+            Some(NaiveTime::from_hms_opt(9, 0, 0).unwrap()),
+            None
+        ]))]
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn bool_array_keeps_null_elements() {
+    let client = connect().await;
+    let row = client
+        .query_one("SELECT ARRAY[true, NULL, false]", &[])
+        .await
+        .unwrap();
+
+    let (values, errors) = values_from_row_lenient(&row);
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(
+        values,
+        vec![Value::Array(ArrayValue::Bool(vec![
+            Some(true),
+            None,
+            Some(false)
+        ]))]
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn interval_and_numeric_arrays_decode_by_element() {
+    let client = connect().await;
+    let row = client
+        .query_one(
+            "SELECT ARRAY['1 mon 2 days 00:00:03'::interval, NULL], ARRAY[1.50, 'NaN']::numeric[]",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    let (values, errors) = values_from_row_lenient(&row);
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(
+        values,
+        vec![
+            Value::Array(ArrayValue::Interval(vec![
+                Some(Interval {
+                    months: 1,
+                    days: 2,
+                    microseconds: 3_000_000,
+                }),
+                None
+            ])),
+            Value::Array(ArrayValue::Numeric(vec!["1.50".into(), "NaN".into()])),
+        ]
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn timetz_array_keeps_offsets() {
+    let client = connect().await;
+    let row = client
+        .query_one(
+            "SELECT ARRAY['09:00+05:30'::timetz, '17:45:10.5-08'::timetz, NULL]",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    let (values, errors) = values_from_row_lenient(&row);
+    assert!(errors.is_empty(), "{:?}", errors);
+    let [Value::Array(ArrayValue::TimeTz(times))] = &values[..] else {
+        panic!("expected a timetz array, got {:?}", values);
+    };
+    let texts = times
+        .iter()
+        .map(|t| t.as_ref().map(TimeTz::to_string))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        texts,
+        [
+            Some("09:00:00+05:30".to_string()),
+            Some("17:45:10.5-08".to_string()),
+            None
+        ]
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn timestamps_past_chrono_range_decode_as_text() {
+    use chrono::NaiveDate;
+
+    let client = connect().await;
+    let row = client
+        .query_one(
+            "SELECT '10000-01-01 00:00:00+00'::timestamptz, \
+                '294000-06-01 12:30:00.25+00'::timestamptz, \
+                'infinity'::timestamptz, \
+                '-infinity'::timestamp",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    let (values, errors) = values_from_row_lenient(&row);
+    assert!(errors.is_empty(), "{:?}", errors);
+    let year_10000 = NaiveDate::from_ymd_opt(10000, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc();
+    assert_eq!(
+        values,
+        vec![
+            Value::TimestampWithTimeZone(year_10000),
+            Value::Text("294000-06-01 12:30:00.25+00".to_string()),
+            Value::Text("infinity".to_string()),
+            Value::Text("-infinity".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn char_keeps_bytes_past_ascii() {
+    let client = connect().await;
+    let row = client
+        .query_one("SELECT 'a'::\"char\", (-61)::\"char\"", &[])
+        .await
+        .unwrap();
+
+    let (values, errors) = values_from_row_lenient(&row);
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(values, [Value::Char('a'), Value::Char('\u{c3}')]);
+}
+
+fn parse_query(sql: &str) -> Box<sqlparser::ast::Query> {
+    match Parser::parse_sql(&PostgreSqlDialect {}, sql)
+        .unwrap()
+        .remove(0)
+    {
+        Statement::Query(query) => query,
+        other => panic!("expected a query, got {}", other),
+    }
+}
+
+#[test]
+fn small_limits_skip_chunked_fetch() {
+    let query = parse_query("SELECT * FROM t LIMIT 100");
+    assert!(!fetches_in_chunks(&query, 100));
+    assert!(fetches_in_chunks(&query, 99));
+    assert!(!fetches_in_chunks(&query, 0));
+    assert!(fetches_in_chunks(&parse_query("SELECT * FROM t"), 100));
+}
+
+// a chunk of rows numbered `rows`, as a FETCH of the cursor returns it
+struct ChunkStream {
+    schema: Schema,
+    records: stream::Iter<std::vec::IntoIter<PgWireResult<Record>>>,
+}
+
+impl Stream for ChunkStream {
+    type Item = PgWireResult<Record>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.records).poll_next(cx)
+    }
+}
+
+impl RecordStream for ChunkStream {
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+}
+
+fn chunk_of(schema: &Schema, rows: Range<i32>) -> SendableStream {
+    let records = rows
+        .map(|g| {
+            Ok(Record {
+                values: vec![Value::Integer(g)],
+                schema: schema.clone(),
+            })
+        })
+        .collect::<Vec<_>>();
+    Box::pin(ChunkStream {
+        schema: schema.clone(),
+        records: stream::iter(records),
+    })
+}
+
+#[tokio::test]
+async fn chunks_are_fetched_once_the_previous_one_is_read() {
+    const FETCH_SIZE: i32 = 50;
+    let schema: Schema = Arc::new(vec![FieldInfo::new(
+        "g".to_string(),
+        None,
+        None,
+        Type::INT4,
+        FieldFormat::Text,
+    )]);
+
+    // 250 rows is an exact multiple of the fetch size, so the last fetch
+    // comes back empty
+    for rows in [250, 260] {
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let fetch: FetchChunk = {
+            let (schema, fetches) = (schema.clone(), fetches.clone());
+            Box::new(move || {
+                let start = (fetches.fetch_add(1, Ordering::SeqCst) as i32 + 1) * FETCH_SIZE;
+                let chunk = chunk_of(&schema, start..(start + FETCH_SIZE).min(rows));
+                Box::pin(async move { Ok(chunk) })
+            })
+        };
+        let first = chunk_of(&schema, 0..FETCH_SIZE);
+        let mut stream = ChunkedRecordStream::new(schema.clone(), FETCH_SIZE as u32, first, fetch);
+
+        // the first chunk is read without fetching more
+        let first_chunk = (&mut stream).take(FETCH_SIZE as usize).count().await;
+        assert_eq!(first_chunk, FETCH_SIZE as usize);
+        assert_eq!(fetches.load(Ordering::SeqCst), 0);
+
+        let rest = stream
+            .map(|record| record.unwrap().values)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(rest.len(), (rows - FETCH_SIZE) as usize);
+        assert_eq!(rest.last(), Some(&vec![Value::Integer(rows - 1)]));
+        assert_eq!(fetches.load(Ordering::SeqCst), (rows / FETCH_SIZE) as usize);
+    }
+}
+
+#[tokio::test]
+async fn chunked_fetch_errors_end_the_stream() {
+    let schema: Schema = Arc::new(vec![]);
+    let fetch: FetchChunk = Box::new(|| {
+        Box::pin(async {
+            Err(PgError::new(sqlstate::CONNECTION_FAILURE, "connection lost").into())
+        })
+    });
+    let first = chunk_of(&schema, 0..2);
+    let stream = ChunkedRecordStream::new(schema.clone(), 2, first, fetch);
+
+    let records = stream.collect::<Vec<_>>().await;
+    assert_eq!(records.len(), 3);
+    assert!(records[..2].iter().all(Result::is_ok));
+    assert!(records[2].is_err());
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn chunked_fetch_streams_every_row() {
+    let var = |name: &str| env::var(name).unwrap_or_else(|_| panic!("{} not set", name));
+    let config = PostgresConfig {
+        host: var("PEERDB_CATALOG_HOST"),
+        port: var("PEERDB_CATALOG_PORT").parse().unwrap(),
+        user: var("PEERDB_CATALOG_USER"),
+        password: var("PEERDB_CATALOG_PASSWORD"),
+        database: var("PEERDB_CATALOG_DATABASE"),
+        ..Default::default()
+    };
+
+    // 1000 rows is an exact multiple of the fetch size, so the last fetch
+    // comes back empty
+    for rows in [1000, 1001] {
+        let query = parse_query(&format!("SELECT g FROM generate_series(1, {}) AS g", rows));
+        let stream = pg_query_chunked("pg", &config, &query, 100).await.unwrap();
+        let values = stream
+            .map(|record| record.unwrap().values)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(values.len(), rows);
+        assert_eq!(values[rows - 1], vec![Value::Integer(rows as i32)]);
+    }
+}
+
+#[test]
+fn partitions_hold_the_nulls_too() {
+    assert_eq!(
+        partition_predicates("id", Some((1, 30)), 3),
+        [
+            "\"id\" <= 10",
+            "\"id\" BETWEEN 11 AND 20",
+            "\"id\" >= 21",
+            "\"id\" IS NULL",
+        ]
+    );
+    assert_eq!(
+        partition_predicates("id", Some((1, 1)), 4),
+        ["\"id\" IS NOT NULL", "\"id\" IS NULL"]
+    );
+    // a column that is NULL on every row
+    assert_eq!(partition_predicates("id", None, 2), ["\"id\" IS NULL"]);
+    // the column is quoted as an identifier
+    assert_eq!(
+        partition_predicates("Order \"Id\"", None, 2),
+        ["\"Order \"\"Id\"\"\" IS NULL"]
+    );
+}
+
+#[test]
+fn limited_and_ordered_queries_are_not_partitioned() {
+    let fetch = ParallelFetch {
+        partition_column: "id".to_string(),
+        ..Default::default()
+    };
+    let ordered = ParallelFetch {
+        ordered: true,
+        ..fetch.clone()
+    };
+    let partitions = |sql: &str, fetch: &ParallelFetch| can_partition(&parse_query(sql), fetch);
+
+    assert!(partitions("SELECT * FROM t", &fetch));
+    assert!(!partitions("SELECT * FROM t LIMIT 10", &fetch));
+    assert!(!partitions(
+        "SELECT * FROM t ORDER BY id OFFSET 5",
+        &ordered
+    ));
+    assert!(!partitions(
+        "SELECT * FROM t FETCH FIRST 3 ROWS ONLY",
+        &fetch
+    ));
+
+    // unless the merge keeps the order of the partition column
+    assert!(!partitions("SELECT * FROM t ORDER BY id", &fetch));
+    assert!(partitions("SELECT * FROM t ORDER BY id", &ordered));
+    assert!(partitions("SELECT * FROM t ORDER BY t.ID ASC", &ordered));
+    assert!(!partitions("SELECT * FROM t ORDER BY id DESC", &ordered));
+    assert!(!partitions(
+        "SELECT * FROM t ORDER BY id NULLS FIRST",
+        &ordered
+    ));
+    assert!(!partitions("SELECT * FROM t ORDER BY name", &ordered));
+    assert!(!partitions("SELECT * FROM t ORDER BY id, name", &ordered));
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn partitioned_fetch_keeps_null_rows() {
+    let var = |name: &str| env::var(name).unwrap_or_else(|_| panic!("{} not set", name));
+    let config = PostgresConfig {
+        host: var("PEERDB_CATALOG_HOST"),
+        port: var("PEERDB_CATALOG_PORT").parse().unwrap(),
+        user: var("PEERDB_CATALOG_USER"),
+        password: var("PEERDB_CATALOG_PASSWORD"),
+        database: var("PEERDB_CATALOG_DATABASE"),
+        ..Default::default()
+    };
+    let fetch = ParallelFetch {
+        partition_column: "k".to_string(),
+        parallelism: 4,
+        ordered: true,
+    };
+
+    let query = parse_query(
+        "SELECT CASE WHEN g % 10 = 0 THEN NULL ELSE g END AS k
+         FROM generate_series(1, 100) AS g",
+    );
+    let stream = pg_query_partitioned("pg", &config, &query, &fetch)
+        .await
+        .unwrap();
+    let values = stream
+        .map(|record| record.unwrap().values)
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(values.len(), 100);
+    assert_eq!(values[90..], vec![vec![Value::Null]; 10]);
+
+    let query = parse_query("SELECT NULL::int AS k FROM generate_series(1, 5)");
+    let stream = pg_query_partitioned("pg", &config, &query, &fetch)
+        .await
+        .unwrap();
+    assert_eq!(stream.count().await, 5);
+}
+
+// counts the types it is asked to resolve
+struct CountingResolver(Arc<AtomicUsize>);
+
+impl TypeResolver for CountingResolver {
+    fn resolve(&self, ty: &Type) -> TypeMetadata {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        ColumnTypeResolver.resolve(ty)
+    }
+}
+
+#[test]
+fn type_cache_resolves_each_oid_once() {
+    let resolved = Arc::new(AtomicUsize::new(0));
+    let mut cache = TypeCache::new(Box::new(CountingResolver(resolved.clone())), 8);
+    let row = [Type::INT4, Type::TEXT, Type::INT4, Type::INT4_ARRAY];
+    for _ in 0..1000 {
+        for ty in &row {
+            cache.get(ty);
+        }
+    }
+    assert_eq!(resolved.load(Ordering::Relaxed), 3);
+    assert_eq!(cache.get(&Type::INT4_ARRAY).element, Some(Type::INT4));
+}
+
+#[test]
+fn type_cache_evicts_least_recently_used() {
+    let resolved = Arc::new(AtomicUsize::new(0));
+    let mut cache = TypeCache::new(Box::new(CountingResolver(resolved.clone())), 2);
+    cache.get(&Type::INT4);
+    cache.get(&Type::TEXT);
+    cache.get(&Type::INT4);
+    // evicts text, the least recently used
+    cache.get(&Type::BOOL);
+    assert_eq!(cache.len(), 2);
+    cache.get(&Type::INT4);
+    assert_eq!(resolved.load(Ordering::Relaxed), 3);
+    cache.get(&Type::TEXT);
+    assert_eq!(resolved.load(Ordering::Relaxed), 4);
+}
+
+#[tokio::test]
+#[ignore = "requires a running postgres"]
+async fn enum_decodes_to_its_label() {
+    let client = connect().await;
+    client
+        .batch_execute(
+            "DO $$ BEGIN
+                CREATE TYPE peerdb_mood AS ENUM ('sad', 'happy');
+            EXCEPTION WHEN duplicate_object THEN NULL;
+            END $$;",
+        )
+        .await
+        .unwrap();
+    let row = client
+        .query_one("SELECT 'happy'::peerdb_mood, NULL::peerdb_mood", &[])
+        .await
+        .unwrap();
+
+    let (values, errors) = values_from_row_lenient(&row);
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(values, vec![Value::Enum("happy".into()), Value::Null]);
+}
@@ -0,0 +1,174 @@
+use std::{fmt, future::Future};
+
+use pgwire::error::{ErrorInfo, PgWireError};
+use tokio_postgres::error::ErrorPosition;
+
+/// SQLSTATE codes reported by nexus itself, see
+/// https://www.postgresql.org/docs/current/errcodes-appendix.html
+pub mod sqlstate {
+    pub const CONNECTION_FAILURE: &str = "08006";
+    pub const FEATURE_NOT_SUPPORTED: &str = "0A000";
+    pub const DATETIME_FIELD_OVERFLOW: &str = "22008";
+    pub const INVALID_PARAMETER_VALUE: &str = "22023";
+    pub const INVALID_TEXT_REPRESENTATION: &str = "22P02";
+    pub const BAD_COPY_FILE_FORMAT: &str = "22P04";
+    pub const INVALID_CURSOR_NAME: &str = "34000";
+    pub const SYNTAX_ERROR: &str = "42601";
+    pub const UNDEFINED_OBJECT: &str = "42704";
+    pub const DUPLICATE_OBJECT: &str = "42710";
+    pub const DATATYPE_MISMATCH: &str = "42804";
+    pub const UNDEFINED_TABLE: &str = "42P01";
+    pub const QUERY_CANCELED: &str = "57014";
+    pub const FDW_ERROR: &str = "HV000";
+    pub const INTERNAL_ERROR: &str = "XX000";
+}
+
+/// An error as the client sees it: a SQLSTATE and message, with the optional
+/// detail, hint and position postgres errors can carry.
+#[derive(Debug)]
+pub struct PgError {
+    severity: String,
+    code: String,
+    message: String,
+    detail: Option<String>,
+    hint: Option<String>,
+    position: Option<String>,
+}
+
+impl PgError {
+    pub fn new(code: &str, message: impl Into<String>) -> Self {
+        Self {
+            severity: "ERROR".to_owned(),
+            code: code.to_owned(),
+            message: message.into(),
+            detail: None,
+            hint: None,
+            position: None,
+        }
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(sqlstate::INTERNAL_ERROR, message)
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Appends the statement's query ID and peer to the error's detail.
+    pub fn with_context(mut self, ctx: &QueryContext) -> Self {
+        self.detail = Some(ctx.append_to(self.detail.as_deref()));
+        self
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// Converts an error from a postgres peer. Errors raised by the server
+    /// keep their SQLSTATE, message, detail, hint and position as they are;
+    /// lost connections become `connection_failure`, and anything else is an
+    /// internal error prefixed with `context`.
+    pub fn from_postgres(err: &tokio_postgres::Error, context: &str) -> Self {
+        if let Some(db) = err.as_db_error() {
+            return Self {
+                severity: db.severity().to_owned(),
+                code: db.code().code().to_owned(),
+                message: db.message().to_owned(),
+                detail: db.detail().map(str::to_owned),
+                hint: db.hint().map(str::to_owned),
+                position: match db.position() {
+                    Some(ErrorPosition::Original(position)) => Some(position.to_string()),
+                    _ => None,
+                },
+            };
+        }
+
+        let code = if err.is_closed() {
+            sqlstate::CONNECTION_FAILURE
+        } else {
+            sqlstate::INTERNAL_ERROR
+        };
+        Self::new(code, format!("{}: {}", context, err))
+    }
+}
+
+tokio::task_local! {
+    static QUERY_ID: String;
+}
+
+/// Identifies the statement that an error or log line belongs to.
+#[derive(Debug, Clone, Default)]
+pub struct QueryContext {
+    pub query_id: String,
+    pub peer: Option<String>,
+}
+
+impl QueryContext {
+    /// Context of the statement running on the current task, if any, for
+    /// `peer`.
+    pub fn current(peer: Option<&str>) -> Self {
+        Self {
+            query_id: QUERY_ID.try_with(Clone::clone).unwrap_or_default(),
+            peer: peer.map(str::to_owned),
+        }
+    }
+
+    /// Runs `f` as the statement with `query_id`, see `QueryContext::current`.
+    pub async fn scope<F: Future>(query_id: String, f: F) -> F::Output {
+        QUERY_ID.scope(query_id, f).await
+    }
+
+    fn append_to(&self, detail: Option<&str>) -> String {
+        let mut out = String::new();
+        if let Some(detail) = detail {
+            out.push_str(detail);
+            out.push('\n');
+        }
+        out.push_str("query_id: ");
+        out.push_str(&self.query_id);
+        if let Some(peer) = &self.peer {
+            out.push_str(", peer: ");
+            out.push_str(peer);
+        }
+        out
+    }
+}
+
+/// Adds the statement's query ID and peer to the detail of `err`. Errors
+/// without a SQLSTATE are reported as internal errors.
+pub fn with_query_context(err: PgWireError, ctx: &QueryContext) -> PgWireError {
+    match err {
+        PgWireError::UserError(mut info) => {
+            let detail = ctx.append_to(info.detail().as_deref());
+            info.set_detail(Some(detail));
+            PgWireError::UserError(info)
+        }
+        PgWireError::ApiError(err) => PgError::internal(err.to_string()).with_context(ctx).into(),
+        err => err,
+    }
+}
+
+impl fmt::Display for PgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for PgError {}
+
+impl From<PgError> for PgWireError {
+    fn from(err: PgError) -> Self {
+        let mut info = ErrorInfo::new(err.severity, err.code, err.message);
+        info.set_detail(err.detail);
+        info.set_hint(err.hint);
+        info.set_position(err.position);
+        PgWireError::UserError(Box::new(info))
+    }
+}
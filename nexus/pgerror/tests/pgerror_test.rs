@@ -0,0 +1,21 @@
+use pgerror::{sqlstate, PgError};
+use pgwire::error::PgWireError;
+
+#[test]
+fn converts_to_user_error_with_sqlstate() {
+    let err = PgError::new(sqlstate::UNDEFINED_OBJECT, "no such peer: \"missing\"")
+        .with_hint("list the available peers with SELECT * FROM peers");
+
+    match PgWireError::from(err) {
+        PgWireError::UserError(info) => {
+            assert_eq!(info.code(), "42704");
+            assert_eq!(info.severity(), "ERROR");
+            assert_eq!(info.message(), "no such peer: \"missing\"");
+            assert_eq!(
+                info.hint().as_deref(),
+                Some("list the available peers with SELECT * FROM peers")
+            );
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
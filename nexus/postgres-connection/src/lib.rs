@@ -2,6 +2,7 @@ use pt::peerdb_peers::PostgresConfig;
 use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
 use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
 use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio_postgres_rustls::MakeRustlsConnect;
 
@@ -77,24 +78,37 @@ pub fn get_pg_connection_string(config: &PostgresConfig) -> String {
     connection_string
 }
 
-pub async fn connect_postgres(config: &PostgresConfig) -> anyhow::Result<tokio_postgres::Client> {
-    let connection_string = get_pg_connection_string(config);
-
+pub fn tls_connector() -> MakeRustlsConnect {
     let mut config = ClientConfig::builder()
         .with_root_certificates(RootCertStore::empty())
         .with_no_client_auth();
     config
         .dangerous()
         .set_certificate_verifier(Arc::new(NoCertificateVerification));
-    let tls_connector = MakeRustlsConnect::new(config);
-    let (client, connection) = tokio_postgres::connect(&connection_string, tls_connector)
+    MakeRustlsConnect::new(config)
+}
+
+static OPEN_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Connections opened by `connect_postgres` that are still open, to peers,
+/// for chunked and partitioned fetches, and to the catalog.
+pub fn open_connections() -> usize {
+    OPEN_CONNECTIONS.load(Ordering::Relaxed)
+}
+
+pub async fn connect_postgres(config: &PostgresConfig) -> anyhow::Result<tokio_postgres::Client> {
+    let connection_string = get_pg_connection_string(config);
+
+    let (client, connection) = tokio_postgres::connect(&connection_string, tls_connector())
         .await
         .map_err(|e| anyhow::anyhow!("error encountered while connecting to postgres {:?}", e))?;
 
+    OPEN_CONNECTIONS.fetch_add(1, Ordering::Relaxed);
     tokio::task::spawn(async move {
         if let Err(e) = connection.await {
             tracing::info!("connection error: {}", e)
         }
+        OPEN_CONNECTIONS.fetch_sub(1, Ordering::Relaxed);
     });
 
     Ok(client)
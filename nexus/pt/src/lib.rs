@@ -13,7 +13,12 @@ pub mod peerdb_peers;
 #[rustfmt::skip]
 #[path ="./gen/peerdb_route.rs"]
 pub mod peerdb_route;
+#[rustfmt::skip]
+#[path ="./gen/peerdb_value.rs"]
+pub mod peerdb_value;
+pub mod secrets;
 
+pub use pbjson_types;
 pub use prost;
 pub use tonic;
 
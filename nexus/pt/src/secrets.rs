@@ -0,0 +1,94 @@
+// references to secrets in string options of peers and mirrors.
+//
+// `env:NAME` stands for the value of the environment variable NAME and
+// `file:/path` for the contents of the file, without a trailing newline.
+// a value that really starts with one of the prefixes is written with a
+// `literal:` prefix, `literal:env:x` standing for `env:x`.
+//
+// the catalog stores options as written, so references are only resolved
+// right before connecting, and errors name the reference, never the secret.
+
+use std::{borrow::Cow, fmt};
+
+use crate::peerdb_peers::Peer;
+
+#[derive(Debug)]
+pub struct SecretError {
+    reference: String,
+    reason: String,
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unable to resolve secret reference {}: {}",
+            self.reference, self.reason
+        )
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+/// Whether `value` refers to a secret rather than being the value itself.
+pub fn is_reference(value: &str) -> bool {
+    value.starts_with("env:") || value.starts_with("file:")
+}
+
+/// Resolves `value` if it is a secret reference, and returns it as is
+/// otherwise.
+pub fn resolve(value: &str) -> Result<Cow<'_, str>, SecretError> {
+    let error = |reason: String| SecretError {
+        reference: value.to_string(),
+        reason,
+    };
+
+    if let Some(literal) = value.strip_prefix("literal:") {
+        Ok(Cow::Borrowed(literal))
+    } else if let Some(name) = value.strip_prefix("env:") {
+        std::env::var(name)
+            .map(Cow::Owned)
+            .map_err(|err| error(err.to_string()))
+    } else if let Some(path) = value.strip_prefix("file:") {
+        let mut contents = std::fs::read_to_string(path).map_err(|err| error(err.to_string()))?;
+        let trimmed = contents.trim_end_matches(['\n', '\r']).len();
+        contents.truncate(trimmed);
+        Ok(Cow::Owned(contents))
+    } else {
+        Ok(Cow::Borrowed(value))
+    }
+}
+
+/// Returns a copy of `peer` with every secret reference in its config
+/// resolved.
+pub fn resolve_peer(peer: &Peer) -> Result<Peer, SecretError> {
+    let error = |err: serde_json::Error| SecretError {
+        reference: format!("in peer {}", peer.name),
+        reason: err.to_string(),
+    };
+
+    let mut json = serde_json::to_value(peer).map_err(error)?;
+    resolve_json(&mut json)?;
+    serde_json::from_value(json).map_err(error)
+}
+
+fn resolve_json(value: &mut serde_json::Value) -> Result<(), SecretError> {
+    match value {
+        serde_json::Value::String(s) => {
+            let resolved = resolve(s)?.into_owned();
+            *s = resolved;
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                resolve_json(value)?;
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for value in map.values_mut() {
+                resolve_json(value)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
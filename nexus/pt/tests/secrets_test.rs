@@ -0,0 +1,64 @@
+use pt::{
+    peerdb_peers::{peer::Config, DbType, Peer, PostgresConfig},
+    secrets::{resolve, resolve_peer},
+};
+
+#[test]
+fn plain_and_literal_values() {
+    assert_eq!(resolve("hunter2").unwrap(), "hunter2");
+    assert_eq!(resolve("literal:env:HOME").unwrap(), "env:HOME");
+    assert_eq!(resolve("literal:literal:x").unwrap(), "literal:x");
+}
+
+#[test]
+fn env_reference() {
+    std::env::set_var("PEERDB_SECRETS_TEST_PASS", "s3cret");
+    assert_eq!(resolve("env:PEERDB_SECRETS_TEST_PASS").unwrap(), "s3cret");
+
+    let err = resolve("env:PEERDB_SECRETS_TEST_MISSING").unwrap_err();
+    assert!(err.to_string().contains("env:PEERDB_SECRETS_TEST_MISSING"));
+}
+
+#[test]
+fn file_reference() {
+    let path = std::env::temp_dir().join("peerdb_secrets_test.txt");
+    std::fs::write(&path, "from-file\n").unwrap();
+    let reference = format!("file:{}", path.display());
+    assert_eq!(resolve(&reference).unwrap(), "from-file");
+    std::fs::remove_file(&path).unwrap();
+
+    let err = resolve(&reference).unwrap_err();
+    assert!(err.to_string().contains(&reference));
+}
+
+#[test]
+fn peer_config_references() {
+    std::env::set_var("PEERDB_SECRETS_TEST_PGPASS", "pg-pass");
+    let peer = Peer {
+        name: "pg".to_string(),
+        r#type: DbType::Postgres as i32,
+        config: Some(Config::PostgresConfig(PostgresConfig {
+            host: "localhost".to_string(),
+            port: 5432,
+            user: "literal:file:user".to_string(),
+            password: "env:PEERDB_SECRETS_TEST_PGPASS".to_string(),
+            database: "postgres".to_string(),
+            ..Default::default()
+        })),
+    };
+
+    let resolved = resolve_peer(&peer).unwrap();
+    let Some(Config::PostgresConfig(config)) = resolved.config else {
+        panic!("expected a postgres config");
+    };
+    assert_eq!(config.password, "pg-pass");
+    assert_eq!(config.user, "file:user");
+    assert_eq!(config.host, "localhost");
+    assert_eq!(config.port, 5432);
+
+    // the peer itself keeps the reference
+    let Some(Config::PostgresConfig(config)) = &peer.config else {
+        unreachable!()
+    };
+    assert_eq!(config.password, "env:PEERDB_SECRETS_TEST_PGPASS");
+}
@@ -0,0 +1,172 @@
+use std::sync::Arc;
+
+use peer_cursor::{Record, Records, Schema};
+use pgwire::api::{
+    results::{FieldFormat, FieldInfo},
+    Type,
+};
+use sqlparser::ast::{self, BinaryOperator, Expr, Query, SelectItem, SetExpr};
+use value::Value;
+
+/// Git revision nexus was built from, as set by the image build.
+const GIT_SHA: &str = match option_env!("PEERDB_VERSION_SHA_SHORT") {
+    Some(sha) => sha,
+    None => "unknown",
+};
+
+/// The client of a session, as the built-in functions report it.
+#[derive(Debug, Clone, Default)]
+pub struct SessionInfo {
+    pub user: String,
+    pub database: String,
+    pub backend_pid: i32,
+}
+
+pub fn peerdb_version() -> String {
+    format!("{} ({})", env!("CARGO_PKG_VERSION"), GIT_SHA)
+}
+
+pub fn version() -> String {
+    format!(
+        "PostgreSQL 14.0 (PeerDB nexus {}) on {}-{}",
+        peerdb_version(),
+        std::env::consts::ARCH,
+        std::env::consts::OS
+    )
+}
+
+/// Evaluates queries like `SELECT version(), peerdb_version()` in nexus
+/// rather than on a peer: a plain SELECT without FROM whose columns call at
+/// least one built-in function and are otherwise made of literals. `None`
+/// for any other query, which is left to the peer.
+pub fn evaluate(query: &Query, session: &SessionInfo) -> Option<Records> {
+    if query.with.is_some()
+        || !query.order_by.is_empty()
+        || query.limit.is_some()
+        || query.offset.is_some()
+        || query.fetch.is_some()
+    {
+        return None;
+    }
+    let SetExpr::Select(select) = query.body.as_ref() else {
+        return None;
+    };
+    if !select.from.is_empty()
+        || select.selection.is_some()
+        || select.having.is_some()
+        || select.distinct.is_some()
+    {
+        return None;
+    }
+
+    let mut calls_builtin = false;
+    let mut fields = Vec::with_capacity(select.projection.len());
+    let mut values = Vec::with_capacity(select.projection.len());
+    for item in &select.projection {
+        let (expr, alias) = match item {
+            SelectItem::UnnamedExpr(expr) => (expr, None),
+            SelectItem::ExprWithAlias { expr, alias } => (expr, Some(alias.value.clone())),
+            _ => return None,
+        };
+        let (value, ty) = eval(expr, session, &mut calls_builtin)?;
+        let name = alias.unwrap_or_else(|| column_name(expr));
+        fields.push(FieldInfo::new(name, None, None, ty, FieldFormat::Text));
+        values.push(value);
+    }
+    if !calls_builtin {
+        return None;
+    }
+
+    let schema: Schema = Arc::new(fields);
+    Some(Records {
+        records: vec![Record {
+            values,
+            schema: schema.clone(),
+        }],
+        schema,
+    })
+}
+
+fn eval(expr: &Expr, session: &SessionInfo, calls_builtin: &mut bool) -> Option<(Value, Type)> {
+    match expr {
+        Expr::Nested(expr) => eval(expr, session, calls_builtin),
+        Expr::Value(ast::Value::SingleQuotedString(s)) => {
+            Some((Value::Text(s.clone()), Type::TEXT))
+        }
+        Expr::Value(ast::Value::Number(n, _)) => match n.parse::<i32>() {
+            Ok(n) => Some((Value::Integer(n), Type::INT4)),
+            Err(_) => Some((Value::BigInt(n.parse().ok()?), Type::INT8)),
+        },
+        Expr::Value(ast::Value::Null) => Some((Value::Null, Type::TEXT)),
+        Expr::BinaryOp {
+            left,
+            op: BinaryOperator::StringConcat,
+            right,
+        } => {
+            let (left, _) = eval(left, session, calls_builtin)?;
+            let (right, _) = eval(right, session, calls_builtin)?;
+            let value = match (left, right) {
+                (Value::Null, _) | (_, Value::Null) => Value::Null,
+                (left, right) => Value::Text(format!("{}{}", as_text(left)?, as_text(right)?)),
+            };
+            Some((value, Type::TEXT))
+        }
+        Expr::Function(function) if function.args.is_empty() && function.over.is_none() => {
+            let value = builtin(&function_name(expr)?, session)?;
+            *calls_builtin = true;
+            Some(value)
+        }
+        // current_user and session_user are keywords called without
+        // parentheses, but may also come through as plain identifiers
+        Expr::Identifier(ident)
+            if ident.quote_style.is_none()
+                && ["current_user", "session_user"]
+                    .contains(&ident.value.to_lowercase().as_str()) =>
+        {
+            let value = builtin(&ident.value.to_lowercase(), session)?;
+            *calls_builtin = true;
+            Some(value)
+        }
+        _ => None,
+    }
+}
+
+fn builtin(name: &str, session: &SessionInfo) -> Option<(Value, Type)> {
+    Some(match name {
+        "version" => (Value::Text(version()), Type::TEXT),
+        "peerdb_version" => (Value::Text(peerdb_version()), Type::TEXT),
+        "current_user" | "session_user" => (Value::Text(session.user.clone()), Type::TEXT),
+        "current_database" => (Value::Text(session.database.clone()), Type::TEXT),
+        "pg_backend_pid" => (Value::Integer(session.backend_pid), Type::INT4),
+        _ => return None,
+    })
+}
+
+// unqualified, as a schema-qualified call is left to the peer
+fn function_name(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Function(function) if function.name.0.len() == 1 => {
+            Some(function.name.0[0].value.to_lowercase())
+        }
+        _ => None,
+    }
+}
+
+fn as_text(value: Value) -> Option<String> {
+    match value {
+        Value::Text(s) => Some(s),
+        Value::Integer(n) => Some(n.to_string()),
+        Value::BigInt(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+// the column name postgres gives to an unaliased expression
+fn column_name(expr: &Expr) -> String {
+    match expr {
+        Expr::Nested(expr) => column_name(expr),
+        Expr::Function(_) => function_name(expr).unwrap_or_else(|| "?column?".to_string()),
+        Expr::Identifier(ident) => ident.value.to_lowercase(),
+        _ => "?column?".to_string(),
+    }
+}
@@ -20,8 +20,13 @@ impl PeerCursors {
         self.cursors.insert(name, peer);
     }
 
-    pub fn remove_cursor(&mut self, name: &str) {
-        self.cursors.remove(name);
+    // whether there was such a cursor
+    pub fn remove_cursor(&mut self, name: &str) -> bool {
+        self.cursors.remove(name).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cursors.len()
     }
 
     pub fn get_peer(&self, name: &str) -> Option<&Peer> {
@@ -1,24 +1,33 @@
 use std::{
     collections::{HashMap, HashSet},
     fmt::Write,
+    net::SocketAddr,
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use analyzer::{PeerDDL, QueryAssociation};
 use async_trait::async_trait;
+use builtins::SessionInfo;
 use bytes::{BufMut, BytesMut};
 use catalog::{Catalog, CatalogConfig, WorkflowDetails};
 use clap::Parser;
 use cursor::PeerCursors;
 use dashmap::{mapref::entry::Entry as DashEntry, DashMap};
 use flow_rs::grpc::{FlowGrpcClient, PeerCreationResult};
+use metrics::Metrics;
 use peer_connections::{PeerConnectionTracker, PeerConnections};
 use peer_cursor::{
-    util::{records_to_query_response, sendable_stream_to_query_response},
-    QueryExecutor, QueryOutput, Schema,
+    progress::{ProgressRegistry, StreamEnd, StreamProgress},
+    util::{
+        records_to_query_response, sendable_stream_to_query_response, with_column_case, ColumnCase,
+        DateStyle, EncodeOptions, IntervalStyle,
+    },
+    QueryExecutor, QueryOutput, Record, Records, Schema, SendableStream,
 };
+use peer_postgres::parallel::ParallelFetch;
 use peerdb_parser::{NexusParsedStatement, NexusQueryParser, NexusStatement};
+use pgerror::{sqlstate, with_query_context, PgError, QueryContext};
 use pgwire::{
     api::{
         auth::{
@@ -28,10 +37,11 @@ use pgwire::{
         portal::Portal,
         query::{ExtendedQueryHandler, SimpleQueryHandler},
         results::{
-            DescribePortalResponse, DescribeResponse, DescribeStatementResponse, Response, Tag,
+            DescribePortalResponse, DescribeResponse, DescribeStatementResponse, FieldFormat,
+            FieldInfo, Response, Tag,
         },
         stmt::StoredStatement,
-        ClientInfo, MakeHandler, Type,
+        ClientInfo, MakeHandler, Type, METADATA_DATABASE, METADATA_USER,
     },
     error::{ErrorInfo, PgWireError, PgWireResult},
     tokio::process_socket,
@@ -41,13 +51,21 @@ use pt::{
     peerdb_peers::{peer::Config, Peer},
 };
 use rand::Rng;
+use sqlparser::ast::{self, Expr};
 use tokio::signal::unix::{signal, SignalKind};
 use tokio::sync::Mutex;
 use tokio::{io::AsyncWriteExt, net::TcpListener};
+use tracing::Instrument;
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+use value::Value;
 
+mod builtins;
 mod cursor;
+mod metrics;
+
+/// Session setting routing tables not qualified by a peer name to a peer.
+const DEFAULT_PEER_SETTING: &str = "peerdb.default_peer";
 
 struct FixedPasswordAuthSource {
     password: String,
@@ -80,6 +98,16 @@ pub struct NexusBackend {
     executors: DashMap<String, Arc<dyn QueryExecutor>>,
     flow_handler: Option<Arc<Mutex<FlowGrpcClient>>>,
     peerdb_fdw_mode: bool,
+    encode_options: Mutex<EncodeOptions>,
+    parallel_fetch: Mutex<ParallelFetch>,
+    // overrides the fetch_size of postgres peers for the session
+    fetch_size: Mutex<Option<u32>>,
+    in_transaction: Mutex<bool>,
+    // streams being sent to the clients of every session
+    streams: Arc<ProgressRegistry>,
+    // known once the client has sent its first query
+    session_info: Mutex<SessionInfo>,
+    metrics: Arc<Metrics>,
 }
 
 impl NexusBackend {
@@ -88,6 +116,8 @@ impl NexusBackend {
         peer_connections: PeerConnectionTracker,
         flow_handler: Option<Arc<Mutex<FlowGrpcClient>>>,
         peerdb_fdw_mode: bool,
+        streams: Arc<ProgressRegistry>,
+        metrics: Arc<Metrics>,
     ) -> Self {
         let query_parser = NexusQueryParser::new(catalog.clone());
         Self {
@@ -98,9 +128,107 @@ impl NexusBackend {
             executors: DashMap::new(),
             flow_handler,
             peerdb_fdw_mode,
+            encode_options: Mutex::new(EncodeOptions::default()),
+            parallel_fetch: Mutex::new(ParallelFetch::default()),
+            fetch_size: Mutex::new(None),
+            in_transaction: Mutex::new(false),
+            streams,
+            session_info: Mutex::new(SessionInfo::default()),
+            metrics,
+        }
+    }
+
+    async fn remember_client<C: ClientInfo>(&self, client: &C) {
+        let mut session_info = self.session_info.lock().await;
+        if session_info.user.is_empty() {
+            let metadata = client.metadata();
+            let user = metadata.get(METADATA_USER).cloned().unwrap_or_default();
+            // like postgres, the database defaults to the user's name
+            let database = metadata
+                .get(METADATA_DATABASE)
+                .cloned()
+                .unwrap_or_else(|| user.clone());
+            match self.catalog.get_user_default_peer(&user).await {
+                Ok(Some(default_peer)) => self.query_parser.set_default_peer(Some(default_peer)),
+                Ok(None) => {}
+                Err(err) => {
+                    tracing::warn!("unable to look up the default peer of {}: {}", user, err)
+                }
+            }
+            *session_info = SessionInfo {
+                user,
+                database,
+                backend_pid: client.pid_and_secret_key().0,
+            };
         }
     }
 
+    // counts what `stream` sends for SHOW peerdb.streams, logging the totals
+    // and how long the query, `started` at its execution, took once it is done
+    fn observe_stream(
+        &self,
+        stream: SendableStream,
+        peer: Option<&str>,
+        stmt: &ast::Statement,
+        started: Instant,
+    ) -> SendableStream {
+        let ctx = QueryContext::current(peer);
+        let progress = StreamProgress::new(ctx.query_id, ctx.peer, stmt.to_string());
+        let metrics = self.metrics.clone();
+        let class = metrics::query_class(stmt);
+        self.streams.register(
+            stream,
+            progress,
+            Box::new(move |progress, totals, end| {
+                tracing::info!(
+                    "[{}] sent {} rows ({} bytes) in {:?}",
+                    progress.query_id,
+                    totals.rows,
+                    totals.bytes,
+                    totals.elapsed
+                );
+                // a query dropped by the client was never done
+                if end != StreamEnd::Dropped {
+                    metrics.observe_query(progress.peer.as_deref(), class, started.elapsed());
+                }
+                let peer = progress.peer.as_deref().unwrap_or("catalog");
+                metrics
+                    .rows_streamed
+                    .with_label_values(&[peer])
+                    .inc_by(totals.rows);
+                if end == StreamEnd::Dropped {
+                    metrics.streams_cancelled.with_label_values(&[peer]).inc();
+                }
+            }),
+        )
+    }
+
+    // a row per stream being sent, for SHOW peerdb.streams
+    fn stream_progress_records(&self) -> Records {
+        let schema = stream_progress_schema();
+        let records = self
+            .streams
+            .streams()
+            .into_iter()
+            .map(|progress| {
+                let snapshot = progress.snapshot();
+                Record {
+                    values: vec![
+                        Value::Text(progress.query_id.clone()),
+                        progress.peer.clone().map_or(Value::Null, Value::Text),
+                        Value::Text(progress.query.clone()),
+                        Value::BigInt(snapshot.rows as i64),
+                        Value::BigInt(snapshot.bytes as i64),
+                        Value::BigInt(snapshot.elapsed.as_millis() as i64),
+                        Value::BigInt(snapshot.idle.as_millis() as i64),
+                    ],
+                    schema: schema.clone(),
+                }
+            })
+            .collect();
+        Records { records, schema }
+    }
+
     // execute a statement on a peer
     async fn execute_statement<'a>(
         &self,
@@ -108,18 +236,33 @@ impl NexusBackend {
         stmt: &sqlparser::ast::Statement,
         peer_holder: Option<Box<Peer>>,
     ) -> PgWireResult<Vec<Response<'a>>> {
-        let res = executor.execute(stmt).await?;
-        match res {
+        let started = Instant::now();
+        let peer = peer_holder.as_ref().map(|peer| peer.name.clone());
+        let res = executor.execute(stmt).await;
+        // streams are timed until their last row is sent, by observe_stream
+        if !matches!(res, Ok(QueryOutput::Stream(_))) {
+            let class = metrics::query_class(stmt);
+            self.metrics
+                .observe_query(peer.as_deref(), class, started.elapsed());
+        }
+        let encode_options = *self.encode_options.lock().await;
+        match res? {
             QueryOutput::AffectedRows(rows) => {
                 Ok(vec![Response::Execution(Tag::new("OK").with_rows(rows))])
             }
             QueryOutput::Stream(rows) => {
+                let rows = self.observe_stream(rows, peer.as_deref(), stmt, started);
                 let schema = rows.schema();
-                let res = sendable_stream_to_query_response(schema, rows)?;
+                let mut res = sendable_stream_to_query_response(schema, rows, encode_options)?;
+                // the row count of the tag is whatever was actually sent, which
+                // is less than requested when the cursor runs out early.
+                if let (ast::Statement::Fetch { .. }, Response::Query(query)) = (stmt, &mut res) {
+                    query.set_command_tag("FETCH");
+                }
                 Ok(vec![res])
             }
             QueryOutput::Records(records) => {
-                let res = records_to_query_response(records)?;
+                let res = records_to_query_response(records, encode_options, Vec::new())?;
                 Ok(vec![res])
             }
             QueryOutput::Cursor(cm) => {
@@ -129,12 +272,15 @@ impl NexusBackend {
                     peer_cursor::CursorModification::Created(cursor_name) => {
                         if let Some(peer_holder) = peer_holder {
                             peer_cursors.add_cursor(cursor_name, peer_holder);
+                            self.metrics.open_cursors.inc();
                         }
                         Ok(vec![Response::Execution(Tag::new("DECLARE CURSOR"))])
                     }
                     peer_cursor::CursorModification::Closed(cursors) => {
                         for cursor_name in cursors {
-                            peer_cursors.remove_cursor(&cursor_name);
+                            if peer_cursors.remove_cursor(&cursor_name) {
+                                self.metrics.open_cursors.dec();
+                            }
                         }
                         Ok(vec![Response::Execution(Tag::new("CLOSE CURSOR"))])
                     }
@@ -143,6 +289,147 @@ impl NexusBackend {
         }
     }
 
+    // checks a SET of a session setting that nexus applies itself before the
+    // statement runs, so that a value nexus can't apply is rejected before
+    // the executor takes it.
+    async fn check_session_setting(
+        &self,
+        stmt: &ast::Statement,
+    ) -> PgWireResult<Option<SessionSetting>> {
+        let ast::Statement::SetVariable {
+            variable, value, ..
+        } = stmt
+        else {
+            return Ok(None);
+        };
+
+        let as_setting = |expr: &Expr| match expr {
+            Expr::Value(ast::Value::SingleQuotedString(s)) => s.clone(),
+            Expr::Value(ast::Value::Number(n, _)) => n.clone(),
+            Expr::Value(ast::Value::Boolean(b)) => b.to_string(),
+            Expr::Identifier(ident) => ident.value.clone(),
+            _ => String::new(),
+        };
+        let setting = value.first().map(as_setting).unwrap_or_default();
+        let invalid_setting =
+            |err: String| PgWireError::from(PgError::new(sqlstate::INVALID_PARAMETER_VALUE, err));
+
+        Ok(Some(match variable.to_string().to_lowercase().as_str() {
+            "intervalstyle" => SessionSetting::IntervalStyle(
+                setting
+                    .parse::<IntervalStyle>()
+                    .map_err(|err| invalid_setting(err.to_string()))?,
+            ),
+            // `SET datestyle TO german, dmy` comes as a list of values
+            "datestyle" => {
+                let setting = value.iter().map(as_setting).collect::<Vec<_>>().join(",");
+                let date_style = self.encode_options.lock().await.date_style;
+                SessionSetting::DateStyle(
+                    date_style
+                        .with_setting(&setting)
+                        .map_err(|err| invalid_setting(err.to_string()))?,
+                )
+            }
+            DEFAULT_PEER_SETTING => {
+                // an empty value unsets it, sending bare tables back to the
+                // catalog
+                SessionSetting::DefaultPeer(match setting.to_lowercase() {
+                    peer_name if peer_name.is_empty() => None,
+                    peer_name => {
+                        let peers = self.query_parser.get_peers_bridge().await?;
+                        if !peers.contains_key(&peer_name) {
+                            return Err(invalid_setting(format!(
+                                "invalid value for {}: no such peer {}",
+                                DEFAULT_PEER_SETTING, setting
+                            )));
+                        }
+                        Some(peer_name)
+                    }
+                })
+            }
+            "peerdb.batch_bytes" => {
+                let batch_bytes = setting.parse::<usize>().map_err(|_| {
+                    invalid_setting(format!("invalid value for peerdb.batch_bytes: {}", setting))
+                })?;
+                // 0 keeps encoding record by record
+                SessionSetting::BatchBytes(Some(batch_bytes).filter(|&b| b > 0))
+            }
+            "peerdb.column_case" => SessionSetting::ColumnCase(
+                setting
+                    .parse::<ColumnCase>()
+                    .map_err(|err| invalid_setting(err.to_string()))?,
+            ),
+            "peerdb.fetch_size" => {
+                let fetch_size = setting.parse::<u32>().map_err(|_| {
+                    invalid_setting(format!("invalid value for peerdb.fetch_size: {}", setting))
+                })?;
+                // 0 fetches whole results at once, whatever the peer says
+                SessionSetting::FetchSize(fetch_size)
+            }
+            "peerdb.partition_column" => SessionSetting::PartitionColumn(setting),
+            "peerdb.parallelism" => {
+                let parallelism = setting.parse::<usize>().map_err(|_| {
+                    invalid_setting(format!("invalid value for peerdb.parallelism: {}", setting))
+                })?;
+                SessionSetting::Parallelism(parallelism)
+            }
+            "peerdb.ordered_partitions" => {
+                let ordered = setting.parse::<bool>().map_err(|_| {
+                    invalid_setting(format!(
+                        "invalid value for peerdb.ordered_partitions: {}",
+                        setting
+                    ))
+                })?;
+                SessionSetting::OrderedPartitions(ordered)
+            }
+            _ => return Ok(None),
+        }))
+    }
+
+    // tracks the transaction state and the setting checked by
+    // `check_session_setting` once the statement has gone through on the
+    // executor.
+    async fn track_session_setting(&self, stmt: &ast::Statement, setting: Option<SessionSetting>) {
+        match stmt {
+            ast::Statement::StartTransaction { .. } => *self.in_transaction.lock().await = true,
+            ast::Statement::Commit { .. } | ast::Statement::Rollback { .. } => {
+                *self.in_transaction.lock().await = false
+            }
+            _ => {}
+        }
+
+        match setting {
+            None => {}
+            Some(SessionSetting::IntervalStyle(interval_style)) => {
+                self.encode_options.lock().await.interval_style = interval_style
+            }
+            Some(SessionSetting::DateStyle(date_style)) => {
+                self.encode_options.lock().await.date_style = date_style
+            }
+            Some(SessionSetting::DefaultPeer(default_peer)) => {
+                self.query_parser.set_default_peer(default_peer)
+            }
+            Some(SessionSetting::BatchBytes(batch_bytes)) => {
+                self.encode_options.lock().await.batch_bytes = batch_bytes
+            }
+            Some(SessionSetting::ColumnCase(column_case)) => {
+                self.encode_options.lock().await.column_case = column_case
+            }
+            Some(SessionSetting::FetchSize(fetch_size)) => {
+                *self.fetch_size.lock().await = Some(fetch_size)
+            }
+            Some(SessionSetting::PartitionColumn(partition_column)) => {
+                self.parallel_fetch.lock().await.partition_column = partition_column
+            }
+            Some(SessionSetting::Parallelism(parallelism)) => {
+                self.parallel_fetch.lock().await.parallelism = parallelism
+            }
+            Some(SessionSetting::OrderedPartitions(ordered)) => {
+                self.parallel_fetch.lock().await.ordered = ordered
+            }
+        }
+    }
+
     async fn check_for_mirror(
         catalog: &Catalog,
         flow_name: &str,
@@ -158,20 +445,75 @@ impl NexusBackend {
         Ok(workflow_details)
     }
 
+    // `drift` lists what differs between the existing mirror and the new
+    // definition.
     fn handle_mirror_existence(
         if_not_exists: bool,
         flow_name: &str,
+        drift: &[&str],
     ) -> PgWireResult<Vec<Response<'static>>> {
         if if_not_exists {
-            let existing_mirror_success = "MIRROR ALREADY EXISTS";
-            Ok(vec![Response::Execution(Tag::new(existing_mirror_success))])
+            let tag = Tag::new(&format!("CREATE MIRROR {}", flow_name));
+            Self::handle_existing("mirror", flow_name, drift, tag)
         } else {
-            Err(PgWireError::UserError(Box::new(ErrorInfo::new(
-                "ERROR".to_owned(),
-                "error".to_owned(),
+            Err(PgWireError::from(PgError::new(
+                sqlstate::DUPLICATE_OBJECT,
                 format!("mirror already exists: {:?}", flow_name),
-            ))))
+            )))
+        }
+    }
+
+    // a CREATE ... IF NOT EXISTS of an object that exists answers with the tag
+    // of its creation, `tag`, like postgres, and fails if the definitions
+    // differ. pgwire's query handlers can't send the NOTICE postgres sends
+    // along, so it is only logged.
+    fn handle_existing(
+        object: &str,
+        name: &str,
+        drift: &[&str],
+        tag: Tag,
+    ) -> PgWireResult<Vec<Response<'static>>> {
+        if drift.is_empty() {
+            tracing::info!("{} {} already exists, skipping", object, name);
+            return Ok(vec![Response::Execution(tag)]);
+        }
+        Err(PgWireError::from(PgError::new(
+            sqlstate::DUPLICATE_OBJECT,
+            format!(
+                "{} {} already exists with a different {}",
+                object,
+                name,
+                drift.join(", ")
+            ),
+        )))
+    }
+
+    fn peer_drift(existing: &Peer, peer: &Peer) -> Vec<&'static str> {
+        let mut drift = Vec::new();
+        if existing.r#type != peer.r#type {
+            drift.push("type");
+        }
+        if existing.config != peer.config {
+            drift.push("options");
+        }
+        drift
+    }
+
+    fn qrep_mirror_drift(existing: &QRepFlowJob, job: &QRepFlowJob) -> Vec<&'static str> {
+        let mut drift = Vec::new();
+        if existing.source_peer != job.source_peer {
+            drift.push("source peer");
+        }
+        if existing.target_peer != job.target_peer {
+            drift.push("target peer");
         }
+        if existing.query_string != job.query_string {
+            drift.push("query");
+        }
+        if existing.flow_options != job.flow_options {
+            drift.push("options");
+        }
+        drift
     }
 
     async fn create_peer<'a>(&self, peer: &Peer) -> anyhow::Result<()> {
@@ -193,11 +535,10 @@ impl NexusBackend {
                 PgWireError::ApiError(format!("unable to check peer validity: {:?}", err).into())
             })?;
         if let PeerCreationResult::Failed(create_err) = create_response {
-            Err(PgWireError::UserError(Box::new(ErrorInfo::new(
-                "ERROR".to_owned(),
-                "internal_error".to_owned(),
+            Err(PgWireError::from(PgError::new(
+                sqlstate::INTERNAL_ERROR,
                 format!("failed to create peer: {}", create_err),
-            )))
+            ))
             .into())
         } else {
             Ok(())
@@ -268,11 +609,10 @@ impl NexusBackend {
                         let no_mirror_success = "NO SUCH MIRROR";
                         Ok(vec![Response::Execution(Tag::new(no_mirror_success))])
                     } else {
-                        Err(PgWireError::UserError(Box::new(ErrorInfo::new(
-                            "ERROR".to_owned(),
-                            "error".to_owned(),
+                        Err(PgWireError::from(PgError::new(
+                            sqlstate::UNDEFINED_OBJECT,
                             format!("no such mirror: {:?}", flow_job_name),
-                        ))))
+                        )))
                     }
                 }
                 _ => unreachable!(),
@@ -290,12 +630,16 @@ impl NexusBackend {
                 PeerDDL::CreateMirrorForSelect {
                     if_not_exists,
                     qrep_flow_job,
+                    warnings,
                 } => {
                     if self.flow_handler.is_none() {
                         return Err(PgWireError::ApiError(
                             "flow service is not configured".into(),
                         ));
                     }
+                    for warning in warnings {
+                        tracing::warn!("mirror {}: {}", qrep_flow_job.name, warning);
+                    }
                     let mirror_details;
                     {
                         mirror_details =
@@ -325,7 +669,25 @@ impl NexusBackend {
                         let create_mirror_success = format!("CREATE MIRROR {}", qrep_flow_job.name);
                         Ok(vec![Response::Execution(Tag::new(&create_mirror_success))])
                     } else {
-                        Self::handle_mirror_existence(*if_not_exists, &qrep_flow_job.name)
+                        let existing = if *if_not_exists {
+                            self.catalog
+                                .get_qrep_flow_job_by_name(&qrep_flow_job.name)
+                                .await
+                                .map_err(|err| {
+                                    PgWireError::ApiError(
+                                        format!("unable to get qrep flow job: {:?}", err).into(),
+                                    )
+                                })?
+                        } else {
+                            None
+                        };
+                        let drift = match existing {
+                            Some(existing) => Self::qrep_mirror_drift(&existing, qrep_flow_job),
+                            // the existing mirror is a cdc mirror
+                            None if *if_not_exists => vec!["mirror type"],
+                            None => Vec::new(),
+                        };
+                        Self::handle_mirror_existence(*if_not_exists, &qrep_flow_job.name, &drift)
                     }
                 }
                 _ => unreachable!(),
@@ -334,19 +696,136 @@ impl NexusBackend {
         }
     }
 
+    // parses and runs the statements of a query message under a fresh query id.
+    // the id and the peer involved are attached to every log line of the message
+    // and to the detail of any error returned for it.
+    //
+    // like postgres' simple query protocol, the statements run in order and an
+    // error aborts the remaining ones. unlike postgres there is no implicit
+    // transaction around them: every statement runs on its own against its peer
+    // or the catalog, so statements that completed before the error are not
+    // rolled back. an error in the first statement is returned as is, an error
+    // in a later one is sent after the results of the earlier statements.
+    //
+    // tables not qualified by a peer name go to the session's default peer as
+    // of each statement, or to `bound_default_peer` for a prepared statement,
+    // which keeps the default peer it was prepared with. a prepared statement
+    // is a single command, as in postgres, and is rejected otherwise.
+    async fn run_statement<'a>(
+        &self,
+        sql: &str,
+        bound_default_peer: Option<Option<String>>,
+    ) -> PgWireResult<Vec<Response<'a>>> {
+        let query_id = uuid::Uuid::new_v4().to_string();
+        let span = tracing::info_span!("query", %query_id, peer = tracing::field::Empty);
+        let mut peer = None;
+
+        let run = async {
+            tracing::info!("received statement: {}", sql);
+            let stmts = match bound_default_peer {
+                Some(_) => self
+                    .query_parser
+                    .parse_single_statement(sql)
+                    .map(|stmt| stmt.into_iter().collect()),
+                None => self.query_parser.parse_statements(sql),
+            };
+            let stmts = match stmts {
+                Ok(stmts) => stmts,
+                Err(err) => {
+                    tracing::error!("statement failed: {}", err);
+                    return Err((Vec::new(), err));
+                }
+            };
+            if stmts.is_empty() {
+                tracing::info!("statement completed");
+                return Ok(vec![Response::EmptyQuery]);
+            }
+
+            let mut responses = Vec::with_capacity(stmts.len());
+            for stmt in stmts {
+                peer = None;
+                let mut class = "unknown";
+                let res = async {
+                    let default_peer = bound_default_peer
+                        .clone()
+                        .unwrap_or_else(|| self.query_parser.default_peer());
+                    let parsed = self
+                        .query_parser
+                        .analyze_statement(stmt, default_peer)
+                        .await?;
+                    class = metrics::statement_class(&parsed.statement);
+                    if let NexusStatement::PeerQuery {
+                        assoc: QueryAssociation::Peer(p),
+                        ..
+                    } = &parsed.statement
+                    {
+                        tracing::Span::current().record("peer", p.name.as_str());
+                        peer = Some(p.name.clone());
+                    }
+                    self.handle_query(parsed.statement).await
+                }
+                .await;
+                let status = if res.is_ok() { "ok" } else { "error" };
+                self.metrics
+                    .statements
+                    .with_label_values(&[class, status])
+                    .inc();
+                match res {
+                    Ok(res) => responses.extend(res),
+                    Err(err) => {
+                        tracing::error!("statement failed: {}", err);
+                        return Err((responses, err));
+                    }
+                }
+            }
+            tracing::info!("statement completed");
+            Ok(responses)
+        }
+        .instrument(span);
+
+        let res = QueryContext::scope(query_id.clone(), run).await;
+        let ctx = QueryContext { query_id, peer };
+        match res {
+            Ok(responses) => Ok(responses),
+            Err((responses, err)) if responses.is_empty() => Err(with_query_context(err, &ctx)),
+            Err((mut responses, err)) => match with_query_context(err, &ctx) {
+                PgWireError::UserError(info) => {
+                    responses.push(Response::Error(info));
+                    Ok(responses)
+                }
+                err => Err(err),
+            },
+        }
+    }
+
     async fn handle_query<'a>(
         &self,
         nexus_stmt: NexusStatement,
     ) -> PgWireResult<Vec<Response<'a>>> {
         match nexus_stmt {
             NexusStatement::PeerDDL { stmt: _, ref ddl } => match ddl.as_ref() {
-                PeerDDL::CreatePeer { peer, .. } => {
+                PeerDDL::CreatePeer {
+                    peer,
+                    if_not_exists,
+                } => {
+                    if *if_not_exists {
+                        let peers = self
+                            .catalog
+                            .get_peers()
+                            .await
+                            .map_err(|e| PgWireError::from(PgError::internal(e.to_string())))?;
+                        if let Some(existing) = peers.get(&peer.name) {
+                            let drift = Self::peer_drift(existing, peer);
+                            return Self::handle_existing(
+                                "peer",
+                                &peer.name,
+                                &drift,
+                                Tag::new("OK"),
+                            );
+                        }
+                    }
                     self.create_peer(peer).await.map_err(|e| {
-                        PgWireError::UserError(Box::new(ErrorInfo::new(
-                            "ERROR".to_owned(),
-                            "internal_error".to_owned(),
-                            e.to_string(),
-                        )))
+                        PgWireError::from(PgError::new(sqlstate::INTERNAL_ERROR, e.to_string()))
                     })?;
 
                     Ok(vec![Response::Execution(Tag::new("OK"))])
@@ -362,7 +841,17 @@ impl NexusBackend {
                     }
                     let mirror_details =
                         Self::check_for_mirror(self.catalog.as_ref(), &flow_job.name).await?;
-                    if mirror_details.is_none() {
+                    if let Some(existing) = mirror_details {
+                        // the catalog only keeps the peers of a cdc mirror
+                        let mut drift = Vec::new();
+                        if existing.source_peer != flow_job.source_peer {
+                            drift.push("source peer");
+                        }
+                        if existing.destination_peer != flow_job.target_peer {
+                            drift.push("target peer");
+                        }
+                        Self::handle_mirror_existence(*if_not_exists, &flow_job.name, &drift)
+                    } else {
                         // reject duplicate source tables or duplicate target tables
                         let table_mappings_count = flow_job.table_mappings.len();
                         if table_mappings_count > 1 {
@@ -407,8 +896,6 @@ impl NexusBackend {
 
                         let create_mirror_success = format!("CREATE MIRROR {}", flow_job.name);
                         Ok(vec![Response::Execution(Tag::new(&create_mirror_success))])
-                    } else {
-                        Self::handle_mirror_existence(*if_not_exists, &flow_job.name)
                     }
                 }
                 PeerDDL::CreateMirrorForSelect { .. } => {
@@ -435,11 +922,10 @@ impl NexusBackend {
                         let create_mirror_success = format!("STARTED WORKFLOW {}", workflow_id);
                         Ok(vec![Response::Execution(Tag::new(&create_mirror_success))])
                     } else {
-                        Err(PgWireError::UserError(Box::new(ErrorInfo::new(
-                            "ERROR".to_owned(),
-                            "error".to_owned(),
+                        Err(PgWireError::from(PgError::new(
+                            sqlstate::UNDEFINED_OBJECT,
                             format!("no such mirror: {:?}", flow_job_name),
-                        ))))
+                        )))
                     }
                 }
                 PeerDDL::DropMirror { .. } => self.handle_drop_mirror(&nexus_stmt).await,
@@ -480,11 +966,10 @@ impl NexusBackend {
                         let no_peer_success = "NO SUCH PEER";
                         Ok(vec![Response::Execution(Tag::new(no_peer_success))])
                     } else {
-                        Err(PgWireError::UserError(Box::new(ErrorInfo::new(
-                            "ERROR".to_owned(),
-                            "error".to_owned(),
+                        Err(PgWireError::from(PgError::new(
+                            sqlstate::UNDEFINED_OBJECT,
                             format!("no such peer: {:?}", peer_name),
-                        ))))
+                        )))
                     }
                 }
                 PeerDDL::ResyncMirror {
@@ -618,11 +1103,10 @@ impl NexusBackend {
                         let no_mirror_success = "NO SUCH MIRROR";
                         Ok(vec![Response::Execution(Tag::new(no_mirror_success))])
                     } else {
-                        Err(PgWireError::UserError(Box::new(ErrorInfo::new(
-                            "ERROR".to_owned(),
-                            "error".to_owned(),
+                        Err(PgWireError::from(PgError::new(
+                            sqlstate::UNDEFINED_OBJECT,
                             format!("no such mirror: {:?}", flow_job_name),
-                        ))))
+                        )))
                     }
                 }
                 PeerDDL::ResumeMirror {
@@ -676,15 +1160,117 @@ impl NexusBackend {
                         let no_mirror_success = "NO SUCH MIRROR";
                         Ok(vec![Response::Execution(Tag::new(no_mirror_success))])
                     } else {
-                        Err(PgWireError::UserError(Box::new(ErrorInfo::new(
-                            "ERROR".to_owned(),
-                            "error".to_owned(),
+                        Err(PgWireError::from(PgError::new(
+                            sqlstate::UNDEFINED_OBJECT,
                             format!("no such mirror: {:?}", flow_job_name),
-                        ))))
+                        )))
                     }
                 }
             },
             NexusStatement::PeerQuery { stmt, assoc } => {
+                // version() and friends are answered by nexus itself
+                if let ast::Statement::Query(query) = &stmt {
+                    let session_info = self.session_info.lock().await.clone();
+                    if let Some(records) = builtins::evaluate(query, &session_info) {
+                        let encode_options = *self.encode_options.lock().await;
+                        return Ok(vec![records_to_query_response(
+                            records,
+                            encode_options,
+                            Vec::new(),
+                        )?]);
+                    }
+                }
+
+                // SHOW peerdb.streams lists the query results being sent
+                // across all sessions
+                if is_show(&stmt, "peerdb.streams") {
+                    let encode_options = *self.encode_options.lock().await;
+                    let records = self.stream_progress_records();
+                    return Ok(vec![records_to_query_response(
+                        records,
+                        encode_options,
+                        Vec::new(),
+                    )?]);
+                }
+
+                if is_show(&stmt, DEFAULT_PEER_SETTING) {
+                    let encode_options = *self.encode_options.lock().await;
+                    let schema = default_peer_schema();
+                    let default_peer = self.query_parser.default_peer().unwrap_or_default();
+                    let records = Records {
+                        records: vec![Record {
+                            values: vec![Value::Text(default_peer)],
+                            schema: schema.clone(),
+                        }],
+                        schema,
+                    };
+                    return Ok(vec![records_to_query_response(
+                        records,
+                        encode_options,
+                        Vec::new(),
+                    )?]);
+                }
+
+                // big SELECTs on postgres peers can be fetched as parallel
+                // range partitions when the session sets a partition column,
+                // or a chunk of fetch_size rows at a time through a cursor
+                if let (QueryAssociation::Peer(peer), ast::Statement::Query(query)) =
+                    (&assoc, &stmt)
+                {
+                    if let Some(Config::PostgresConfig(ref c)) = peer.config {
+                        let parallel_fetch = self.parallel_fetch.lock().await.clone();
+                        let fetch_size = self.fetch_size.lock().await.or(c.fetch_size).unwrap_or(0);
+                        // a separate connection wouldn't see the transaction
+                        let chunked = !*self.in_transaction.lock().await
+                            && peer_postgres::fetch::fetches_in_chunks(query, fetch_size);
+                        if parallel_fetch.is_enabled() || chunked {
+                            let peer = pt::secrets::resolve_peer(peer).map_err(|err| {
+                                PgWireError::from(PgError::new(
+                                    sqlstate::CONNECTION_FAILURE,
+                                    err.to_string(),
+                                ))
+                            })?;
+                            let Some(Config::PostgresConfig(ref c)) = peer.config else {
+                                unreachable!()
+                            };
+                            let started = Instant::now();
+                            let stream = if parallel_fetch.is_enabled() {
+                                tracing::info!(
+                                    "handling partitioned peer[{}] query: {}",
+                                    peer.name,
+                                    stmt
+                                );
+                                peer_postgres::parallel::pg_query_partitioned(
+                                    &peer.name,
+                                    c,
+                                    query,
+                                    &parallel_fetch,
+                                )
+                                .await?
+                            } else {
+                                tracing::info!(
+                                    "handling chunked peer[{}] query: {}",
+                                    peer.name,
+                                    stmt
+                                );
+                                peer_postgres::fetch::pg_query_chunked(
+                                    &peer.name, c, query, fetch_size,
+                                )
+                                .await?
+                            };
+                            let stream =
+                                self.observe_stream(stream, Some(&peer.name), &stmt, started);
+                            let encode_options = *self.encode_options.lock().await;
+                            let res = sendable_stream_to_query_response(
+                                stream.schema(),
+                                stream,
+                                encode_options,
+                            )?;
+                            return Ok(vec![res]);
+                        }
+                    }
+                }
+
                 // get the query executor
                 let (peer_holder, executor): (Option<_>, Arc<dyn QueryExecutor>) = match assoc {
                     QueryAssociation::Peer(peer) => {
@@ -692,9 +1278,10 @@ impl NexusBackend {
                         (
                             Some(peer.clone()),
                             self.get_peer_executor(&peer).await.map_err(|err| {
-                                PgWireError::ApiError(
-                                    format!("unable to get peer executor: {:?}", err).into(),
-                                )
+                                PgWireError::from(PgError::new(
+                                    sqlstate::CONNECTION_FAILURE,
+                                    format!("unable to get peer executor: {:?}", err),
+                                ))
                             })?,
                         )
                     }
@@ -704,18 +1291,27 @@ impl NexusBackend {
                     }
                 };
 
+                let setting = self.check_session_setting(&stmt).await?;
+                let on_catalog = peer_holder.is_none();
                 let res = self
                     .execute_statement(executor.as_ref(), &stmt, peer_holder)
                     .await;
+                // tables only end up on the catalog without a default peer
+                let res = match res {
+                    Err(err) if on_catalog => Err(hint_default_peer(err)),
+                    res => res,
+                };
                 // log the error if execution failed
                 if let Err(err) = &res {
                     tracing::error!("query execution failed: {:?}", err);
+                } else {
+                    self.track_session_setting(&stmt, setting).await;
                 }
                 res
             }
 
             NexusStatement::PeerCursor { stmt, cursor } => {
-                let executor = {
+                let (peer_holder, executor) = {
                     let peer_cursors = self.peer_cursors.lock().await;
                     let peer = match cursor {
                         analyzer::CursorEvent::Fetch(c, _) => peer_cursors.get_peer(&c),
@@ -723,21 +1319,33 @@ impl NexusBackend {
                         analyzer::CursorEvent::Close(c) => peer_cursors.get_peer(&c),
                     };
                     match peer {
-                        None => self.catalog.clone(),
-                        Some(peer) => self.get_peer_executor(peer).await.map_err(|err| {
-                            PgWireError::ApiError(
-                                format!("unable to get peer executor: {:?}", err).into(),
-                            )
-                        })?,
+                        None => (None, self.catalog.clone()),
+                        Some(peer) => (
+                            Some(Box::new(peer.clone())),
+                            self.get_peer_executor(peer).await.map_err(|err| {
+                                PgWireError::from(PgError::new(
+                                    sqlstate::CONNECTION_FAILURE,
+                                    format!("unable to get peer executor: {:?}", err),
+                                ))
+                            })?,
+                        ),
                     }
                 };
 
-                self.execute_statement(executor.as_ref(), &stmt, None).await
+                // the peer only labels what the fetch sends, and how long it
+                // took
+                self.execute_statement(executor.as_ref(), &stmt, peer_holder)
+                    .await
             }
 
             NexusStatement::Rollback { stmt } => {
-                self.execute_statement(self.catalog.as_ref(), &stmt, None)
-                    .await
+                let res = self
+                    .execute_statement(self.catalog.as_ref(), &stmt, None)
+                    .await;
+                if res.is_ok() {
+                    self.track_session_setting(&stmt, None).await;
+                }
+                res
             }
 
             NexusStatement::Empty => Ok(vec![Response::EmptyQuery]),
@@ -774,6 +1382,8 @@ impl NexusBackend {
         Ok(match self.executors.entry(peer.name.clone()) {
             DashEntry::Occupied(entry) => Arc::clone(entry.get()),
             DashEntry::Vacant(entry) => {
+                // the catalog keeps secret references, resolve them to connect
+                let peer = pt::secrets::resolve_peer(peer)?;
                 let executor: Arc<dyn QueryExecutor> = match &peer.config {
                     Some(Config::BigqueryConfig(ref c)) => {
                         let executor = peer_bigquery::BigQueryQueryExecutor::new(
@@ -804,6 +1414,10 @@ impl NexusBackend {
                 };
 
                 entry.insert(Arc::clone(&executor));
+                self.metrics
+                    .peer_executors
+                    .with_label_values(&[peer.name.as_str()])
+                    .inc();
                 executor
             }
         })
@@ -817,38 +1431,54 @@ impl NexusBackend {
             NexusStatement::PeerCursor { .. } => Ok(None),
             NexusStatement::Empty => Ok(None),
             NexusStatement::Rollback { .. } => Ok(None),
+            NexusStatement::PeerQuery { stmt, .. } if is_show(stmt, "peerdb.streams") => {
+                Ok(Some(stream_progress_schema()))
+            }
+            NexusStatement::PeerQuery { stmt, .. } if is_show(stmt, DEFAULT_PEER_SETTING) => {
+                Ok(Some(default_peer_schema()))
+            }
             NexusStatement::PeerQuery { stmt, assoc } => {
+                if let ast::Statement::Query(query) = stmt {
+                    // the session doesn't change the columns of built-ins
+                    if let Some(records) = builtins::evaluate(query, &SessionInfo::default()) {
+                        return Ok(Some(records.schema));
+                    }
+                }
                 let schema: Option<Schema> = match assoc {
                     QueryAssociation::Peer(peer) => match &peer.config {
                         Some(Config::BigqueryConfig(_)) => {
                             let executor = self.get_peer_executor(peer).await.map_err(|err| {
-                                PgWireError::ApiError(
-                                    format!("unable to get peer executor: {:?}", err).into(),
-                                )
+                                PgWireError::from(PgError::new(
+                                    sqlstate::CONNECTION_FAILURE,
+                                    format!("unable to get peer executor: {:?}", err),
+                                ))
                             })?;
                             executor.describe(stmt).await?
                         }
                         Some(Config::MysqlConfig(_)) => {
                             let executor = self.get_peer_executor(peer).await.map_err(|err| {
-                                PgWireError::ApiError(
-                                    format!("unable to get peer executor: {:?}", err).into(),
-                                )
+                                PgWireError::from(PgError::new(
+                                    sqlstate::CONNECTION_FAILURE,
+                                    format!("unable to get peer executor: {:?}", err),
+                                ))
                             })?;
                             executor.describe(stmt).await?
                         }
                         Some(Config::PostgresConfig(_)) => {
                             let executor = self.get_peer_executor(peer).await.map_err(|err| {
-                                PgWireError::ApiError(
-                                    format!("unable to get peer executor: {:?}", err).into(),
-                                )
+                                PgWireError::from(PgError::new(
+                                    sqlstate::CONNECTION_FAILURE,
+                                    format!("unable to get peer executor: {:?}", err),
+                                ))
                             })?;
                             executor.describe(stmt).await?
                         }
                         Some(Config::SnowflakeConfig(_)) => {
                             let executor = self.get_peer_executor(peer).await.map_err(|err| {
-                                PgWireError::ApiError(
-                                    format!("unable to get peer executor: {:?}", err).into(),
-                                )
+                                PgWireError::from(PgError::new(
+                                    sqlstate::CONNECTION_FAILURE,
+                                    format!("unable to get peer executor: {:?}", err),
+                                ))
                             })?;
                             executor.describe(stmt).await?
                         }
@@ -865,19 +1495,98 @@ impl NexusBackend {
     }
 }
 
+/// A value of a session setting that nexus applies itself.
+enum SessionSetting {
+    IntervalStyle(IntervalStyle),
+    DateStyle(DateStyle),
+    DefaultPeer(Option<String>),
+    BatchBytes(Option<usize>),
+    ColumnCase(ColumnCase),
+    FetchSize(u32),
+    PartitionColumn(String),
+    Parallelism(usize),
+    OrderedPartitions(bool),
+}
+
+// whether `stmt` is a SHOW of `setting`, a setting nexus answers itself
+fn is_show(stmt: &ast::Statement, setting: &str) -> bool {
+    match stmt {
+        ast::Statement::ShowVariable { variable } => {
+            let name = variable
+                .iter()
+                .map(|ident| ident.value.to_lowercase())
+                .collect::<Vec<_>>();
+            name.join(".") == setting
+        }
+        _ => false,
+    }
+}
+
+// a table missing from the catalog is more likely one of a peer's, named
+// without the peer
+fn hint_default_peer(err: PgWireError) -> PgWireError {
+    match err {
+        PgWireError::UserError(mut info)
+            if info.code() == sqlstate::UNDEFINED_TABLE && info.hint().is_none() =>
+        {
+            info.set_hint(Some(format!(
+                "no default peer set: qualify the table with a peer name, or SET {} to a peer",
+                DEFAULT_PEER_SETTING
+            )));
+            PgWireError::UserError(info)
+        }
+        err => err,
+    }
+}
+
+fn default_peer_schema() -> Schema {
+    Arc::new(vec![FieldInfo::new(
+        DEFAULT_PEER_SETTING.to_string(),
+        None,
+        None,
+        Type::TEXT,
+        FieldFormat::Text,
+    )])
+}
+
+fn stream_progress_schema() -> Schema {
+    Arc::new(
+        [
+            ("query_id", Type::TEXT),
+            ("peer", Type::TEXT),
+            ("query", Type::TEXT),
+            ("rows", Type::INT8),
+            ("bytes", Type::INT8),
+            ("elapsed_ms", Type::INT8),
+            ("idle_ms", Type::INT8),
+        ]
+        .into_iter()
+        .map(|(name, ty)| FieldInfo::new(name.to_string(), None, None, ty, FieldFormat::Text))
+        .collect(),
+    )
+}
+
+impl Drop for NexusBackend {
+    fn drop(&mut self) {
+        for executor in self.executors.iter() {
+            self.metrics
+                .peer_executors
+                .with_label_values(&[executor.key().as_str()])
+                .dec();
+        }
+        let cursors = self.peer_cursors.get_mut().len();
+        self.metrics.open_cursors.sub(cursors as i64);
+    }
+}
+
 #[async_trait]
 impl SimpleQueryHandler for NexusBackend {
-    async fn do_query<'a, C>(
-        &self,
-        _client: &mut C,
-        sql: &'a str,
-    ) -> PgWireResult<Vec<Response<'a>>>
+    async fn do_query<'a, C>(&self, client: &mut C, sql: &'a str) -> PgWireResult<Vec<Response<'a>>>
     where
         C: ClientInfo + Unpin + Send + Sync,
     {
-        let parsed = self.query_parser.parse_simple_sql(sql).await?;
-        let nexus_stmt = parsed.statement;
-        self.handle_query(nexus_stmt).await
+        self.remember_client(client).await;
+        self.run_statement(sql, None).await
     }
 }
 
@@ -933,13 +1642,14 @@ impl ExtendedQueryHandler for NexusBackend {
 
     async fn do_query<'a, C>(
         &self,
-        _client: &mut C,
+        client: &mut C,
         portal: &'a Portal<Self::Statement>,
         _max_rows: usize,
     ) -> PgWireResult<Response<'a>>
     where
         C: ClientInfo + Unpin + Send + Sync,
     {
+        self.remember_client(client).await;
         let stmt = &portal.statement.statement;
         tracing::info!("[eqp] do_query: {}", stmt.query);
 
@@ -949,14 +1659,10 @@ impl ExtendedQueryHandler for NexusBackend {
             sql = sql.replace(&format!("${}", i + 1), &parameter_to_string(portal, i)?);
         }
 
-        let parsed = self.query_parser.parse_simple_sql(&sql).await?;
-        let nexus_stmt = parsed.statement;
-        let result = self.handle_query(nexus_stmt).await?;
-        if result.is_empty() {
-            Ok(Response::EmptyQuery)
-        } else {
-            Ok(result.into_iter().next().unwrap())
-        }
+        let result = self
+            .run_statement(&sql, Some(stmt.default_peer.clone()))
+            .await?;
+        Ok(result.into_iter().next().unwrap_or(Response::EmptyQuery))
     }
 
     async fn do_describe_portal<C>(
@@ -967,9 +1673,10 @@ impl ExtendedQueryHandler for NexusBackend {
     where
         C: ClientInfo + Unpin + Send + Sync,
     {
+        let column_case = self.encode_options.lock().await.column_case;
         Ok(
             if let Some(schema) = self.do_describe(&target.statement.statement).await? {
-                DescribePortalResponse::new((*schema).clone())
+                DescribePortalResponse::new((*with_column_case(schema, column_case)).clone())
             } else {
                 DescribePortalResponse::no_data()
             },
@@ -984,8 +1691,10 @@ impl ExtendedQueryHandler for NexusBackend {
     where
         C: ClientInfo + Unpin + Send + Sync,
     {
+        let column_case = self.encode_options.lock().await.column_case;
         Ok(
             if let Some(schema) = self.do_describe(&target.statement).await? {
+                let schema = with_column_case(schema, column_case);
                 DescribeStatementResponse::new(target.parameter_types.clone(), (*schema).clone())
             } else {
                 DescribeStatementResponse::no_data()
@@ -1057,6 +1766,19 @@ struct Args {
 
     #[clap(long, env = "PEERDB_FDW_MODE", default_value = "false")]
     peerdb_fwd_mode: String,
+
+    /// Seconds a query result may go without sending a row before a warning
+    /// is logged, `0` to never warn.
+    ///
+    /// Defaults to `60`.
+    #[clap(long, default_value_t = 60, env = "PEERDB_STREAM_STALL_TIMEOUT")]
+    stream_stall_timeout_secs: u64,
+
+    /// Port to serve prometheus metrics and health checks on.
+    ///
+    /// This is an optional parameter. If not provided, no metrics are served.
+    #[clap(long, env = "PEERDB_METRICS_PORT")]
+    metrics_port: Option<u16>,
 }
 
 // Get catalog config from args
@@ -1119,6 +1841,24 @@ fn setup_tracing(log_dir: Option<&str>) -> TracerGuards {
     }
 }
 
+// logs the streams that have not sent a row for `timeout`, every `timeout`
+async fn warn_stalled_streams(streams: Arc<ProgressRegistry>, timeout: Duration) {
+    let mut interval = tokio::time::interval(timeout);
+    loop {
+        interval.tick().await;
+        for progress in streams.stalled(timeout) {
+            let snapshot = progress.snapshot();
+            tracing::warn!(
+                "[{}] no rows sent for {:?} after {} rows: {}",
+                progress.query_id,
+                snapshot.idle,
+                snapshot.rows,
+                progress.query
+            );
+        }
+    }
+}
+
 async fn run_migrations<'a>(config: &CatalogConfig<'a>) -> anyhow::Result<()> {
     // retry connecting to the catalog 3 times with 30 seconds delay
     // if it fails, return an error
@@ -1175,6 +1915,28 @@ pub async fn main() -> anyhow::Result<()> {
         None
     };
 
+    let metrics = Arc::new(Metrics::new()?);
+    if let Some(port) = args.metrics_port {
+        let addr: SocketAddr = format!("{}:{}", args.host, port).parse()?;
+        let serve = metrics::serve(
+            addr,
+            metrics.clone(),
+            peer_conns.clone(),
+            flow_handler.clone(),
+        );
+        tokio::spawn(async move {
+            if let Err(err) = serve.await {
+                tracing::error!("metrics endpoint failed: {:?}", err);
+            }
+        });
+    }
+
+    let streams = Arc::new(ProgressRegistry::default());
+    if args.stream_stall_timeout_secs > 0 {
+        let timeout = Duration::from_secs(args.stream_stall_timeout_secs);
+        tokio::spawn(warn_stalled_streams(streams.clone(), timeout));
+    }
+
     let mut sigintstream = signal(SignalKind::interrupt()).expect("Failed to setup signal handler");
     loop {
         let (mut socket, _) = tokio::select! {
@@ -1183,6 +1945,8 @@ pub async fn main() -> anyhow::Result<()> {
         }?;
         let conn_flow_handler = flow_handler.clone();
         let conn_peer_conns = peer_conns.clone();
+        let conn_streams = streams.clone();
+        let conn_metrics = metrics.clone();
         let peerdb_fdw_mode = args.peerdb_fwd_mode == "true";
         let authenticator_ref = authenticator.make();
         let pg_config = catalog_config.to_postgres_config();
@@ -1198,15 +1962,20 @@ pub async fn main() -> anyhow::Result<()> {
                         tracker,
                         conn_flow_handler,
                         peerdb_fdw_mode,
+                        conn_streams,
+                        conn_metrics.clone(),
                     ));
-                    process_socket(
+                    conn_metrics.active_sessions.inc();
+                    let res = process_socket(
                         socket,
                         None,
                         authenticator_ref,
                         processor.clone(),
                         processor,
                     )
-                    .await
+                    .await;
+                    conn_metrics.active_sessions.dec();
+                    res
                 }
                 Err(e) => {
                     tracing::error!("Failed to connect to catalog: {}", e);
@@ -0,0 +1,231 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc, time::Duration};
+
+use analyzer::PeerDDL;
+use flow_rs::grpc::FlowGrpcClient;
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use peer_connections::PeerConnections;
+use peerdb_parser::NexusStatement;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use sqlparser::ast;
+use tokio::sync::Mutex;
+
+/// Metrics of the server and its peers. Labels are limited to peer names and
+/// statement classes, never the SQL itself, to keep their cardinality down.
+pub struct Metrics {
+    registry: Registry,
+    pub active_sessions: IntGauge,
+    /// Statements by class and status, `ok` or `error`.
+    pub statements: IntCounterVec,
+    /// Time until queries on a peer are done, their rows all sent, by peer
+    /// and class.
+    peer_query_duration: HistogramVec,
+    catalog_query_duration: Histogram,
+    /// Rows sent to clients, by peer.
+    pub rows_streamed: IntCounterVec,
+    /// Results dropped before the client read all of them, by peer.
+    pub streams_cancelled: IntCounterVec,
+    /// Peer executors held by the sessions, one per session and peer.
+    pub peer_executors: IntGaugeVec,
+    pub open_cursors: IntGauge,
+    postgres_connections: IntGauge,
+    catalog_pool_size: IntGauge,
+    catalog_pool_available: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new_custom(Some("nexus".to_string()), None)?;
+        let metrics = Self {
+            active_sessions: IntGauge::new("active_sessions", "Sessions connected to nexus")?,
+            statements: IntCounterVec::new(
+                Opts::new("statements_total", "Statements run, by class and status"),
+                &["class", "status"],
+            )?,
+            peer_query_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "peer_query_duration_seconds",
+                    "Time until a query on a peer was done, its rows all sent",
+                ),
+                &["peer", "class"],
+            )?,
+            catalog_query_duration: Histogram::with_opts(HistogramOpts::new(
+                "catalog_query_duration_seconds",
+                "Time until a query on the catalog was done, its rows all sent",
+            ))?,
+            rows_streamed: IntCounterVec::new(
+                Opts::new("rows_streamed_total", "Rows sent to clients"),
+                &["peer"],
+            )?,
+            streams_cancelled: IntCounterVec::new(
+                Opts::new(
+                    "streams_cancelled_total",
+                    "Results dropped before they were fully sent",
+                ),
+                &["peer"],
+            )?,
+            peer_executors: IntGaugeVec::new(
+                Opts::new("peer_executors", "Peer executors held by sessions"),
+                &["peer"],
+            )?,
+            open_cursors: IntGauge::new("open_cursors", "Cursors open on peers")?,
+            postgres_connections: IntGauge::new(
+                "postgres_connections",
+                "Connections open to postgres peers and the catalog, outside the catalog pool",
+            )?,
+            catalog_pool_size: IntGauge::new(
+                "catalog_pool_connections",
+                "Connections of the catalog pool",
+            )?,
+            catalog_pool_available: IntGauge::new(
+                "catalog_pool_available_connections",
+                "Idle connections of the catalog pool",
+            )?,
+            registry,
+        };
+
+        let r = &metrics.registry;
+        r.register(Box::new(metrics.active_sessions.clone()))?;
+        r.register(Box::new(metrics.statements.clone()))?;
+        r.register(Box::new(metrics.peer_query_duration.clone()))?;
+        r.register(Box::new(metrics.catalog_query_duration.clone()))?;
+        r.register(Box::new(metrics.rows_streamed.clone()))?;
+        r.register(Box::new(metrics.streams_cancelled.clone()))?;
+        r.register(Box::new(metrics.peer_executors.clone()))?;
+        r.register(Box::new(metrics.open_cursors.clone()))?;
+        r.register(Box::new(metrics.postgres_connections.clone()))?;
+        r.register(Box::new(metrics.catalog_pool_size.clone()))?;
+        r.register(Box::new(metrics.catalog_pool_available.clone()))?;
+        Ok(metrics)
+    }
+
+    /// Records how long a query took, on `peer` or on the catalog for
+    /// `None`.
+    pub fn observe_query(&self, peer: Option<&str>, class: &str, elapsed: Duration) {
+        let elapsed = elapsed.as_secs_f64();
+        match peer {
+            Some(peer) => self
+                .peer_query_duration
+                .with_label_values(&[peer, class])
+                .observe(elapsed),
+            None => self.catalog_query_duration.observe(elapsed),
+        }
+    }
+
+    fn encode(&self, peer_conns: &PeerConnections) -> prometheus::Result<Vec<u8>> {
+        self.postgres_connections
+            .set(postgres_connection::open_connections() as i64);
+        let status = peer_conns.status();
+        self.catalog_pool_size.set(status.size as i64);
+        self.catalog_pool_available.set(status.available as i64);
+
+        let mut buf = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// Class of a statement for the `class` label.
+pub fn statement_class(stmt: &NexusStatement) -> &'static str {
+    match stmt {
+        NexusStatement::PeerDDL { ddl, .. } => match ddl.as_ref() {
+            PeerDDL::CreatePeer { .. } => "create_peer",
+            PeerDDL::DropPeer { .. } => "drop_peer",
+            _ => "mirror",
+        },
+        NexusStatement::PeerQuery { stmt, .. } => query_class(stmt),
+        NexusStatement::PeerCursor { .. } => "cursor",
+        NexusStatement::Rollback { .. } => "transaction",
+        NexusStatement::Empty => "empty",
+    }
+}
+
+/// Class of a statement run on a peer or the catalog.
+pub fn query_class(stmt: &ast::Statement) -> &'static str {
+    match stmt {
+        ast::Statement::Query(_) => "select",
+        ast::Statement::Insert { .. } => "insert",
+        ast::Statement::Update { .. } => "update",
+        ast::Statement::Delete { .. } => "delete",
+        ast::Statement::SetVariable { .. } | ast::Statement::ShowVariable { .. } => "setting",
+        ast::Statement::StartTransaction { .. }
+        | ast::Statement::Commit { .. }
+        | ast::Statement::Rollback { .. } => "transaction",
+        ast::Statement::Declare { .. } | ast::Statement::Fetch { .. } => "cursor",
+        _ => "other",
+    }
+}
+
+#[derive(Clone)]
+struct Endpoint {
+    metrics: Arc<Metrics>,
+    peer_conns: Arc<PeerConnections>,
+    flow_handler: Option<Arc<Mutex<FlowGrpcClient>>>,
+}
+
+impl Endpoint {
+    async fn handle(self, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let res = match (req.method(), req.uri().path()) {
+            (&Method::GET, "/metrics") => match self.metrics.encode(&self.peer_conns) {
+                Ok(body) => Response::builder()
+                    .header("Content-Type", TextEncoder::new().format_type())
+                    .body(Body::from(body)),
+                Err(err) => {
+                    tracing::error!("unable to encode metrics: {}", err);
+                    Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body(Body::from(err.to_string()))
+                }
+            },
+            (&Method::GET, "/healthz") => {
+                let mut unhealthy = Vec::new();
+                if !self.peer_conns.is_healthy().await {
+                    unhealthy.push("catalog");
+                }
+                if let Some(flow_handler) = &self.flow_handler {
+                    if !flow_handler.lock().await.is_healthy().await {
+                        unhealthy.push("flow");
+                    }
+                }
+                if unhealthy.is_empty() {
+                    Response::builder().body(Body::from("ok"))
+                } else {
+                    Response::builder()
+                        .status(StatusCode::SERVICE_UNAVAILABLE)
+                        .body(Body::from(format!("unhealthy: {}", unhealthy.join(", "))))
+                }
+            }
+            _ => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty()),
+        };
+        Ok(res.unwrap_or_else(|_| Response::new(Body::empty())))
+    }
+}
+
+/// Serves `/metrics` for prometheus and a `/healthz` that checks the catalog
+/// and, when configured, the flow server.
+pub async fn serve(
+    addr: SocketAddr,
+    metrics: Arc<Metrics>,
+    peer_conns: Arc<PeerConnections>,
+    flow_handler: Option<Arc<Mutex<FlowGrpcClient>>>,
+) -> anyhow::Result<()> {
+    let endpoint = Endpoint {
+        metrics,
+        peer_conns,
+        flow_handler,
+    };
+    let make_service = make_service_fn(move |_| {
+        let endpoint = endpoint.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| endpoint.clone().handle(req))) }
+    });
+    tracing::info!("Serving metrics on {}", addr);
+    Server::try_bind(&addr)?.serve(make_service).await?;
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use std::{
     fs::{read_dir, File},
     io::{prelude::*, BufReader, Write},
+    net::TcpStream,
     path::Path,
     process::Command,
     thread,
@@ -48,8 +49,14 @@ struct PeerDBServer {
 
 impl PeerDBServer {
     fn new() -> Self {
+        Self::with_env(&[])
+    }
+
+    // starts the server with `envs` on top of the test's own environment
+    fn with_env(envs: &[(&str, &str)]) -> Self {
         let mut server_start = Command::new("cargo");
         server_start.envs(std::env::vars());
+        server_start.envs(envs.iter().copied());
         server_start.args(["run"]);
         tracing::info!("Starting server...");
 
@@ -199,6 +206,132 @@ fn extended_query_protocol_no_params_catalog() {
     assert!(res > 0);
 }
 
+#[test]
+#[ignore = "create peers needs flow api"]
+fn create_peer_if_not_exists_keeps_existing_peer() {
+    let server = PeerDBServer::new();
+    let mut client = server.connect_dying();
+    create_peers::create_pg::create(&mut client);
+
+    // a different definition for an existing peer is neither validated nor
+    // applied, so an unreachable host is accepted and the peer keeps working.
+    let res = client.simple_query(
+        "CREATE PEER IF NOT EXISTS pg_test FROM POSTGRES WITH
+        (host = 'nexus.invalid', port = '5432', user = 'u', password = 'p', database = 'd');",
+    );
+    assert!(res.is_ok(), "{:?}", res);
+    let res = client.simple_query("SELECT COUNT(*) FROM pg_test.test.test_table;");
+    assert!(res.is_ok(), "{:?}", res);
+}
+
+#[test]
+#[ignore = "create peers needs flow api"]
+fn default_peer_routes_bare_tables() {
+    let server = PeerDBServer::new();
+    let mut client = server.connect_dying();
+    create_peers::create_pg::create(&mut client);
+
+    let res = client.simple_query("SET peerdb.default_peer = 'no_such_peer';");
+    assert!(res.is_err(), "unknown default peer should be rejected");
+
+    client
+        .batch_execute("SET peerdb.default_peer = 'pg_test';")
+        .expect("setting the default peer should succeed");
+    let default_peer: String = client
+        .query_one("SHOW peerdb.default_peer", &[])
+        .expect("SHOW should succeed")
+        .get(0);
+    assert_eq!(default_peer, "pg_test");
+
+    // prepared with pg_test as the default peer, it keeps running there
+    let stmt = client
+        .prepare("SELECT COUNT(*) FROM test.test_table")
+        .expect("bare table should be routed to the default peer");
+    client
+        .batch_execute("SET peerdb.default_peer = '';")
+        .expect("unsetting the default peer should succeed");
+    client
+        .query_one(&stmt, &[])
+        .expect("prepared statement should keep its default peer");
+
+    let err = client
+        .simple_query("SELECT COUNT(*) FROM test.test_table;")
+        .expect_err("bare table without a default peer should be looked up in the catalog");
+    let hint = err.as_db_error().and_then(|e| e.hint()).unwrap_or_default();
+    assert!(hint.contains("no default peer set"), "{:?}", err);
+}
+
+#[test]
+#[ignore = "create peers needs flow api"]
+fn create_peer_if_not_exists_fails_on_other_options() {
+    let server = PeerDBServer::new();
+    let mut client = server.connect_dying();
+    create_peers::create_pg::create(&mut client);
+
+    let create = |database: &str| {
+        format!(
+            "CREATE PEER IF NOT EXISTS pg_test FROM POSTGRES WITH (host = '{}', port = '{}', \
+            user = '{}', password = '{}', database = '{}');",
+            std::env::var("PEERDB_CATALOG_HOST").unwrap(),
+            std::env::var("PEERDB_CATALOG_PORT").unwrap(),
+            std::env::var("PEERDB_CATALOG_USER").unwrap(),
+            std::env::var("PEERDB_CATALOG_PASSWORD").unwrap(),
+            database,
+        )
+    };
+    let database = std::env::var("PEERDB_CATALOG_DATABASE").unwrap();
+    client
+        .batch_execute(&create(&database))
+        .expect("the same peer should be skipped");
+
+    let err = client
+        .batch_execute(&create("nexus_other_database"))
+        .expect_err("a peer with other options should be rejected");
+    let code = err.code().map(|code| code.code()).unwrap_or_default();
+    assert_eq!(code, "42710", "{:?}", err);
+}
+
+#[test]
+#[ignore = "create peers needs flow api"]
+fn jsonb_null_differs_from_sql_null() {
+    let server = PeerDBServer::new();
+    let mut client = server.connect_dying();
+    create_peers::create_pg::create(&mut client);
+
+    // written on the peer itself, which pg_test points at
+    let var = |name: &str| std::env::var(name).unwrap_or_else(|_| panic!("{} not set", name));
+    let mut peer = Client::connect(
+        &format!(
+            "postgresql://{}:{}@{}:{}/{}",
+            var("PEERDB_CATALOG_USER"),
+            var("PEERDB_CATALOG_PASSWORD"),
+            var("PEERDB_CATALOG_HOST"),
+            var("PEERDB_CATALOG_PORT"),
+            var("PEERDB_CATALOG_DATABASE"),
+        ),
+        NoTls,
+    )
+    .expect("failed to connect to pg peer");
+    peer.batch_execute(
+        "DROP TABLE IF EXISTS public.jsonb_nulls;
+        CREATE TABLE public.jsonb_nulls (id int, v jsonb);
+        INSERT INTO public.jsonb_nulls VALUES (1, NULL), (2, 'null'::jsonb);",
+    )
+    .expect("failed to create jsonb_nulls");
+
+    let res = client
+        .simple_query("SELECT id, v FROM pg_test.public.jsonb_nulls ORDER BY id;")
+        .expect("query on jsonb_nulls should succeed");
+    let values: Vec<Option<&str>> = res
+        .iter()
+        .filter_map(|msg| match msg {
+            SimpleQueryMessage::Row(row) => Some(row.get(1)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(values, [None, Some("null")]);
+}
+
 #[test]
 fn query_unknown_peer_doesnt_crash_server() {
     let server = PeerDBServer::new();
@@ -215,6 +348,126 @@ fn query_unknown_peer_doesnt_crash_server() {
     assert!(res.is_ok());
 }
 
+#[test]
+fn failing_statement_is_traceable_by_query_id() {
+    let server = PeerDBServer::new();
+    let mut client = server.connect_dying();
+
+    let err = client
+        .simple_query("SELECT * FROM nexus_no_such_table;")
+        .expect_err("query on a missing table should fail");
+    let detail = err
+        .as_db_error()
+        .and_then(|db| db.detail())
+        .expect("error should have a detail");
+    let query_id = detail
+        .lines()
+        .find_map(|line| line.strip_prefix("query_id: "))
+        .map(|id| id.split(',').next().unwrap().to_string())
+        .expect("detail should carry the query id");
+
+    // give the server a moment to write out its logs
+    thread::sleep(Duration::from_millis(500));
+    let logs = std::fs::read_to_string("server.log").expect("unable to read server.log");
+    let lifecycle: Vec<&str> = logs.lines().filter(|l| l.contains(&query_id)).collect();
+    assert!(lifecycle.iter().any(|l| l.contains("received statement")));
+    assert!(lifecycle.iter().any(|l| l.contains("statement failed")));
+}
+
+#[test]
+fn multi_statement_simple_query() {
+    let server = PeerDBServer::new();
+    let mut client = server.connect_dying();
+
+    // every statement of the message gets its own CommandComplete.
+    let messages = client
+        .simple_query("SELECT 1; SELECT * FROM peers; SELECT 2;")
+        .expect("multi statement query should succeed");
+    let completed = messages
+        .iter()
+        .filter(|m| matches!(m, SimpleQueryMessage::CommandComplete(_)))
+        .count();
+    assert_eq!(completed, 3);
+
+    // an error aborts the statements after it, and the connection stays usable.
+    let res = client.simple_query("SELECT 1; SELECT * FROM nexus_no_such_table; SELECT 2;");
+    assert!(res.is_err());
+    let res = client.simple_query("SELECT * FROM peers;");
+    assert!(res.is_ok());
+}
+
+#[test]
+fn multi_statement_simple_query_has_no_implicit_transaction() {
+    let server = PeerDBServer::new();
+    let mut client = server.connect_dying();
+
+    // the SET is kept although a later statement of the message fails.
+    let res = client.simple_query(
+        "SET peerdb.column_case = 'upper'; SELECT * FROM nexus_no_such_table; SELECT 2;",
+    );
+    assert!(res.is_err());
+    let row = client
+        .query_one("SELECT 1 AS one", &[])
+        .expect("query after the failed message should succeed");
+    assert_eq!(row.columns()[0].name(), "ONE");
+}
+
+#[test]
+fn prepared_statement_is_a_single_command() {
+    let server = PeerDBServer::new();
+    let mut client = server.connect_dying();
+
+    let err = client
+        .prepare("SELECT 1; SELECT 2;")
+        .expect_err("several commands should not be prepared");
+    let code = err.code().map(|code| code.code()).unwrap_or_default();
+    assert_eq!(code, "42601", "{:?}", err);
+
+    let row = client
+        .query_one("SELECT 1", &[])
+        .expect("a single command should be prepared");
+    assert_eq!(row.get::<_, i32>(0), 1);
+}
+
+#[test]
+fn interval_style_is_checked_before_it_is_set() {
+    let server = PeerDBServer::new();
+    let mut client = server.connect_dying();
+    let interval = |client: &mut Client| -> String {
+        let messages = client
+            .simple_query("SELECT interval '-1 day 02:00'")
+            .expect("interval query should succeed");
+        messages
+            .into_iter()
+            .find_map(|m| match m {
+                SimpleQueryMessage::Row(row) => row.get(0).map(String::from),
+                _ => None,
+            })
+            .expect("interval query should return a row")
+    };
+
+    // rejected by nexus before the catalog takes it
+    let res = client.simple_query("SET intervalstyle TO 'german'");
+    assert!(res.is_err());
+    let row = client
+        .query_one("SHOW intervalstyle", &[])
+        .expect("SHOW intervalstyle should succeed");
+    assert_eq!(row.get::<_, String>(0), "postgres");
+
+    client
+        .batch_execute("SET intervalstyle TO postgres_verbose")
+        .expect("postgres_verbose should be accepted");
+    assert_eq!(interval(&mut client), "@ 1 day -2 hours ago");
+    client
+        .batch_execute("RESET intervalstyle")
+        .expect("RESET should be accepted");
+    assert_eq!(interval(&mut client), "-1 days +02:00:00");
+    client
+        .batch_execute("SET intervalstyle TO DEFAULT")
+        .expect("DEFAULT should be accepted");
+    assert_eq!(interval(&mut client), "-1 days +02:00:00");
+}
+
 #[test]
 #[ignore = "requires some work for extended query prepares on bigquery."]
 fn extended_query_protocol_no_params_bq() {
@@ -234,3 +487,96 @@ fn extended_query_protocol_no_params_bq() {
     // check that the result is non-empty.
     assert!(res > 0);
 }
+
+#[test]
+fn builtin_functions_are_answered_by_nexus() {
+    let server = PeerDBServer::new();
+    let mut client = server.connect_dying();
+
+    let row = client
+        .query_one(
+            "SELECT version(), peerdb_version(), current_user, current_database() AS db",
+            &[],
+        )
+        .expect("built-in functions should be evaluated");
+    let version: String = row.get("version");
+    let peerdb_version: String = row.get("peerdb_version");
+    assert!(version.starts_with("PostgreSQL 14"), "{}", version);
+    assert!(version.contains(&peerdb_version), "{}", version);
+    assert_eq!(row.get::<_, String>("current_user"), "peerdb");
+    assert_eq!(row.get::<_, String>("db"), "peerdb");
+
+    let row = client
+        .query_one("SELECT 'nexus ' || peerdb_version(), pg_backend_pid()", &[])
+        .expect("built-in functions should be evaluated in expressions");
+    assert!(row.get::<_, String>(0).starts_with("nexus "));
+}
+
+#[test]
+fn void_function_returns_empty_value() {
+    let server = PeerDBServer::new();
+    let mut client = server.connect_dying();
+
+    let stmt = client
+        .prepare("SELECT pg_sleep(0)")
+        .expect("void function should describe");
+    assert_eq!(stmt.columns()[0].type_(), &postgres::types::Type::VOID);
+
+    let res = client
+        .simple_query("SELECT pg_sleep(0)")
+        .expect("void function should run");
+    let row = res
+        .iter()
+        .find_map(|msg| match msg {
+            SimpleQueryMessage::Row(row) => Some(row),
+            _ => None,
+        })
+        .expect("void function should return a row");
+    assert_eq!(row.get(0), Some(""));
+}
+
+// the body of an http GET of `path` on the metrics port
+fn http_get(port: u16, path: &str) -> String {
+    let mut stream = TcpStream::connect(("localhost", port)).expect("unable to connect");
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn metrics_endpoint_counts_statements() {
+    let server = PeerDBServer::with_env(&[("PEERDB_METRICS_PORT", "9901")]);
+    let mut client = server.connect_dying();
+    client
+        .simple_query("SELECT * FROM peers;")
+        .expect("catalog query should succeed");
+
+    let metrics = http_get(9901, "/metrics");
+    assert!(metrics.starts_with("HTTP/1.1 200"), "{}", metrics);
+    assert!(
+        metrics.contains(r#"nexus_statements_total{class="select",status="ok"}"#),
+        "{}",
+        metrics
+    );
+    assert!(metrics.contains("nexus_active_sessions"), "{}", metrics);
+    // timed once its rows were all sent
+    assert!(
+        metrics.contains("nexus_catalog_query_duration_seconds_count 1"),
+        "{}",
+        metrics
+    );
+    assert!(
+        metrics.contains("nexus_postgres_connections"),
+        "{}",
+        metrics
+    );
+
+    let health = http_get(9901, "/healthz");
+    assert!(health.starts_with("HTTP/1.1 200"), "{}", health);
+}
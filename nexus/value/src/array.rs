@@ -5,14 +5,17 @@ use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use pgwire::types::ToSqlText;
 use postgres_types::{IsNull, ToSql, Type};
 
+use crate::{interval::Interval, timetz::TimeTz, Value};
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum ArrayValue {
     Empty,
-    Bool(Vec<bool>),
+    Bool(Vec<Option<bool>>),
     TinyInt(Vec<i8>),
     SmallInt(Vec<i16>),
     Integer(Vec<i32>),
     BigInt(Vec<i64>),
+    Oid(Vec<u32>),
     Float(Vec<f32>),
     Double(Vec<f64>),
     Numeric(Vec<String>),
@@ -22,19 +25,149 @@ pub enum ArrayValue {
     Binary(Vec<Bytes>),
     VarBinary(Vec<Bytes>),
     Date(Vec<NaiveDate>),
-    Time(Vec<NaiveTime>),
-    TimeWithTimeZone(Vec<NaiveTime>),
+    Time(Vec<Option<NaiveTime>>),
+    TimeTz(Vec<Option<TimeTz>>),
     Timestamp(Vec<DateTime<Utc>>),
     TimestampWithTimeZone(Vec<DateTime<Utc>>),
+    Interval(Vec<Option<Interval>>),
 }
 
 impl ArrayValue {
+    /// Name of the array's type, see `Value::type_name`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ArrayValue::Empty => "array",
+            ArrayValue::Bool(_) => "boolean[]",
+            ArrayValue::TinyInt(_) => "tinyint[]",
+            ArrayValue::SmallInt(_) => "smallint[]",
+            ArrayValue::Integer(_) => "integer[]",
+            ArrayValue::BigInt(_) => "bigint[]",
+            ArrayValue::Oid(_) => "oid[]",
+            ArrayValue::Float(_) => "real[]",
+            ArrayValue::Double(_) => "double precision[]",
+            ArrayValue::Numeric(_) => "numeric[]",
+            ArrayValue::Char(_) => "char[]",
+            ArrayValue::VarChar(_) => "varchar[]",
+            ArrayValue::Text(_) => "text[]",
+            ArrayValue::Binary(_) => "binary[]",
+            ArrayValue::VarBinary(_) => "bytea[]",
+            ArrayValue::Date(_) => "date[]",
+            ArrayValue::Time(_) => "time[]",
+            ArrayValue::TimeTz(_) => "timetz[]",
+            ArrayValue::Timestamp(_) => "timestamp[]",
+            ArrayValue::TimestampWithTimeZone(_) => "timestamptz[]",
+            ArrayValue::Interval(_) => "interval[]",
+        }
+    }
+
+    /// Bytes allocated for the elements of the array, see `Value::heap_size`.
+    pub fn heap_size(&self) -> usize {
+        fn vec_size<T>(v: &Vec<T>) -> usize {
+            v.capacity() * std::mem::size_of::<T>()
+        }
+
+        match self {
+            ArrayValue::Empty => 0,
+            ArrayValue::Bool(arr) => vec_size(arr),
+            ArrayValue::TinyInt(arr) => vec_size(arr),
+            ArrayValue::SmallInt(arr) => vec_size(arr),
+            ArrayValue::Integer(arr) => vec_size(arr),
+            ArrayValue::BigInt(arr) => vec_size(arr),
+            ArrayValue::Oid(arr) => vec_size(arr),
+            ArrayValue::Float(arr) => vec_size(arr),
+            ArrayValue::Double(arr) => vec_size(arr),
+            ArrayValue::Char(arr) => vec_size(arr),
+            ArrayValue::Numeric(arr) | ArrayValue::VarChar(arr) | ArrayValue::Text(arr) => {
+                vec_size(arr) + arr.iter().map(String::capacity).sum::<usize>()
+            }
+            ArrayValue::Binary(arr) | ArrayValue::VarBinary(arr) => {
+                vec_size(arr) + arr.iter().map(Bytes::len).sum::<usize>()
+            }
+            ArrayValue::Date(arr) => vec_size(arr),
+            ArrayValue::Time(arr) => vec_size(arr),
+            ArrayValue::TimeTz(arr) => vec_size(arr),
+            ArrayValue::Timestamp(arr) | ArrayValue::TimestampWithTimeZone(arr) => vec_size(arr),
+            ArrayValue::Interval(arr) => vec_size(arr),
+        }
+    }
+
+    /// Length in bytes of the elements' data, see `Value::byte_len`. NULL
+    /// elements take none.
+    pub fn byte_len(&self) -> usize {
+        fn sum<T>(arr: &[T], len: impl Fn(&T) -> usize) -> usize {
+            arr.iter().map(len).sum()
+        }
+
+        match self {
+            ArrayValue::Empty => 0,
+            ArrayValue::Bool(arr) => arr.iter().flatten().count(),
+            ArrayValue::TinyInt(arr) => arr.len(),
+            ArrayValue::SmallInt(arr) => arr.len() * 2,
+            ArrayValue::Integer(arr) => arr.len() * 4,
+            ArrayValue::Oid(arr) => arr.len() * 4,
+            ArrayValue::Float(arr) => arr.len() * 4,
+            ArrayValue::Date(arr) => arr.len() * 4,
+            ArrayValue::BigInt(arr) => arr.len() * 8,
+            ArrayValue::Double(arr) => arr.len() * 8,
+            ArrayValue::Time(arr) => arr.iter().flatten().count() * 8,
+            ArrayValue::TimeTz(arr) => arr.iter().flatten().count() * 12,
+            ArrayValue::Timestamp(arr) | ArrayValue::TimestampWithTimeZone(arr) => arr.len() * 8,
+            ArrayValue::Interval(arr) => arr.iter().flatten().count() * 16,
+            ArrayValue::Char(arr) => sum(arr, |c| c.len_utf8()),
+            ArrayValue::Numeric(arr) | ArrayValue::VarChar(arr) | ArrayValue::Text(arr) => {
+                sum(arr, String::len)
+            }
+            ArrayValue::Binary(arr) | ArrayValue::VarBinary(arr) => sum(arr, |b| b.len()),
+        }
+    }
+
+    /// The array as JSON, each element as `Value::as_json` writes it.
+    pub fn as_json(&self) -> serde_json::Value {
+        fn elements<T: Clone>(arr: &[T], f: impl Fn(T) -> Value) -> serde_json::Value {
+            serde_json::Value::Array(arr.iter().map(|v| f(v.clone()).as_json()).collect())
+        }
+
+        fn nullable_elements<T: Clone>(
+            arr: &[Option<T>],
+            f: impl Fn(T) -> Value,
+        ) -> serde_json::Value {
+            elements(arr, |v| v.map_or(Value::Null, &f))
+        }
+
+        match self {
+            ArrayValue::Empty => serde_json::Value::Array(Vec::new()),
+            ArrayValue::Bool(arr) => nullable_elements(arr, Value::Bool),
+            ArrayValue::TinyInt(arr) => elements(arr, Value::TinyInt),
+            ArrayValue::SmallInt(arr) => elements(arr, Value::SmallInt),
+            ArrayValue::Integer(arr) => elements(arr, Value::Integer),
+            ArrayValue::BigInt(arr) => elements(arr, Value::BigInt),
+            ArrayValue::Oid(arr) => elements(arr, Value::Oid),
+            ArrayValue::Float(arr) => elements(arr, Value::Float),
+            ArrayValue::Double(arr) => elements(arr, Value::Double),
+            // numeric elements are kept as their decimal text
+            ArrayValue::Numeric(arr) => elements(arr, Value::Text),
+            ArrayValue::Char(arr) => elements(arr, Value::Char),
+            ArrayValue::VarChar(arr) => elements(arr, Value::VarChar),
+            ArrayValue::Text(arr) => elements(arr, Value::Text),
+            ArrayValue::Binary(arr) => elements(arr, Value::Binary),
+            ArrayValue::VarBinary(arr) => elements(arr, Value::VarBinary),
+            ArrayValue::Date(arr) => elements(arr, Value::Date),
+            ArrayValue::Time(arr) => nullable_elements(arr, Value::Time),
+            ArrayValue::TimeTz(arr) => nullable_elements(arr, |t| Value::Text(t.to_string())),
+            ArrayValue::Timestamp(arr) => elements(arr, Value::Timestamp),
+            ArrayValue::TimestampWithTimeZone(arr) => elements(arr, Value::TimestampWithTimeZone),
+            ArrayValue::Interval(arr) => nullable_elements(arr, Value::Interval),
+        }
+    }
+
     pub fn to_serde_json_value(&self) -> serde_json::Value {
         match self {
             ArrayValue::Empty => serde_json::Value::Null,
-            ArrayValue::Bool(arr) => {
-                serde_json::Value::Array(arr.iter().map(|&v| serde_json::Value::Bool(v)).collect())
-            }
+            ArrayValue::Bool(arr) => serde_json::Value::Array(
+                arr.iter()
+                    .map(|v| v.map_or(serde_json::Value::Null, serde_json::Value::Bool))
+                    .collect(),
+            ),
             ArrayValue::TinyInt(arr) => serde_json::Value::Array(
                 arr.iter()
                     .map(|&v| serde_json::Value::Number(v.into()))
@@ -57,6 +190,11 @@ impl ArrayValue {
                     })
                     .collect(),
             ),
+            ArrayValue::Oid(arr) => serde_json::Value::Array(
+                arr.iter()
+                    .map(|&v| serde_json::Value::Number(v.into()))
+                    .collect(),
+            ),
             ArrayValue::Float(arr) => serde_json::Value::Array(
                 arr.iter()
                     .map(|&v| {
@@ -101,12 +239,12 @@ impl ArrayValue {
             ),
             ArrayValue::Time(arr) => serde_json::Value::Array(
                 arr.iter()
-                    .map(|&v| serde_json::Value::String(v.to_string()))
+                    .map(|v| v.map_or(serde_json::Value::Null, |v| v.to_string().into()))
                     .collect(),
             ),
-            ArrayValue::TimeWithTimeZone(arr) => serde_json::Value::Array(
+            ArrayValue::TimeTz(arr) => serde_json::Value::Array(
                 arr.iter()
-                    .map(|&v| serde_json::Value::String(v.to_string()))
+                    .map(|v| v.map_or(serde_json::Value::Null, |v| v.to_string().into()))
                     .collect(),
             ),
             ArrayValue::Timestamp(arr) => serde_json::Value::Array(
@@ -124,6 +262,15 @@ impl ArrayValue {
                     .map(|v| serde_json::Value::String(v.clone()))
                     .collect(),
             ),
+            ArrayValue::Interval(arr) => serde_json::Value::Array(
+                arr.iter()
+                    .map(|v| {
+                        v.map_or(serde_json::Value::Null, |v| {
+                            Value::Interval(v).to_serde_json_value()
+                        })
+                    })
+                    .collect(),
+            ),
         }
     }
 }
@@ -140,6 +287,7 @@ impl ToSql for ArrayValue {
             ArrayValue::SmallInt(arr) => arr.to_sql(ty, out)?,
             ArrayValue::Integer(arr) => arr.to_sql(ty, out)?,
             ArrayValue::BigInt(arr) => arr.to_sql(ty, out)?,
+            ArrayValue::Oid(arr) => arr.to_sql(ty, out)?,
             ArrayValue::Float(arr) => arr.to_sql(ty, out)?,
             ArrayValue::Double(arr) => arr.to_sql(ty, out)?,
             ArrayValue::Numeric(arr) => arr.to_sql(ty, out)?,
@@ -153,9 +301,10 @@ impl ToSql for ArrayValue {
             ArrayValue::VarBinary(_arr) => todo!("support encoding array of varbinary"),
             ArrayValue::Date(arr) => arr.to_sql(ty, out)?,
             ArrayValue::Time(arr) => arr.to_sql(ty, out)?,
-            ArrayValue::TimeWithTimeZone(arr) => arr.to_sql(ty, out)?,
+            ArrayValue::TimeTz(arr) => arr.to_sql(ty, out)?,
             ArrayValue::Timestamp(arr) => arr.to_sql(ty, out)?,
             ArrayValue::TimestampWithTimeZone(arr) => arr.to_sql(ty, out)?,
+            ArrayValue::Interval(arr) => arr.to_sql(ty, out)?,
             ArrayValue::Empty => IsNull::Yes,
         };
 
@@ -169,6 +318,8 @@ impl ToSql for ArrayValue {
                 | Type::INT2_ARRAY
                 | Type::INT4_ARRAY
                 | Type::INT8_ARRAY
+                | Type::OID_ARRAY
+                | Type::OID_VECTOR
                 | Type::FLOAT4_ARRAY
                 | Type::FLOAT8_ARRAY
                 | Type::NUMERIC_ARRAY
@@ -181,6 +332,7 @@ impl ToSql for ArrayValue {
                 | Type::TIMETZ_ARRAY
                 | Type::TIMESTAMP_ARRAY
                 | Type::TIMESTAMPTZ_ARRAY
+                | Type::INTERVAL_ARRAY
         )
     }
 
@@ -206,21 +358,49 @@ macro_rules! array_to_sql_text {
     }};
 }
 
+// like array_to_sql_text, for arrays whose elements may be NULL
+macro_rules! nullable_array_to_sql_text {
+    ($arr:expr, $ty:expr, $out:expr) => {{
+        for v in $arr {
+            match v {
+                Some(v) => {
+                    v.to_sql_text($ty, $out)?;
+                }
+                None => $out.put_slice(b"NULL"),
+            }
+            $out.put_slice(b",");
+        }
+    }};
+}
+
 impl ToSqlText for ArrayValue {
     fn to_sql_text(
         &self,
         ty: &Type,
         out: &mut BytesMut,
     ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        // oidvector is written as its space separated elements, without braces
+        if let (ArrayValue::Oid(arr), &Type::OID_VECTOR) = (self, ty) {
+            let oids: Vec<String> = arr.iter().map(u32::to_string).collect();
+            out.put_slice(oids.join(" ").as_bytes());
+            return Ok(IsNull::No);
+        }
+
         // We start array values with '{'
         out.put_slice(b"{");
 
         match self {
-            ArrayValue::Bool(arr) => array_to_sql_text!(arr, ty, out),
+            ArrayValue::Bool(arr) => nullable_array_to_sql_text!(arr, ty, out),
             ArrayValue::TinyInt(arr) => array_to_sql_text!(arr, ty, out),
             ArrayValue::SmallInt(arr) => array_to_sql_text!(arr, ty, out),
             ArrayValue::Integer(arr) => array_to_sql_text!(arr, ty, out),
             ArrayValue::BigInt(arr) => array_to_sql_text!(arr, ty, out),
+            ArrayValue::Oid(arr) => {
+                for v in arr {
+                    out.put_slice(v.to_string().as_bytes());
+                    out.put_slice(b",");
+                }
+            }
             ArrayValue::Float(arr) => array_to_sql_text!(arr, ty, out),
             ArrayValue::Double(arr) => array_to_sql_text!(arr, ty, out),
             ArrayValue::Numeric(arr) => array_to_sql_text!(arr, ty, out),
@@ -230,10 +410,15 @@ impl ToSqlText for ArrayValue {
             ArrayValue::Binary(_arr) => todo!("implement encoding array of binary"),
             ArrayValue::VarBinary(_arr) => todo!("implement encoding array of varbinary"),
             ArrayValue::Date(arr) => array_to_sql_text!(arr, ty, out),
-            ArrayValue::Time(arr) => array_to_sql_text!(arr, ty, out),
-            ArrayValue::TimeWithTimeZone(arr) => array_to_sql_text!(arr, ty, out),
+            ArrayValue::Time(arr) => nullable_array_to_sql_text!(arr, ty, out),
+            ArrayValue::TimeTz(arr) => nullable_array_to_sql_text!(arr, ty, out),
             ArrayValue::Timestamp(arr) => array_to_sql_text!(arr, ty, out),
             ArrayValue::TimestampWithTimeZone(arr) => array_to_sql_text!(arr, ty, out),
+            // their text depends on the session's IntervalStyle, which only
+            // the encoder knows
+            ArrayValue::Interval(_) => {
+                return Err("interval arrays have no text form of their own".into())
+            }
             ArrayValue::Empty => {}
         }
 
@@ -0,0 +1,239 @@
+use std::borrow::Cow;
+
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use postgres_types::Type;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use uuid::Uuid;
+
+use crate::{kind::pg_type_to_value_kind, TypeTag, Value};
+
+impl Value {
+    /// Parses `text`, as postgres writes a value of type `ty`, into the kind
+    /// of value columns of `ty` are read into. Text is parsed like
+    /// `coerce_to` parses it, and bytea from its hex form.
+    pub fn parse(ty: &Type, text: &str) -> anyhow::Result<Value> {
+        match pg_type_to_value_kind(ty) {
+            TypeTag::VarBinary => {
+                let hex = text
+                    .strip_prefix("\\x")
+                    .with_context(|| format!("{:?} is not bytea in hex form", text))?;
+                Ok(Value::VarBinary(hex::decode(hex)?.into()))
+            }
+            kind => Value::Text(text.to_string())
+                .coerce_to(kind)
+                .with_context(|| format!("invalid input for type {}", ty)),
+        }
+    }
+
+    /// Converts the value into a value of type `tag`, as when rows of an
+    /// untyped source go to typed columns. Integers convert between widths
+    /// when they fit, numbers between kinds, timestamps between time zones,
+    /// and text parses into the type. Anything converts to text, in its JSON
+    /// form if it isn't text already, and to json. NULL stays NULL.
+    pub fn coerce_to(self, tag: TypeTag) -> anyhow::Result<Value> {
+        if self.is_null() {
+            return Ok(Value::Null);
+        }
+        if self.type_tag() == Some(tag) {
+            return Ok(self);
+        }
+        let from = self.type_name();
+        let fail = || anyhow::anyhow!("cannot coerce {} to {:?}", from, tag);
+        Ok(match tag {
+            TypeTag::Bool => match self.text().as_deref().map(str::trim) {
+                Some("t" | "true" | "yes" | "on" | "1") => Value::Bool(true),
+                Some("f" | "false" | "no" | "off" | "0") => Value::Bool(false),
+                _ => return Err(fail()),
+            },
+            TypeTag::TinyInt => Value::TinyInt(self.fit(tag)?),
+            TypeTag::SmallInt => Value::SmallInt(self.fit(tag)?),
+            TypeTag::Integer => Value::Integer(self.fit(tag)?),
+            TypeTag::BigInt => Value::BigInt(self.integer()?),
+            TypeTag::Oid => Value::Oid(self.fit(tag)?),
+            TypeTag::LargeObject => Value::LargeObject(self.fit(tag)?),
+            TypeTag::Float => Value::Float(self.float()? as f32),
+            TypeTag::Double => Value::Double(self.float()?),
+            TypeTag::Numeric => Value::Numeric(self.numeric()?),
+            TypeTag::Char => {
+                let text = self.text().ok_or_else(fail)?;
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Value::Char(c),
+                    _ => anyhow::bail!("{:?} is not a single character", text),
+                }
+            }
+            TypeTag::VarChar => Value::VarChar(self.into_text()),
+            TypeTag::Text => Value::Text(self.into_text()),
+            TypeTag::Enum => Value::Enum(self.text().ok_or_else(fail)?.into_owned()),
+            TypeTag::Binary | TypeTag::VarBinary => {
+                let bytes = match self {
+                    Value::Binary(b) | Value::VarBinary(b) => b,
+                    _ => return Err(fail()),
+                };
+                if tag == TypeTag::Binary {
+                    Value::Binary(bytes)
+                } else {
+                    Value::VarBinary(bytes)
+                }
+            }
+            TypeTag::Date => match self {
+                Value::Timestamp(ts) | Value::TimestampWithTimeZone(ts) => {
+                    Value::Date(ts.date_naive())
+                }
+                Value::PostgresTimestamp(ts) => Value::Date(ts.date()),
+                _ => Value::Date(self.parse_text(fail, |s| s.parse::<NaiveDate>())?),
+            },
+            TypeTag::Time => Value::Time(self.parse_text(fail, |s| s.parse::<NaiveTime>())?),
+            TypeTag::Timestamp => Value::Timestamp(self.timestamp(fail)?),
+            TypeTag::TimestampWithTimeZone => Value::TimestampWithTimeZone(self.timestamp(fail)?),
+            TypeTag::PostgresTimestamp => match self {
+                Value::Timestamp(ts) | Value::TimestampWithTimeZone(ts) => {
+                    Value::PostgresTimestamp(ts.naive_utc())
+                }
+                Value::Date(d) => Value::PostgresTimestamp(d.and_time(NaiveTime::MIN)),
+                _ => Value::PostgresTimestamp(self.parse_text(fail, parse_naive_timestamp)?),
+            },
+            TypeTag::Uuid => Value::Uuid(self.parse_text(fail, Uuid::parse_str)?),
+            TypeTag::Json | TypeTag::JsonB => {
+                let json = match self {
+                    Value::Json(j) | Value::JsonB(j) => j,
+                    Value::Text(_) | Value::TextBytes(_) | Value::VarChar(_) => {
+                        self.parse_text(fail, |s| serde_json::from_str::<serde_json::Value>(s))?
+                    }
+                    _ => self.to_serde_json_value(),
+                };
+                if tag == TypeTag::Json {
+                    Value::Json(json)
+                } else {
+                    Value::JsonB(json)
+                }
+            }
+            TypeTag::TimeWithTimeZone
+            | TypeTag::IpAddr
+            | TypeTag::Interval
+            | TypeTag::Array
+            | TypeTag::Hstore
+            | TypeTag::Range => return Err(fail()),
+        })
+    }
+
+    // the value as text, for the kinds of values that are text
+    fn text(&self) -> Option<Cow<'_, str>> {
+        match self {
+            Value::Text(s) | Value::VarChar(s) | Value::Enum(s) => Some(Cow::Borrowed(s)),
+            Value::TextBytes(b) => std::str::from_utf8(b).ok().map(Cow::Borrowed),
+            Value::Char(c) => Some(Cow::Owned(c.to_string())),
+            _ => None,
+        }
+    }
+
+    fn into_text(self) -> String {
+        match self {
+            Value::Text(s) | Value::VarChar(s) | Value::Enum(s) => s,
+            value => match value.to_serde_json_value() {
+                serde_json::Value::String(s) => s,
+                json => json.to_string(),
+            },
+        }
+    }
+
+    // parses the text of the value with `parse`, failing with `fail` if the
+    // value isn't text
+    fn parse_text<T, E>(
+        &self,
+        fail: impl Fn() -> anyhow::Error,
+        parse: impl Fn(&str) -> Result<T, E>,
+    ) -> anyhow::Result<T>
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let text = self.text().ok_or_else(fail)?;
+        parse(text.trim()).with_context(|| format!("cannot parse {:?}", text))
+    }
+
+    fn integer(&self) -> anyhow::Result<i64> {
+        match self {
+            Value::TinyInt(n) => Ok(*n as i64),
+            Value::SmallInt(n) => Ok(*n as i64),
+            Value::Integer(n) => Ok(*n as i64),
+            Value::BigInt(n) => Ok(*n),
+            Value::Oid(n) | Value::LargeObject(n) => Ok(*n as i64),
+            Value::Numeric(n) if n.fract().is_zero() => n
+                .to_i64()
+                .ok_or_else(|| anyhow::anyhow!("{} is out of range for an integer", n)),
+            Value::Float(_) | Value::Double(_) => {
+                let f = self.float()?;
+                if f.fract() != 0.0 || !(i64::MIN as f64..i64::MAX as f64).contains(&f) {
+                    anyhow::bail!("{} is not an integer", f);
+                }
+                Ok(f as i64)
+            }
+            _ => self.parse_text(
+                || anyhow::anyhow!("cannot coerce {} to an integer", self.type_name()),
+                |s| s.parse::<i64>(),
+            ),
+        }
+    }
+
+    // the value as an integer of the type `tag` stands for
+    fn fit<T: TryFrom<i64>>(&self, tag: TypeTag) -> anyhow::Result<T> {
+        let n = self.integer()?;
+        T::try_from(n).map_err(|_| anyhow::anyhow!("{} is out of range for {:?}", n, tag))
+    }
+
+    fn float(&self) -> anyhow::Result<f64> {
+        match self {
+            Value::Float(f) => Ok(*f as f64),
+            Value::Double(f) => Ok(*f),
+            Value::Numeric(n) => n
+                .to_f64()
+                .ok_or_else(|| anyhow::anyhow!("{} is out of range for a float", n)),
+            Value::TinyInt(_)
+            | Value::SmallInt(_)
+            | Value::Integer(_)
+            | Value::BigInt(_)
+            | Value::Oid(_)
+            | Value::LargeObject(_) => Ok(self.integer()? as f64),
+            _ => self.parse_text(
+                || anyhow::anyhow!("cannot coerce {} to a float", self.type_name()),
+                |s| s.parse::<f64>(),
+            ),
+        }
+    }
+
+    fn numeric(&self) -> anyhow::Result<Decimal> {
+        match self {
+            Value::Float(_) | Value::Double(_) => Ok(Decimal::try_from(self.float()?)?),
+            Value::TinyInt(_)
+            | Value::SmallInt(_)
+            | Value::Integer(_)
+            | Value::BigInt(_)
+            | Value::Oid(_)
+            | Value::LargeObject(_) => Ok(Decimal::from(self.integer()?)),
+            _ => self.parse_text(
+                || anyhow::anyhow!("cannot coerce {} to a numeric", self.type_name()),
+                |s| s.parse::<Decimal>(),
+            ),
+        }
+    }
+
+    fn timestamp(&self, fail: impl Fn() -> anyhow::Error) -> anyhow::Result<DateTime<Utc>> {
+        match self {
+            Value::Timestamp(ts) | Value::TimestampWithTimeZone(ts) => Ok(*ts),
+            // timestamps without a time zone are taken as UTC
+            Value::PostgresTimestamp(ts) => Ok(ts.and_utc()),
+            Value::Date(d) => Ok(d.and_time(NaiveTime::MIN).and_utc()),
+            _ => self.parse_text(fail, |s| {
+                DateTime::parse_from_rfc3339(s)
+                    .or_else(|_| DateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f%#z"))
+                    .map(|ts| ts.with_timezone(&Utc))
+            }),
+        }
+    }
+}
+
+fn parse_naive_timestamp(s: &str) -> chrono::ParseResult<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f"))
+}
@@ -0,0 +1,220 @@
+use postgres_types::Type;
+
+use crate::Value;
+
+/// A point of one of postgres' geometric types.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// An axis aligned box, with `low` holding the smaller coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub low: Point,
+    pub high: Point,
+}
+
+impl BoundingBox {
+    fn around(points: &[Point]) -> Option<Self> {
+        let (first, rest) = points.split_first()?;
+        let mut bbox = BoundingBox {
+            low: *first,
+            high: *first,
+        };
+        for p in rest {
+            bbox.low.x = bbox.low.x.min(p.x);
+            bbox.low.y = bbox.low.y.min(p.y);
+            bbox.high.x = bbox.high.x.max(p.x);
+            bbox.high.y = bbox.high.y.max(p.y);
+        }
+        Some(bbox)
+    }
+
+    /// Whether `p` lies inside the box or on its edges.
+    pub fn contains(&self, p: Point) -> bool {
+        self.low.x <= p.x && p.x <= self.high.x && self.low.y <= p.y && p.y <= self.high.y
+    }
+}
+
+/// A geometric value, parsed from the text postgres outputs for it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Geometry {
+    Point(Point),
+    /// The infinite line `a*x + b*y + c = 0`.
+    Line {
+        a: f64,
+        b: f64,
+        c: f64,
+    },
+    Lseg(Point, Point),
+    Box(BoundingBox),
+    Path {
+        points: Vec<Point>,
+        closed: bool,
+    },
+    Polygon(Vec<Point>),
+    Circle {
+        center: Point,
+        radius: f64,
+    },
+}
+
+impl Geometry {
+    /// Parses the text output of a value of geometric type `ty`, such as
+    /// `((0,0),(1,1),(2,0))` for a polygon.
+    pub fn parse(ty: &Type, text: &str) -> Option<Self> {
+        let text = text.trim();
+        match *ty {
+            Type::POINT => {
+                let [p] = points(text)?[..] else {
+                    return None;
+                };
+                Some(Geometry::Point(p))
+            }
+            Type::LINE => {
+                let inner = text.strip_prefix('{')?.strip_suffix('}')?;
+                let [a, b, c] = numbers(inner)?[..] else {
+                    return None;
+                };
+                Some(Geometry::Line { a, b, c })
+            }
+            Type::LSEG => {
+                let [p, q] = points(text)?[..] else {
+                    return None;
+                };
+                Some(Geometry::Lseg(p, q))
+            }
+            Type::BOX => {
+                let [p, q] = points(text)?[..] else {
+                    return None;
+                };
+                BoundingBox::around(&[p, q]).map(Geometry::Box)
+            }
+            Type::PATH => Some(Geometry::Path {
+                closed: !text.starts_with('['),
+                points: points(text)?,
+            }),
+            Type::POLYGON => Some(Geometry::Polygon(points(text)?)),
+            Type::CIRCLE => {
+                let inner = text.strip_prefix('<')?.strip_suffix('>')?;
+                let (center, radius) = inner.rsplit_once(',')?;
+                let [center] = points(center)?[..] else {
+                    return None;
+                };
+                Some(Geometry::Circle {
+                    center,
+                    radius: radius.trim().parse().ok()?,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// The smallest box holding the geometry, or None for an infinite line.
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        match self {
+            Geometry::Point(p) => BoundingBox::around(&[*p]),
+            Geometry::Line { .. } => None,
+            Geometry::Lseg(p, q) => BoundingBox::around(&[*p, *q]),
+            Geometry::Box(bbox) => Some(*bbox),
+            Geometry::Path { points, .. } | Geometry::Polygon(points) => {
+                BoundingBox::around(points)
+            }
+            Geometry::Circle { center, radius } => Some(BoundingBox {
+                low: Point {
+                    x: center.x - radius,
+                    y: center.y - radius,
+                },
+                high: Point {
+                    x: center.x + radius,
+                    y: center.y + radius,
+                },
+            }),
+        }
+    }
+
+    /// The geometry as JSON, `{"center": {"x": .., "y": ..}, "radius": ..}`
+    /// for a circle. None for the geometries without a JSON form.
+    pub fn to_json(&self) -> Option<serde_json::Value> {
+        match self {
+            Geometry::Circle { center, radius } => Some(serde_json::json!({
+                "center": { "x": center.x, "y": center.y },
+                "radius": radius,
+            })),
+            _ => None,
+        }
+    }
+
+    /// Whether `p` lies inside the geometry or on its boundary, for the
+    /// geometries that enclose an area.
+    pub fn contains(&self, p: Point) -> Option<bool> {
+        match self {
+            Geometry::Box(bbox) => Some(bbox.contains(p)),
+            Geometry::Circle { center, radius } => {
+                Some((p.x - center.x).hypot(p.y - center.y) <= *radius)
+            }
+            Geometry::Polygon(points) => Some(polygon_contains(points, p)),
+            _ => None,
+        }
+    }
+}
+
+// even-odd rule, counting crossings of a ray going right from p
+fn polygon_contains(points: &[Point], p: Point) -> bool {
+    if !BoundingBox::around(points).is_some_and(|bbox| bbox.contains(p)) {
+        return false;
+    }
+    let mut inside = false;
+    let mut prev = match points.last() {
+        Some(last) => *last,
+        None => return false,
+    };
+    for &cur in points {
+        if on_segment(prev, cur, p) {
+            return true;
+        }
+        if (cur.y > p.y) != (prev.y > p.y) {
+            let x = cur.x + (p.y - cur.y) * (prev.x - cur.x) / (prev.y - cur.y);
+            if p.x < x {
+                inside = !inside;
+            }
+        }
+        prev = cur;
+    }
+    inside
+}
+
+fn on_segment(a: Point, b: Point, p: Point) -> bool {
+    let cross = (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x);
+    cross == 0.0 && BoundingBox::around(&[a, b]).is_some_and(|bbox| bbox.contains(p))
+}
+
+// the points of a geometric value's text, ignoring the brackets around them
+fn points(text: &str) -> Option<Vec<Point>> {
+    let coords = numbers(&text.replace(['(', ')', '[', ']'], ""))?;
+    if coords.is_empty() || coords.len() % 2 != 0 {
+        return None;
+    }
+    Some(
+        coords
+            .chunks(2)
+            .map(|xy| Point { x: xy[0], y: xy[1] })
+            .collect(),
+    )
+}
+
+fn numbers(text: &str) -> Option<Vec<f64>> {
+    text.split(',').map(|n| n.trim().parse().ok()).collect()
+}
+
+impl Value {
+    /// Reads a geometric value of type `ty`, which decodes as its text output.
+    pub fn geometry(&self, ty: &Type) -> Option<Geometry> {
+        match self {
+            Value::Text(text) | Value::VarChar(text) => Geometry::parse(ty, text),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::hash::{Hash, Hasher};
+
+use crate::{
+    array::ArrayValue,
+    range::{Range, RangeBound},
+    Value,
+};
+
+// tags written ahead of each value, so values of different kinds with the
+// same content hash differently
+const NULL: u8 = 0;
+const BOOL: u8 = 1;
+const INT: u8 = 2;
+const FLOAT: u8 = 3;
+const NUMERIC: u8 = 4;
+const TEXT: u8 = 5;
+const BYTES: u8 = 6;
+const DATE: u8 = 7;
+const TIME: u8 = 8;
+const TIMESTAMP: u8 = 9;
+const INET: u8 = 10;
+const INTERVAL: u8 = 11;
+const ARRAY: u8 = 12;
+const JSON: u8 = 13;
+const UUID: u8 = 14;
+const HSTORE: u8 = 15;
+const RANGE: u8 = 16;
+
+impl Value {
+    /// Hashes the value as part of a key, such as the unique key columns of
+    /// an upsert. Values equal by `value_eq` hash alike. So do values that
+    /// only differ in how they were decoded: all integer widths, all text
+    /// kinds, and json whatever its key order or whether it was parsed.
+    /// Floats hash by their bit pattern and numerics by their normalized
+    /// digits, so `1.50` and `1.5` are the same key.
+    pub fn hash_key<H: Hasher>(&self, hasher: &mut H) {
+        match self {
+            Value::Null | Value::NullTyped(_) => NULL.hash(hasher),
+            Value::Bool(b) => (BOOL, b).hash(hasher),
+            Value::TinyInt(n) => (INT, *n as i64).hash(hasher),
+            Value::SmallInt(n) => (INT, *n as i64).hash(hasher),
+            Value::Oid(n) | Value::LargeObject(n) => (INT, *n as i64).hash(hasher),
+            Value::Integer(n) => (INT, *n as i64).hash(hasher),
+            Value::BigInt(n) => (INT, n).hash(hasher),
+            Value::Float(n) => (FLOAT, n.to_bits()).hash(hasher),
+            Value::Double(n) => (FLOAT, n.to_bits()).hash(hasher),
+            Value::Numeric(n) => (NUMERIC, n.normalize().to_string()).hash(hasher),
+            Value::Char(c) => {
+                let mut buf = [0; 4];
+                (TEXT, c.encode_utf8(&mut buf).as_bytes()).hash(hasher)
+            }
+            Value::VarChar(s) | Value::Text(s) | Value::Enum(s) => {
+                (TEXT, s.as_bytes()).hash(hasher)
+            }
+            Value::TextBytes(b) => (TEXT, b.as_ref()).hash(hasher),
+            Value::Binary(b) | Value::VarBinary(b) => (BYTES, b.as_ref()).hash(hasher),
+            Value::Date(d) => (DATE, d).hash(hasher),
+            Value::Time(t) | Value::TimeWithTimeZone(t) => (TIME, t).hash(hasher),
+            Value::Timestamp(ts) | Value::TimestampWithTimeZone(ts) => {
+                (TIMESTAMP, ts.naive_utc()).hash(hasher)
+            }
+            Value::PostgresTimestamp(ts) => (TIMESTAMP, ts).hash(hasher),
+            Value::IpAddr(ip) => (INET, ip.to_string()).hash(hasher),
+            Value::Interval(i) => (INTERVAL, i).hash(hasher),
+            Value::Array(arr) => {
+                (ARRAY, arr.type_name()).hash(hasher);
+                hash_array(arr, hasher);
+            }
+            Value::Json(j) | Value::JsonB(j) => {
+                JSON.hash(hasher);
+                hash_json(j, hasher);
+            }
+            Value::JsonText(_) | Value::JsonBText(_) => {
+                JSON.hash(hasher);
+                hash_json(&self.to_serde_json_value(), hasher);
+            }
+            Value::Uuid(u) => (UUID, u).hash(hasher),
+            Value::Hstore(map) => {
+                let mut entries = map.iter().collect::<Vec<_>>();
+                entries.sort();
+                (HSTORE, entries).hash(hasher);
+            }
+            Value::Range(range) => {
+                RANGE.hash(hasher);
+                match range.as_ref() {
+                    Range::Empty => 0u8.hash(hasher),
+                    Range::Bounds { lower, upper } => {
+                        1u8.hash(hasher);
+                        hash_bound(lower, hasher);
+                        hash_bound(upper, hasher);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn hash_bound<H: Hasher>(bound: &RangeBound, hasher: &mut H) {
+    match bound {
+        RangeBound::Inclusive(value) => {
+            0u8.hash(hasher);
+            value.hash_key(hasher);
+        }
+        RangeBound::Exclusive(value) => {
+            1u8.hash(hasher);
+            value.hash_key(hasher);
+        }
+        RangeBound::Unbounded => 2u8.hash(hasher),
+    }
+}
+
+fn hash_array<H: Hasher>(arr: &ArrayValue, hasher: &mut H) {
+    match arr {
+        ArrayValue::Empty => {}
+        ArrayValue::Bool(arr) => arr.hash(hasher),
+        ArrayValue::TinyInt(arr) => arr.hash(hasher),
+        ArrayValue::SmallInt(arr) => arr.hash(hasher),
+        ArrayValue::Integer(arr) => arr.hash(hasher),
+        ArrayValue::BigInt(arr) => arr.hash(hasher),
+        ArrayValue::Oid(arr) => arr.hash(hasher),
+        ArrayValue::Float(arr) => arr
+            .iter()
+            .map(|f| f.to_bits())
+            .collect::<Vec<_>>()
+            .hash(hasher),
+        ArrayValue::Double(arr) => arr
+            .iter()
+            .map(|f| f.to_bits())
+            .collect::<Vec<_>>()
+            .hash(hasher),
+        ArrayValue::Numeric(arr) | ArrayValue::VarChar(arr) | ArrayValue::Text(arr) => {
+            arr.hash(hasher)
+        }
+        ArrayValue::Char(arr) => arr.hash(hasher),
+        ArrayValue::Binary(arr) | ArrayValue::VarBinary(arr) => arr.hash(hasher),
+        ArrayValue::Date(arr) => arr.hash(hasher),
+        ArrayValue::Time(arr) => arr.hash(hasher),
+        ArrayValue::TimeTz(arr) => arr
+            .iter()
+            .map(|t| t.map(|t| (t.time, t.offset.local_minus_utc())))
+            .collect::<Vec<_>>()
+            .hash(hasher),
+        ArrayValue::Timestamp(arr) | ArrayValue::TimestampWithTimeZone(arr) => arr.hash(hasher),
+        ArrayValue::Interval(arr) => arr.hash(hasher),
+    }
+}
+
+// objects hash their entries in key order, as json equality ignores it
+fn hash_json<H: Hasher>(value: &serde_json::Value, hasher: &mut H) {
+    match value {
+        serde_json::Value::Null => 0u8.hash(hasher),
+        serde_json::Value::Bool(b) => (1u8, b).hash(hasher),
+        serde_json::Value::Number(n) => (2u8, n.to_string()).hash(hasher),
+        serde_json::Value::String(s) => (3u8, s).hash(hasher),
+        serde_json::Value::Array(values) => {
+            (4u8, values.len()).hash(hasher);
+            values.iter().for_each(|v| hash_json(v, hasher));
+        }
+        serde_json::Value::Object(map) => {
+            (5u8, map.len()).hash(hasher);
+            let mut entries = map.iter().collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            for (key, value) in entries {
+                key.hash(hasher);
+                hash_json(value, hasher);
+            }
+        }
+    }
+}
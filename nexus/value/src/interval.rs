@@ -0,0 +1,62 @@
+use std::error::Error;
+
+use bytes::{Buf, BufMut, BytesMut};
+use postgres_types::{accepts, to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+/// An `interval`, kept as the months, days and microseconds postgres stores
+/// it as. The parts don't convert into one another exactly, a month isn't a
+/// fixed number of days, and keeping them apart also keeps intervals of many
+/// millennia from overflowing a single count of microseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Interval {
+    pub months: i32,
+    pub days: i32,
+    pub microseconds: i64,
+}
+
+impl Interval {
+    pub fn from_micros(microseconds: i64) -> Self {
+        Interval {
+            months: 0,
+            days: 0,
+            microseconds,
+        }
+    }
+
+    /// The length of the interval in microseconds, taking a month as 30 days
+    /// and a day as 24 hours like postgres does to compare intervals.
+    pub fn total_micros(&self) -> i128 {
+        const MICROS_PER_DAY: i128 = 86_400_000_000;
+        (self.months as i128 * 30 + self.days as i128) * MICROS_PER_DAY + self.microseconds as i128
+    }
+}
+
+impl<'a> FromSql<'a> for Interval {
+    fn from_sql(_: &Type, mut raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        if raw.len() != 16 {
+            return Err("invalid interval".into());
+        }
+        let microseconds = raw.get_i64();
+        let days = raw.get_i32();
+        let months = raw.get_i32();
+        Ok(Interval {
+            months,
+            days,
+            microseconds,
+        })
+    }
+
+    accepts!(INTERVAL);
+}
+
+impl ToSql for Interval {
+    fn to_sql(&self, _: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        out.put_i64(self.microseconds);
+        out.put_i32(self.days);
+        out.put_i32(self.months);
+        Ok(IsNull::No)
+    }
+
+    accepts!(INTERVAL);
+    to_sql_checked!();
+}
@@ -0,0 +1,88 @@
+use postgres_types::{Kind, Type};
+
+use crate::TypeTag;
+
+/// The kind of value a postgres column of type `ty` is read into. Types the
+/// readers don't know are read as their text, and so are `Text`.
+pub fn pg_type_to_value_kind(ty: &Type) -> TypeTag {
+    match *ty {
+        Type::BOOL => TypeTag::Bool,
+        Type::CHAR => TypeTag::Char,
+        Type::INT2 => TypeTag::SmallInt,
+        Type::INT4
+        | Type::TID
+        | Type::XID
+        | Type::CID
+        | Type::PG_NDISTINCT
+        | Type::PG_DEPENDENCIES => TypeTag::Integer,
+        Type::INT8 => TypeTag::BigInt,
+        Type::OID => TypeTag::Oid,
+        Type::FLOAT4 => TypeTag::Float,
+        Type::FLOAT8 => TypeTag::Double,
+        Type::NUMERIC => TypeTag::Numeric,
+        Type::BYTEA => TypeTag::VarBinary,
+        Type::JSON => TypeTag::Json,
+        Type::JSONB => TypeTag::JsonB,
+        Type::UUID => TypeTag::Uuid,
+        Type::INET | Type::CIDR => TypeTag::IpAddr,
+        Type::DATE => TypeTag::Date,
+        Type::TIME => TypeTag::Time,
+        Type::TIMETZ => TypeTag::TimeWithTimeZone,
+        Type::TIMESTAMP => TypeTag::PostgresTimestamp,
+        Type::TIMESTAMPTZ => TypeTag::TimestampWithTimeZone,
+        Type::INTERVAL => TypeTag::Interval,
+        // geometric values and their arrays are kept as postgres writes them
+        Type::POINT
+        | Type::POINT_ARRAY
+        | Type::LINE
+        | Type::LINE_ARRAY
+        | Type::LSEG
+        | Type::LSEG_ARRAY
+        | Type::BOX
+        | Type::BOX_ARRAY
+        | Type::POLYGON
+        | Type::POLYGON_ARRAY
+        | Type::CIRCLE
+        | Type::CIRCLE_ARRAY => TypeTag::Text,
+        Type::OID_VECTOR | Type::ANYARRAY => TypeTag::Array,
+        Type::TS_RANGE | Type::TSTZ_RANGE | Type::DATE_RANGE => TypeTag::Range,
+        _ => match ty.kind() {
+            Kind::Array(_) => TypeTag::Array,
+            Kind::Enum(_) => TypeTag::Enum,
+            _ => TypeTag::Text,
+        },
+    }
+}
+
+/// The OID of the postgres type a value of `kind` is described as. Kinds
+/// without a type of their own, like enums and hstore whose OIDs differ from
+/// one database to the next, or ranges whose type depends on their bounds,
+/// are described as text, and arrays as arrays of text.
+pub fn value_kind_to_pg_oid(kind: TypeTag) -> u32 {
+    let ty = match kind {
+        TypeTag::Bool => Type::BOOL,
+        TypeTag::TinyInt | TypeTag::SmallInt => Type::INT2,
+        TypeTag::Oid | TypeTag::LargeObject => Type::OID,
+        TypeTag::Integer => Type::INT4,
+        TypeTag::BigInt => Type::INT8,
+        TypeTag::Float => Type::FLOAT4,
+        TypeTag::Double => Type::FLOAT8,
+        TypeTag::Numeric => Type::NUMERIC,
+        TypeTag::Char => Type::CHAR,
+        TypeTag::VarChar => Type::VARCHAR,
+        TypeTag::Text | TypeTag::Enum | TypeTag::Hstore | TypeTag::Range => Type::TEXT,
+        TypeTag::Binary | TypeTag::VarBinary => Type::BYTEA,
+        TypeTag::Date => Type::DATE,
+        TypeTag::Time => Type::TIME,
+        TypeTag::TimeWithTimeZone => Type::TIMETZ,
+        TypeTag::Timestamp | TypeTag::PostgresTimestamp => Type::TIMESTAMP,
+        TypeTag::TimestampWithTimeZone => Type::TIMESTAMPTZ,
+        TypeTag::IpAddr => Type::INET,
+        TypeTag::Interval => Type::INTERVAL,
+        TypeTag::Array => Type::TEXT_ARRAY,
+        TypeTag::Json => Type::JSON,
+        TypeTag::JsonB => Type::JSONB,
+        TypeTag::Uuid => Type::UUID,
+    };
+    ty.oid()
+}
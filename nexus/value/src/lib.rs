@@ -7,14 +7,31 @@ use std::collections::HashMap;
 use std::str::FromStr;
 use uuid::Uuid;
 pub mod array;
+pub mod coerce;
+pub mod geometry;
+pub mod hash;
+pub mod interval;
+pub mod kind;
+pub mod normalize;
+pub mod numeric;
+pub mod proto;
+pub mod range;
+pub mod timetz;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Value {
     Null,
+    /// NULL of a known type, for when the value travels without the schema
+    /// of its column, as in serialized records. Plain `Null` is the usual
+    /// NULL when the schema is at hand.
+    NullTyped(TypeTag),
     Bool(bool),
     TinyInt(i8),
     SmallInt(i16),
     Oid(u32),
+    /// OID of a large object, read from an oid column known to reference
+    /// one rather than as a plain `Oid`.
+    LargeObject(u32),
     Integer(i32),
     BigInt(i64),
     Float(f32),
@@ -23,6 +40,8 @@ pub enum Value {
     Char(char),
     VarChar(String),
     Text(String),
+    /// UTF-8 text sharing a buffer with the other cells of its row.
+    TextBytes(Bytes),
     Binary(Bytes),
     VarBinary(Bytes),
     Date(NaiveDate),
@@ -32,13 +51,54 @@ pub enum Value {
     PostgresTimestamp(NaiveDateTime),
     TimestampWithTimeZone(DateTime<Utc>),
     IpAddr(postgres_inet::MaskedIpAddr),
-    Interval(i64),
+    Interval(interval::Interval),
     Array(ArrayValue),
     Json(serde_json::Value),
     JsonB(serde_json::Value),
+    /// JSON kept as the text sent by the peer, parsed only on demand. It is
+    /// written to clients as is, not in the compact form of `Value::Json`.
+    JsonText(Bytes),
+    /// JSONB kept as the text sent by the peer, like `Value::JsonText`.
+    JsonBText(Bytes),
     Uuid(Uuid),
     Enum(String),
     Hstore(HashMap<String, String>),
+    Range(Box<range::Range>),
+}
+
+/// The type of a value without the value, see `Value::NullTyped`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TypeTag {
+    Bool,
+    TinyInt,
+    SmallInt,
+    Oid,
+    Integer,
+    BigInt,
+    Float,
+    Double,
+    Numeric,
+    Char,
+    VarChar,
+    Text,
+    Binary,
+    VarBinary,
+    Date,
+    Time,
+    TimeWithTimeZone,
+    Timestamp,
+    PostgresTimestamp,
+    TimestampWithTimeZone,
+    IpAddr,
+    Interval,
+    Array,
+    Json,
+    JsonB,
+    Uuid,
+    Enum,
+    Hstore,
+    Range,
+    LargeObject,
 }
 
 use std::fmt;
@@ -48,6 +108,55 @@ impl Value {
         Value::Null
     }
 
+    pub fn null_typed(tag: TypeTag) -> Self {
+        Value::NullTyped(tag)
+    }
+
+    /// Whether the value is NULL, typed or not.
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null | Value::NullTyped(_))
+    }
+
+    /// The type of the value, which a typed NULL keeps and a plain NULL
+    /// doesn't have. Borrowed text and json have the type of their owned
+    /// counterparts.
+    pub fn type_tag(&self) -> Option<TypeTag> {
+        Some(match self {
+            Value::Null => return None,
+            Value::NullTyped(tag) => *tag,
+            Value::Bool(_) => TypeTag::Bool,
+            Value::TinyInt(_) => TypeTag::TinyInt,
+            Value::SmallInt(_) => TypeTag::SmallInt,
+            Value::Oid(_) => TypeTag::Oid,
+            Value::LargeObject(_) => TypeTag::LargeObject,
+            Value::Integer(_) => TypeTag::Integer,
+            Value::BigInt(_) => TypeTag::BigInt,
+            Value::Float(_) => TypeTag::Float,
+            Value::Double(_) => TypeTag::Double,
+            Value::Numeric(_) => TypeTag::Numeric,
+            Value::Char(_) => TypeTag::Char,
+            Value::VarChar(_) => TypeTag::VarChar,
+            Value::Text(_) | Value::TextBytes(_) => TypeTag::Text,
+            Value::Binary(_) => TypeTag::Binary,
+            Value::VarBinary(_) => TypeTag::VarBinary,
+            Value::Date(_) => TypeTag::Date,
+            Value::Time(_) => TypeTag::Time,
+            Value::TimeWithTimeZone(_) => TypeTag::TimeWithTimeZone,
+            Value::Timestamp(_) => TypeTag::Timestamp,
+            Value::PostgresTimestamp(_) => TypeTag::PostgresTimestamp,
+            Value::TimestampWithTimeZone(_) => TypeTag::TimestampWithTimeZone,
+            Value::IpAddr(_) => TypeTag::IpAddr,
+            Value::Interval(_) => TypeTag::Interval,
+            Value::Array(_) => TypeTag::Array,
+            Value::Json(_) | Value::JsonText(_) => TypeTag::Json,
+            Value::JsonB(_) | Value::JsonBText(_) => TypeTag::JsonB,
+            Value::Uuid(_) => TypeTag::Uuid,
+            Value::Enum(_) => TypeTag::Enum,
+            Value::Hstore(_) => TypeTag::Hstore,
+            Value::Range(_) => TypeTag::Range,
+        })
+    }
+
     pub fn bool(value: bool) -> Self {
         Value::Bool(value)
     }
@@ -92,6 +201,10 @@ impl Value {
         Value::Text(value)
     }
 
+    pub fn text_bytes(value: Bytes) -> Self {
+        Value::TextBytes(value)
+    }
+
     pub fn binary(value: Vec<u8>) -> Self {
         Value::Binary(Bytes::from(value))
     }
@@ -128,7 +241,7 @@ impl Value {
         Value::IpAddr(value)
     }
 
-    pub fn interval(value: i64) -> Self {
+    pub fn interval(value: interval::Interval) -> Self {
         Value::Interval(value)
     }
 
@@ -144,6 +257,14 @@ impl Value {
         Value::JsonB(value)
     }
 
+    pub fn json_text(value: Bytes) -> Self {
+        Value::JsonText(value)
+    }
+
+    pub fn jsonb_text(value: Bytes) -> Self {
+        Value::JsonBText(value)
+    }
+
     pub fn uuid(value: Uuid) -> Self {
         Value::Uuid(value)
     }
@@ -156,6 +277,146 @@ impl Value {
         Value::Hstore(value)
     }
 
+    pub fn range(value: range::Range) -> Self {
+        Value::Range(Box::new(value))
+    }
+
+    /// Name of the value's type, for diagnostics that shouldn't include the
+    /// value itself.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null | Value::NullTyped(_) => "null",
+            Value::Bool(_) => "boolean",
+            Value::TinyInt(_) => "tinyint",
+            Value::SmallInt(_) => "smallint",
+            Value::Oid(_) => "oid",
+            Value::LargeObject(_) => "large object",
+            Value::Integer(_) => "integer",
+            Value::BigInt(_) => "bigint",
+            Value::Float(_) => "real",
+            Value::Double(_) => "double precision",
+            Value::Numeric(_) => "numeric",
+            Value::Char(_) => "char",
+            Value::VarChar(_) => "varchar",
+            Value::Text(_) | Value::TextBytes(_) => "text",
+            Value::Binary(_) => "binary",
+            Value::VarBinary(_) => "bytea",
+            Value::Date(_) => "date",
+            Value::Time(_) => "time",
+            Value::TimeWithTimeZone(_) => "timetz",
+            Value::Timestamp(_) | Value::PostgresTimestamp(_) => "timestamp",
+            Value::TimestampWithTimeZone(_) => "timestamptz",
+            Value::IpAddr(_) => "inet",
+            Value::Interval(_) => "interval",
+            Value::Array(arr) => arr.type_name(),
+            Value::Json(_) | Value::JsonText(_) => "json",
+            Value::JsonB(_) | Value::JsonBText(_) => "jsonb",
+            Value::Uuid(_) => "uuid",
+            Value::Enum(_) => "enum",
+            Value::Hstore(_) => "hstore",
+            Value::Range(_) => "range",
+        }
+    }
+
+    /// Approximate number of bytes the value takes in memory, counting
+    /// allocated capacity rather than just the data.
+    pub fn heap_size(&self) -> usize {
+        let owned = match self {
+            Value::VarChar(s) | Value::Text(s) | Value::Enum(s) => s.capacity(),
+            Value::TextBytes(b)
+            | Value::JsonText(b)
+            | Value::JsonBText(b)
+            | Value::Binary(b)
+            | Value::VarBinary(b) => b.len(),
+            Value::Array(arr) => arr.heap_size(),
+            Value::Json(j) | Value::JsonB(j) => json_heap_size(j),
+            Value::Hstore(map) => {
+                map.capacity() * std::mem::size_of::<(String, String)>()
+                    + map
+                        .iter()
+                        .map(|(k, v)| k.capacity() + v.capacity())
+                        .sum::<usize>()
+            }
+            Value::Range(range) => match range.as_ref() {
+                range::Range::Empty => std::mem::size_of::<range::Range>(),
+                range::Range::Bounds { lower, upper } => {
+                    std::mem::size_of::<range::Range>()
+                        + [lower, upper]
+                            .iter()
+                            .filter_map(|bound| bound.value())
+                            .map(|value| value.heap_size() - std::mem::size_of::<Self>())
+                            .sum::<usize>()
+                }
+            },
+            _ => 0,
+        };
+        std::mem::size_of::<Self>() + owned
+    }
+
+    /// Length in bytes of the value's data, about what it takes on the wire:
+    /// the UTF-8 length of text, the length of binary, the width of fixed
+    /// size values, and the length of the text of numerics and json. Unlike
+    /// `heap_size` it counts neither spare capacity nor the enum itself.
+    pub fn byte_len(&self) -> usize {
+        match self {
+            Value::Null | Value::NullTyped(_) => 0,
+            Value::Bool(_) | Value::TinyInt(_) => 1,
+            Value::SmallInt(_) => 2,
+            Value::Oid(_)
+            | Value::LargeObject(_)
+            | Value::Integer(_)
+            | Value::Float(_)
+            | Value::Date(_) => 4,
+            Value::BigInt(_)
+            | Value::Double(_)
+            | Value::Time(_)
+            | Value::TimeWithTimeZone(_)
+            | Value::Timestamp(_)
+            | Value::PostgresTimestamp(_)
+            | Value::TimestampWithTimeZone(_) => 8,
+            Value::Uuid(_) | Value::Interval(_) => 16,
+            Value::Numeric(n) => n.to_string().len(),
+            Value::Char(c) => c.len_utf8(),
+            Value::VarChar(s) | Value::Text(s) | Value::Enum(s) => s.len(),
+            Value::TextBytes(b)
+            | Value::JsonText(b)
+            | Value::JsonBText(b)
+            | Value::Binary(b)
+            | Value::VarBinary(b) => b.len(),
+            Value::IpAddr(ip) => match ip.address() {
+                std::net::IpAddr::V4(_) => 4,
+                std::net::IpAddr::V6(_) => 16,
+            },
+            Value::Json(j) | Value::JsonB(j) => j.to_string().len(),
+            Value::Hstore(map) => map.iter().map(|(k, v)| k.len() + v.len()).sum(),
+            Value::Array(arr) => arr.byte_len(),
+            Value::Range(range) => match range.as_ref() {
+                range::Range::Empty => 0,
+                range::Range::Bounds { lower, upper } => [lower, upper]
+                    .iter()
+                    .filter_map(|bound| bound.value())
+                    .map(Value::byte_len)
+                    .sum(),
+            },
+        }
+    }
+
+    /// Equality for comparing rows, where unlike `==` a NaN float equals a
+    /// NaN with the same bits.
+    pub fn value_eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+            (Value::Double(a), Value::Double(b)) => a.to_bits() == b.to_bits(),
+            (Value::Array(ArrayValue::Float(a)), Value::Array(ArrayValue::Float(b))) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.to_bits() == b.to_bits())
+            }
+            (Value::Array(ArrayValue::Double(a)), Value::Array(ArrayValue::Double(b))) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.to_bits() == b.to_bits())
+            }
+            _ => self == other,
+        }
+    }
+
     pub fn from_string(value: &str) -> Result<Self, Box<dyn std::error::Error>> {
         let serde_json_value: serde_json::Value = serde_json::from_str(value)?;
         Ok(Self::from_serde_json_value(&serde_json_value))
@@ -193,7 +454,7 @@ impl Value {
                                 .collect(),
                         )),
                         serde_json::Value::Bool(_) => Value::Array(ArrayValue::Bool(
-                            arr.iter().filter_map(|v| v.as_bool()).collect(),
+                            arr.iter().map(|v| v.as_bool()).collect(),
                         )),
                         _ty => {
                             let err = format!("unsupported array type: {:?}", _ty);
@@ -220,9 +481,11 @@ impl Value {
 
     pub fn to_serde_json_value(&self) -> serde_json::Value {
         match self {
-            Value::Null => serde_json::Value::Null,
+            Value::Null | Value::NullTyped(_) => serde_json::Value::Null,
             Value::Bool(b) => serde_json::Value::Bool(*b),
-            Value::Oid(o) => serde_json::Value::Number(serde_json::Number::from(*o)),
+            Value::Oid(o) | Value::LargeObject(o) => {
+                serde_json::Value::Number(serde_json::Number::from(*o))
+            }
             Value::TinyInt(n) => serde_json::Value::Number(serde_json::Number::from(*n)),
             Value::SmallInt(n) => serde_json::Value::Number(serde_json::Number::from(*n)),
             Value::Integer(n) => serde_json::Value::Number(serde_json::Number::from(*n)),
@@ -237,6 +500,9 @@ impl Value {
             Value::Char(c) => serde_json::Value::String(c.to_string()),
             Value::VarChar(s) => serde_json::Value::String(s.clone()),
             Value::Text(s) => serde_json::Value::String(s.clone()),
+            Value::TextBytes(b) => {
+                serde_json::Value::String(String::from_utf8_lossy(b).into_owned())
+            }
             Value::Binary(b) => serde_json::Value::String(BASE64_STANDARD.encode(b)),
             Value::VarBinary(b) => serde_json::Value::String(BASE64_STANDARD.encode(b)),
             Value::Date(d) => serde_json::Value::String(d.to_string()),
@@ -246,10 +512,19 @@ impl Value {
             Value::Timestamp(ts) => serde_json::Value::String(ts.to_rfc3339()),
             Value::TimestampWithTimeZone(ts) => serde_json::Value::String(ts.to_rfc3339()),
             Value::IpAddr(ip) => serde_json::Value::String(ip.to_string()),
-            Value::Interval(i) => serde_json::Value::Number(serde_json::Number::from(*i)),
+            Value::Interval(i) => serde_json::json!({
+                "months": i.months,
+                "days": i.days,
+                "microseconds": i.microseconds,
+            }),
             Value::Array(arr) => arr.to_serde_json_value(),
             Value::Json(s) => s.clone(),
             Value::JsonB(s) => s.clone(),
+            Value::JsonText(b) | Value::JsonBText(b) => {
+                serde_json::from_slice(b).unwrap_or_else(|_| {
+                    serde_json::Value::String(String::from_utf8_lossy(b).into_owned())
+                })
+            }
             Value::Uuid(u) => serde_json::Value::String(u.to_string()),
             Value::Enum(s) => serde_json::Value::String(s.clone()),
             Value::Hstore(map) => {
@@ -259,8 +534,52 @@ impl Value {
                 }
                 serde_json::Value::Object(object)
             }
+            Value::Range(range) => range_json(range, Value::to_serde_json_value),
         }
     }
+
+    /// The canonical JSON form of the value, for anything writing values out
+    /// as JSON. Unlike `to_serde_json_value`, bigints are strings since most
+    /// JSON readers lose precision past 2^53, floats that aren't finite are
+    /// strings like `"NaN"`, and every timestamp is in RFC 3339. Binary is
+    /// base64, and arrays and ranges map their elements the same way.
+    pub fn as_json(&self) -> serde_json::Value {
+        match self {
+            Value::BigInt(n) => serde_json::Value::String(n.to_string()),
+            Value::Float(f) => float_json(*f as f64),
+            Value::Double(f) => float_json(*f),
+            Value::PostgresTimestamp(ts) => serde_json::Value::String(ts.and_utc().to_rfc3339()),
+            Value::Array(arr) => arr.as_json(),
+            Value::Range(range) => range_json(range, Value::as_json),
+            _ => self.to_serde_json_value(),
+        }
+    }
+}
+
+fn float_json(f: f64) -> serde_json::Value {
+    match serde_json::Number::from_f64(f) {
+        Some(n) => serde_json::Value::Number(n),
+        None if f.is_nan() => serde_json::Value::String("NaN".to_string()),
+        None if f > 0.0 => serde_json::Value::String("Infinity".to_string()),
+        None => serde_json::Value::String("-Infinity".to_string()),
+    }
+}
+
+fn range_json(
+    range: &range::Range,
+    bound_json: fn(&Value) -> serde_json::Value,
+) -> serde_json::Value {
+    let range::Range::Bounds { lower, upper } = range else {
+        return serde_json::Value::String("empty".to_string());
+    };
+    let bound =
+        |bound: &range::RangeBound| bound.value().map_or(serde_json::Value::Null, bound_json);
+    serde_json::json!({
+        "lower": bound(lower),
+        "lower_inclusive": lower.is_inclusive(),
+        "upper": bound(upper),
+        "upper_inclusive": upper.is_inclusive(),
+    })
 }
 
 impl fmt::Display for Value {
@@ -271,3 +590,20 @@ impl fmt::Display for Value {
         }
     }
 }
+
+fn json_heap_size(value: &serde_json::Value) -> usize {
+    match value {
+        serde_json::Value::String(s) => s.capacity(),
+        serde_json::Value::Array(arr) => {
+            arr.capacity() * std::mem::size_of::<serde_json::Value>()
+                + arr.iter().map(json_heap_size).sum::<usize>()
+        }
+        serde_json::Value::Object(map) => map
+            .iter()
+            .map(|(k, v)| {
+                k.capacity() + std::mem::size_of::<serde_json::Value>() + json_heap_size(v)
+            })
+            .sum(),
+        _ => 0,
+    }
+}
@@ -0,0 +1,105 @@
+use rust_decimal::Decimal;
+
+use crate::{
+    array::ArrayValue,
+    range::{Range, RangeBound},
+    Value,
+};
+
+impl Value {
+    /// A canonical form of the value, so that values which only differ in
+    /// how they are written compare equal: floats without negative zero or
+    /// NaN payloads, numerics without trailing zeros, json with its object
+    /// keys sorted, and text read from a buffer as `Value::Text`. Meant for
+    /// comparing values, in tests or to detect changes, never for values
+    /// that are written out, which keep what the source sent. Compare
+    /// normalized values with `value_eq`, as NaN isn't `==` to itself.
+    pub fn normalize(&self) -> Value {
+        match self {
+            Value::Float(f) => Value::Float(normalize_f32(*f)),
+            Value::Double(f) => Value::Double(normalize_f64(*f)),
+            Value::Numeric(n) => Value::Numeric(n.normalize()),
+            Value::TextBytes(b) => match std::str::from_utf8(b) {
+                Ok(s) => Value::Text(s.to_string()),
+                Err(_) => self.clone(),
+            },
+            // json text is parsed, keys and all, by `as_json`
+            Value::Json(_) | Value::JsonText(_) => Value::Json(sorted_json(self.as_json())),
+            Value::JsonB(_) | Value::JsonBText(_) => Value::JsonB(sorted_json(self.as_json())),
+            Value::Array(arr) => Value::Array(normalize_array(arr)),
+            Value::Range(range) => Value::range(match range.as_ref() {
+                Range::Empty => Range::Empty,
+                Range::Bounds { lower, upper } => Range::Bounds {
+                    lower: normalize_bound(lower),
+                    upper: normalize_bound(upper),
+                },
+            }),
+            _ => self.clone(),
+        }
+    }
+}
+
+fn normalize_f32(f: f32) -> f32 {
+    if f.is_nan() {
+        f32::NAN
+    } else {
+        // -0.0 + 0.0 is 0.0
+        f + 0.0
+    }
+}
+
+fn normalize_f64(f: f64) -> f64 {
+    if f.is_nan() {
+        f64::NAN
+    } else {
+        f + 0.0
+    }
+}
+
+fn sorted_json(json: serde_json::Value) -> serde_json::Value {
+    match json {
+        serde_json::Value::Object(map) => {
+            let mut entries = map.into_iter().collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            serde_json::Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, sorted_json(value)))
+                    .collect(),
+            )
+        }
+        serde_json::Value::Array(values) => {
+            serde_json::Value::Array(values.into_iter().map(sorted_json).collect())
+        }
+        json => json,
+    }
+}
+
+fn normalize_array(arr: &ArrayValue) -> ArrayValue {
+    match arr {
+        ArrayValue::Float(arr) => {
+            ArrayValue::Float(arr.iter().copied().map(normalize_f32).collect())
+        }
+        ArrayValue::Double(arr) => {
+            ArrayValue::Double(arr.iter().copied().map(normalize_f64).collect())
+        }
+        // elements that aren't decimals, like NaN, are kept as they are
+        ArrayValue::Numeric(arr) => ArrayValue::Numeric(
+            arr.iter()
+                .map(|n| match n.parse::<Decimal>() {
+                    Ok(n) => n.normalize().to_string(),
+                    Err(_) => n.clone(),
+                })
+                .collect(),
+        ),
+        arr => arr.clone(),
+    }
+}
+
+fn normalize_bound(bound: &RangeBound) -> RangeBound {
+    match bound {
+        RangeBound::Inclusive(value) => RangeBound::Inclusive(value.normalize()),
+        RangeBound::Exclusive(value) => RangeBound::Exclusive(value.normalize()),
+        RangeBound::Unbounded => RangeBound::Unbounded,
+    }
+}
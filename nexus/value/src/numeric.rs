@@ -0,0 +1,70 @@
+use rust_decimal::RoundingStrategy;
+
+use crate::Value;
+
+// the most fractional digits a rust_decimal can hold
+const MAX_SCALE: u32 = 28;
+
+/// How fractional digits beyond a target scale are dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// Round halves away from zero, as postgres does for NUMERIC.
+    HalfUp,
+    /// Round halves to the nearest even digit.
+    HalfEven,
+    /// Drop the extra digits.
+    Truncate,
+}
+
+impl From<RoundingMode> for RoundingStrategy {
+    fn from(mode: RoundingMode) -> Self {
+        match mode {
+            RoundingMode::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            RoundingMode::HalfEven => RoundingStrategy::MidpointNearestEven,
+            RoundingMode::Truncate => RoundingStrategy::ToZero,
+        }
+    }
+}
+
+impl Value {
+    /// Fits a `Value::Numeric` into a destination `NUMERIC(precision, scale)`,
+    /// rounding any digits beyond `scale` with `mode`. Fails if the rounded
+    /// value has more than `precision` digits in total. NULL passes through.
+    pub fn round_numeric_to_scale(
+        &self,
+        precision: u32,
+        scale: u32,
+        mode: RoundingMode,
+    ) -> anyhow::Result<Value> {
+        let n = match self {
+            Value::Null | Value::NullTyped(_) => return Ok(self.clone()),
+            Value::Numeric(n) => n,
+            other => anyhow::bail!("cannot round {} to a numeric scale", other.type_name()),
+        };
+        if scale > precision || scale > MAX_SCALE {
+            anyhow::bail!(
+                "invalid numeric precision and scale ({}, {})",
+                precision,
+                scale
+            );
+        }
+
+        let mut rounded = n.round_dp_with_strategy(scale, mode.into());
+        // pad with trailing zeros so the digit count below covers the full scale
+        rounded.rescale(scale);
+        let digits = rounded
+            .mantissa()
+            .unsigned_abs()
+            .checked_ilog10()
+            .map_or(0, |d| d + 1);
+        if rounded.scale() != scale || digits > precision {
+            anyhow::bail!(
+                "numeric value {} overflows NUMERIC({}, {})",
+                n,
+                precision,
+                scale
+            );
+        }
+        Ok(Value::Numeric(rounded))
+    }
+}
@@ -0,0 +1,442 @@
+//! Conversions between `Value` and its protobuf form, for passing values
+//! over the RPC layer.
+
+use std::net::IpAddr;
+
+use anyhow::Context;
+use bytes::Bytes;
+use chrono::{DateTime, NaiveDate, NaiveTime, Timelike, Utc};
+use postgres_inet::MaskedIpAddr;
+use pt::{
+    pbjson_types::{Duration, Timestamp},
+    peerdb_value::{self as pb, value::Value as PbKind, ArrayKind},
+};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+use crate::{
+    array::ArrayValue,
+    interval::Interval,
+    range::{Range, RangeBound},
+    TypeTag, Value,
+};
+
+fn timestamp(ts: DateTime<Utc>) -> Timestamp {
+    Timestamp {
+        seconds: ts.timestamp(),
+        nanos: ts.timestamp_subsec_nanos() as i32,
+    }
+}
+
+fn from_timestamp(ts: Timestamp) -> anyhow::Result<DateTime<Utc>> {
+    DateTime::from_timestamp(ts.seconds, ts.nanos as u32).context("timestamp out of range")
+}
+
+fn date(d: NaiveDate) -> Timestamp {
+    timestamp(d.and_time(NaiveTime::MIN).and_utc())
+}
+
+fn time(t: NaiveTime) -> Duration {
+    Duration {
+        seconds: t.num_seconds_from_midnight() as i64,
+        nanos: t.nanosecond() as i32,
+    }
+}
+
+fn from_time(d: Duration) -> anyhow::Result<NaiveTime> {
+    NaiveTime::from_num_seconds_from_midnight_opt(d.seconds as u32, d.nanos as u32)
+        .context("time out of range")
+}
+
+fn interval(iv: Interval) -> pb::Interval {
+    pb::Interval {
+        seconds: iv.microseconds / 1_000_000,
+        nanos: (iv.microseconds % 1_000_000) as i32 * 1000,
+        months: iv.months,
+        days: iv.days,
+    }
+}
+
+fn from_interval(iv: pb::Interval) -> Interval {
+    Interval {
+        months: iv.months,
+        days: iv.days,
+        microseconds: iv.seconds * 1_000_000 + (iv.nanos / 1000) as i64,
+    }
+}
+
+// the protobuf enum mirrors TypeTag one to one, plus its unspecified zero
+macro_rules! type_tags {
+    ($($tag:ident),* $(,)?) => {
+        impl From<TypeTag> for pb::TypeTag {
+            fn from(tag: TypeTag) -> Self {
+                match tag {
+                    $(TypeTag::$tag => pb::TypeTag::$tag,)*
+                }
+            }
+        }
+
+        impl TryFrom<pb::TypeTag> for TypeTag {
+            type Error = anyhow::Error;
+
+            fn try_from(tag: pb::TypeTag) -> anyhow::Result<Self> {
+                match tag {
+                    $(pb::TypeTag::$tag => Ok(TypeTag::$tag),)*
+                    pb::TypeTag::Unspecified => anyhow::bail!("unspecified type tag"),
+                }
+            }
+        }
+    };
+}
+
+type_tags!(
+    Bool,
+    TinyInt,
+    SmallInt,
+    Oid,
+    Integer,
+    BigInt,
+    Float,
+    Double,
+    Numeric,
+    Char,
+    VarChar,
+    Text,
+    Binary,
+    VarBinary,
+    Date,
+    Time,
+    TimeWithTimeZone,
+    Timestamp,
+    PostgresTimestamp,
+    TimestampWithTimeZone,
+    IpAddr,
+    Interval,
+    Array,
+    Json,
+    JsonB,
+    Uuid,
+    Enum,
+    Hstore,
+    Range,
+    LargeObject,
+);
+
+impl From<Value> for pb::Value {
+    fn from(value: Value) -> Self {
+        let kind = match value {
+            Value::Null => None,
+            Value::NullTyped(tag) => Some(PbKind::NullValue(pb::TypeTag::from(tag).into())),
+            Value::Bool(b) => Some(PbKind::BoolValue(b)),
+            Value::TinyInt(i) => Some(PbKind::TinyIntValue(i.into())),
+            Value::SmallInt(i) => Some(PbKind::SmallIntValue(i.into())),
+            Value::Oid(o) => Some(PbKind::OidValue(o)),
+            Value::LargeObject(o) => Some(PbKind::LargeObjectValue(o)),
+            Value::Integer(i) => Some(PbKind::IntegerValue(i)),
+            Value::BigInt(i) => Some(PbKind::BigIntValue(i)),
+            Value::Float(f) => Some(PbKind::FloatValue(f)),
+            Value::Double(f) => Some(PbKind::DoubleValue(f)),
+            Value::Numeric(n) => Some(PbKind::NumericValue(n.to_string())),
+            Value::Char(c) => Some(PbKind::CharValue(c.to_string())),
+            Value::VarChar(s) => Some(PbKind::VarCharValue(s)),
+            Value::Text(s) => Some(PbKind::TextValue(s)),
+            Value::TextBytes(b) => {
+                Some(PbKind::TextValue(String::from_utf8_lossy(&b).into_owned()))
+            }
+            Value::Binary(b) => Some(PbKind::BinaryValue(b.to_vec())),
+            Value::VarBinary(b) => Some(PbKind::VarBinaryValue(b.to_vec())),
+            Value::Date(d) => Some(PbKind::DateValue(date(d))),
+            Value::Time(t) => Some(PbKind::TimeValue(time(t))),
+            Value::TimeWithTimeZone(t) => Some(PbKind::TimeWithTimeZoneValue(time(t))),
+            Value::Timestamp(ts) => Some(PbKind::TimestampValue(timestamp(ts))),
+            Value::PostgresTimestamp(ts) => {
+                Some(PbKind::PostgresTimestampValue(timestamp(ts.and_utc())))
+            }
+            Value::TimestampWithTimeZone(ts) => {
+                Some(PbKind::TimestampWithTimeZoneValue(timestamp(ts)))
+            }
+            Value::IpAddr(ip) => Some(PbKind::IpAddrValue(pb::IpAddr {
+                address: match ip.address() {
+                    IpAddr::V4(v4) => v4.octets().to_vec(),
+                    IpAddr::V6(v6) => v6.octets().to_vec(),
+                },
+                netmask: ip.netmask().into(),
+            })),
+            Value::Interval(iv) => Some(PbKind::IntervalValue(interval(iv))),
+            Value::Array(arr) => Some(PbKind::ArrayValue(arr.into())),
+            Value::Json(j) => Some(PbKind::JsonValue(j.to_string())),
+            Value::JsonB(j) => Some(PbKind::JsonBValue(j.to_string())),
+            Value::JsonText(b) => Some(PbKind::JsonValue(String::from_utf8_lossy(&b).into_owned())),
+            Value::JsonBText(b) => {
+                Some(PbKind::JsonBValue(String::from_utf8_lossy(&b).into_owned()))
+            }
+            Value::Uuid(u) => Some(PbKind::UuidValue(u.as_bytes().to_vec())),
+            Value::Enum(s) => Some(PbKind::EnumValue(s)),
+            Value::Hstore(entries) => Some(PbKind::HstoreValue(pb::Hstore { entries })),
+            Value::Range(range) => Some(PbKind::RangeValue((*range).into())),
+        };
+        pb::Value { value: kind }
+    }
+}
+
+impl TryFrom<pb::Value> for Value {
+    type Error = anyhow::Error;
+
+    fn try_from(value: pb::Value) -> anyhow::Result<Self> {
+        let Some(kind) = value.value else {
+            return Ok(Value::Null);
+        };
+        Ok(match kind {
+            PbKind::BoolValue(b) => Value::Bool(b),
+            PbKind::TinyIntValue(i) => Value::TinyInt(i.try_into()?),
+            PbKind::SmallIntValue(i) => Value::SmallInt(i.try_into()?),
+            PbKind::OidValue(o) => Value::Oid(o),
+            PbKind::LargeObjectValue(o) => Value::LargeObject(o),
+            PbKind::IntegerValue(i) => Value::Integer(i),
+            PbKind::BigIntValue(i) => Value::BigInt(i),
+            PbKind::FloatValue(f) => Value::Float(f),
+            PbKind::DoubleValue(f) => Value::Double(f),
+            PbKind::NumericValue(n) => Value::Numeric(n.parse::<Decimal>()?),
+            PbKind::CharValue(s) => {
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Value::Char(c),
+                    _ => anyhow::bail!("char value must be a single character: {:?}", s),
+                }
+            }
+            PbKind::VarCharValue(s) => Value::VarChar(s),
+            PbKind::TextValue(s) => Value::Text(s),
+            PbKind::BinaryValue(b) => Value::Binary(Bytes::from(b)),
+            PbKind::VarBinaryValue(b) => Value::VarBinary(Bytes::from(b)),
+            PbKind::DateValue(ts) => Value::Date(from_timestamp(ts)?.date_naive()),
+            PbKind::TimeValue(d) => Value::Time(from_time(d)?),
+            PbKind::TimeWithTimeZoneValue(d) => Value::TimeWithTimeZone(from_time(d)?),
+            PbKind::TimestampValue(ts) => Value::Timestamp(from_timestamp(ts)?),
+            PbKind::PostgresTimestampValue(ts) => {
+                Value::PostgresTimestamp(from_timestamp(ts)?.naive_utc())
+            }
+            PbKind::TimestampWithTimeZoneValue(ts) => {
+                Value::TimestampWithTimeZone(from_timestamp(ts)?)
+            }
+            PbKind::IpAddrValue(ip) => {
+                let address = match ip.address.len() {
+                    4 => IpAddr::from(<[u8; 4]>::try_from(ip.address.as_slice())?),
+                    16 => IpAddr::from(<[u8; 16]>::try_from(ip.address.as_slice())?),
+                    n => anyhow::bail!("invalid ip address length: {}", n),
+                };
+                Value::IpAddr(MaskedIpAddr::new(address, ip.netmask.try_into()?))
+            }
+            PbKind::IntervalValue(iv) => Value::Interval(from_interval(iv)),
+            PbKind::ArrayValue(arr) => Value::Array(arr.try_into()?),
+            PbKind::JsonValue(s) => Value::Json(serde_json::from_str(&s)?),
+            PbKind::JsonBValue(s) => Value::JsonB(serde_json::from_str(&s)?),
+            PbKind::UuidValue(b) => Value::Uuid(Uuid::from_slice(&b)?),
+            PbKind::EnumValue(s) => Value::Enum(s),
+            PbKind::HstoreValue(hstore) => Value::Hstore(hstore.entries),
+            PbKind::RangeValue(range) => Value::Range(Box::new(range.try_into()?)),
+            PbKind::NullValue(tag) => {
+                let tag = pb::TypeTag::try_from(tag)
+                    .map_err(|_| anyhow::anyhow!("unknown type tag: {}", tag))?;
+                Value::NullTyped(tag.try_into()?)
+            }
+        })
+    }
+}
+
+fn bound(bound: RangeBound) -> (Option<Box<pb::Value>>, bool) {
+    match bound {
+        RangeBound::Inclusive(value) => (Some(Box::new(value.into())), true),
+        RangeBound::Exclusive(value) => (Some(Box::new(value.into())), false),
+        RangeBound::Unbounded => (None, false),
+    }
+}
+
+fn from_bound(value: Option<Box<pb::Value>>, inclusive: bool) -> anyhow::Result<RangeBound> {
+    let Some(value) = value else {
+        return Ok(RangeBound::Unbounded);
+    };
+    let value = Value::try_from(*value)?;
+    Ok(if inclusive {
+        RangeBound::Inclusive(value)
+    } else {
+        RangeBound::Exclusive(value)
+    })
+}
+
+impl From<Range> for pb::Range {
+    fn from(range: Range) -> Self {
+        match range {
+            Range::Empty => pb::Range {
+                empty: true,
+                ..Default::default()
+            },
+            Range::Bounds { lower, upper } => {
+                let (lower, lower_inclusive) = bound(lower);
+                let (upper, upper_inclusive) = bound(upper);
+                pb::Range {
+                    empty: false,
+                    lower,
+                    lower_inclusive,
+                    upper,
+                    upper_inclusive,
+                }
+            }
+        }
+    }
+}
+
+impl TryFrom<pb::Range> for Range {
+    type Error = anyhow::Error;
+
+    fn try_from(range: pb::Range) -> anyhow::Result<Self> {
+        if range.empty {
+            return Ok(Range::Empty);
+        }
+        Ok(Range::Bounds {
+            lower: from_bound(range.lower, range.lower_inclusive)?,
+            upper: from_bound(range.upper, range.upper_inclusive)?,
+        })
+    }
+}
+
+impl From<ArrayValue> for pb::Array {
+    fn from(arr: ArrayValue) -> Self {
+        fn elements<T>(arr: Vec<T>, f: impl Fn(T) -> Value) -> Vec<pb::Value> {
+            arr.into_iter().map(|v| f(v).into()).collect()
+        }
+
+        fn nullable_elements<T>(arr: Vec<Option<T>>, f: impl Fn(T) -> Value) -> Vec<pb::Value> {
+            elements(arr, |v| v.map_or(Value::Null, &f))
+        }
+
+        let (kind, elements) = match arr {
+            ArrayValue::Empty => (ArrayKind::Empty, Vec::new()),
+            ArrayValue::Bool(arr) => (ArrayKind::Bool, nullable_elements(arr, Value::Bool)),
+            ArrayValue::TinyInt(arr) => (ArrayKind::TinyInt, elements(arr, Value::TinyInt)),
+            ArrayValue::SmallInt(arr) => (ArrayKind::SmallInt, elements(arr, Value::SmallInt)),
+            ArrayValue::Integer(arr) => (ArrayKind::Integer, elements(arr, Value::Integer)),
+            ArrayValue::BigInt(arr) => (ArrayKind::BigInt, elements(arr, Value::BigInt)),
+            ArrayValue::Oid(arr) => (ArrayKind::Oid, elements(arr, Value::Oid)),
+            ArrayValue::Float(arr) => (ArrayKind::Float, elements(arr, Value::Float)),
+            ArrayValue::Double(arr) => (ArrayKind::Double, elements(arr, Value::Double)),
+            // numeric array elements are kept as their decimal text
+            ArrayValue::Numeric(arr) => (
+                ArrayKind::Numeric,
+                arr.into_iter()
+                    .map(|n| pb::Value {
+                        value: Some(PbKind::NumericValue(n)),
+                    })
+                    .collect(),
+            ),
+            ArrayValue::Char(arr) => (ArrayKind::Char, elements(arr, Value::Char)),
+            ArrayValue::VarChar(arr) => (ArrayKind::VarChar, elements(arr, Value::VarChar)),
+            ArrayValue::Text(arr) => (ArrayKind::Text, elements(arr, Value::Text)),
+            ArrayValue::Binary(arr) => (ArrayKind::Binary, elements(arr, Value::Binary)),
+            ArrayValue::VarBinary(arr) => (ArrayKind::VarBinary, elements(arr, Value::VarBinary)),
+            ArrayValue::Date(arr) => (ArrayKind::Date, elements(arr, Value::Date)),
+            ArrayValue::Time(arr) => (ArrayKind::Time, nullable_elements(arr, Value::Time)),
+            // timetz elements are kept as their text, which holds the offset
+            ArrayValue::TimeTz(arr) => (
+                ArrayKind::TimeWithTimeZone,
+                nullable_elements(arr, |t| Value::Text(t.to_string())),
+            ),
+            ArrayValue::Timestamp(arr) => (ArrayKind::Timestamp, elements(arr, Value::Timestamp)),
+            ArrayValue::TimestampWithTimeZone(arr) => (
+                ArrayKind::TimestampWithTimeZone,
+                elements(arr, Value::TimestampWithTimeZone),
+            ),
+            ArrayValue::Interval(arr) => {
+                (ArrayKind::Interval, nullable_elements(arr, Value::Interval))
+            }
+        };
+        pb::Array {
+            kind: kind.into(),
+            elements,
+        }
+    }
+}
+
+impl TryFrom<pb::Array> for ArrayValue {
+    type Error = anyhow::Error;
+
+    fn try_from(arr: pb::Array) -> anyhow::Result<Self> {
+        macro_rules! elements {
+            ($variant:ident) => {
+                arr.elements
+                    .into_iter()
+                    .map(|v| match Value::try_from(v)? {
+                        Value::$variant(v) => Ok(v),
+                        other => anyhow::bail!(
+                            "unexpected {} element in {} array",
+                            other.type_name(),
+                            stringify!($variant)
+                        ),
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?
+            };
+        }
+
+        // like elements, for arrays whose elements may be NULL
+        macro_rules! nullable_elements {
+            ($variant:ident) => {
+                arr.elements
+                    .into_iter()
+                    .map(|v| match Value::try_from(v)? {
+                        Value::Null => Ok(None),
+                        Value::$variant(v) => Ok(Some(v)),
+                        other => anyhow::bail!(
+                            "unexpected {} element in {} array",
+                            other.type_name(),
+                            stringify!($variant)
+                        ),
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?
+            };
+        }
+
+        let kind = ArrayKind::try_from(arr.kind)
+            .map_err(|_| anyhow::anyhow!("unknown array kind: {}", arr.kind))?;
+        Ok(match kind {
+            ArrayKind::Empty => ArrayValue::Empty,
+            ArrayKind::Bool => ArrayValue::Bool(nullable_elements!(Bool)),
+            ArrayKind::TinyInt => ArrayValue::TinyInt(elements!(TinyInt)),
+            ArrayKind::SmallInt => ArrayValue::SmallInt(elements!(SmallInt)),
+            ArrayKind::Integer => ArrayValue::Integer(elements!(Integer)),
+            ArrayKind::BigInt => ArrayValue::BigInt(elements!(BigInt)),
+            ArrayKind::Oid => ArrayValue::Oid(elements!(Oid)),
+            ArrayKind::Float => ArrayValue::Float(elements!(Float)),
+            ArrayKind::Double => ArrayValue::Double(elements!(Double)),
+            ArrayKind::Numeric => ArrayValue::Numeric(
+                arr.elements
+                    .into_iter()
+                    .map(|v| match v.value {
+                        Some(PbKind::NumericValue(n)) => Ok(n),
+                        _ => anyhow::bail!("unexpected element in numeric array"),
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?,
+            ),
+            ArrayKind::Char => ArrayValue::Char(elements!(Char)),
+            ArrayKind::VarChar => ArrayValue::VarChar(elements!(VarChar)),
+            ArrayKind::Text => ArrayValue::Text(elements!(Text)),
+            ArrayKind::Binary => ArrayValue::Binary(elements!(Binary)),
+            ArrayKind::VarBinary => ArrayValue::VarBinary(elements!(VarBinary)),
+            ArrayKind::Date => ArrayValue::Date(elements!(Date)),
+            ArrayKind::Time => ArrayValue::Time(nullable_elements!(Time)),
+            ArrayKind::Interval => ArrayValue::Interval(nullable_elements!(Interval)),
+            ArrayKind::TimeWithTimeZone => ArrayValue::TimeTz(
+                arr.elements
+                    .into_iter()
+                    .map(|v| match v.value {
+                        None => Ok(None),
+                        Some(PbKind::TextValue(t)) => t.parse().map(Some),
+                        _ => anyhow::bail!("unexpected element in timetz array"),
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?,
+            ),
+            ArrayKind::Timestamp => ArrayValue::Timestamp(elements!(Timestamp)),
+            ArrayKind::TimestampWithTimeZone => {
+                ArrayValue::TimestampWithTimeZone(elements!(TimestampWithTimeZone))
+            }
+        })
+    }
+}
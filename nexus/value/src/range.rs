@@ -0,0 +1,143 @@
+use std::{error::Error, fmt};
+
+use bytes::Buf;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use postgres_types::{FromSql, Kind, Type};
+
+use crate::Value;
+
+// flags of a range's binary form
+const EMPTY: u8 = 0x01;
+const LOWER_INCLUSIVE: u8 = 0x02;
+const UPPER_INCLUSIVE: u8 = 0x04;
+const LOWER_UNBOUNDED: u8 = 0x08;
+const UPPER_UNBOUNDED: u8 = 0x10;
+
+/// One end of a range.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RangeBound {
+    Inclusive(Value),
+    Exclusive(Value),
+    Unbounded,
+}
+
+impl RangeBound {
+    pub fn value(&self) -> Option<&Value> {
+        match self {
+            RangeBound::Inclusive(value) | RangeBound::Exclusive(value) => Some(value),
+            RangeBound::Unbounded => None,
+        }
+    }
+
+    pub fn is_inclusive(&self) -> bool {
+        matches!(self, RangeBound::Inclusive(_))
+    }
+}
+
+/// A value of a range type. The bounds are values of the range's element
+/// type: `Value::Date` for a daterange, `Value::PostgresTimestamp` for a
+/// tsrange and `Value::TimestampWithTimeZone` for a tstzrange.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Range {
+    Empty,
+    Bounds {
+        lower: RangeBound,
+        upper: RangeBound,
+    },
+}
+
+impl<'a> FromSql<'a> for Range {
+    fn from_sql(ty: &Type, mut raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let Kind::Range(element) = ty.kind() else {
+            return Err(format!("{} is not a range type", ty).into());
+        };
+        if raw.is_empty() {
+            return Err("invalid range".into());
+        }
+        let flags = raw.get_u8();
+        if flags & EMPTY != 0 {
+            return Ok(Range::Empty);
+        }
+        let lower = read_bound(
+            element,
+            &mut raw,
+            flags & LOWER_UNBOUNDED != 0,
+            flags & LOWER_INCLUSIVE != 0,
+        )?;
+        let upper = read_bound(
+            element,
+            &mut raw,
+            flags & UPPER_UNBOUNDED != 0,
+            flags & UPPER_INCLUSIVE != 0,
+        )?;
+        Ok(Range::Bounds { lower, upper })
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(*ty, Type::TS_RANGE | Type::TSTZ_RANGE | Type::DATE_RANGE)
+    }
+}
+
+fn read_bound(
+    element: &Type,
+    raw: &mut &[u8],
+    unbounded: bool,
+    inclusive: bool,
+) -> Result<RangeBound, Box<dyn Error + Sync + Send>> {
+    if unbounded {
+        return Ok(RangeBound::Unbounded);
+    }
+    if raw.len() < 4 {
+        return Err("invalid range bound".into());
+    }
+    let len = raw.get_i32();
+    if len < 0 || raw.len() < len as usize {
+        return Err("invalid range bound".into());
+    }
+    let (bound, rest) = raw.split_at(len as usize);
+    *raw = rest;
+
+    let value = match *element {
+        Type::DATE => Value::Date(NaiveDate::from_sql(element, bound)?),
+        Type::TIMESTAMP => Value::postgres_timestamp(NaiveDateTime::from_sql(element, bound)?),
+        Type::TIMESTAMPTZ => {
+            Value::TimestampWithTimeZone(DateTime::<Utc>::from_sql(element, bound)?)
+        }
+        _ => return Err(format!("unsupported range element type {}", element).into()),
+    };
+    Ok(if inclusive {
+        RangeBound::Inclusive(value)
+    } else {
+        RangeBound::Exclusive(value)
+    })
+}
+
+// a bound as postgres writes it, quoted when it holds a space
+fn bound_text(value: &Value) -> String {
+    match value {
+        Value::Date(d) => d.format("%Y-%m-%d").to_string(),
+        Value::PostgresTimestamp(ts) => format!("\"{}\"", ts.format("%Y-%m-%d %H:%M:%S%.f")),
+        Value::Timestamp(ts) | Value::TimestampWithTimeZone(ts) => {
+            format!("\"{}+00\"", ts.format("%Y-%m-%d %H:%M:%S%.f"))
+        }
+        value => format!("{}", value),
+    }
+}
+
+/// The range's text output, such as `[2024-01-01,2024-02-01)`.
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Range::Bounds { lower, upper } = self else {
+            return f.write_str("empty");
+        };
+        f.write_str(if lower.is_inclusive() { "[" } else { "(" })?;
+        if let Some(value) = lower.value() {
+            f.write_str(&bound_text(value))?;
+        }
+        f.write_str(",")?;
+        if let Some(value) = upper.value() {
+            f.write_str(&bound_text(value))?;
+        }
+        f.write_str(if upper.is_inclusive() { "]" } else { ")" })
+    }
+}
@@ -0,0 +1,107 @@
+use std::{error::Error, fmt, str::FromStr};
+
+use bytes::{Buf, BufMut, BytesMut};
+use chrono::{FixedOffset, NaiveTime, Timelike};
+use pgwire::types::ToSqlText;
+use postgres_types::{accepts, to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+/// A `time with time zone`, keeping the UTC offset it was written with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeTz {
+    pub time: NaiveTime,
+    pub offset: FixedOffset,
+}
+
+impl fmt::Display for TimeTz {
+    /// Writes the time like postgres does, e.g. `09:00:00+05:30` or
+    /// `17:45:10.5-08`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.offset.local_minus_utc();
+        let sign = if secs < 0 { '-' } else { '+' };
+        let secs = secs.unsigned_abs();
+        let t = self.time;
+        write!(f, "{:02}:{:02}:{:02}", t.hour(), t.minute(), t.second())?;
+        let micros = t.nanosecond() / 1000;
+        if micros != 0 {
+            let digits = format!("{:06}", micros);
+            write!(f, ".{}", digits.trim_end_matches('0'))?;
+        }
+        write!(f, "{}{:02}", sign, secs / 3600)?;
+        if secs % 3600 != 0 {
+            write!(f, ":{:02}", secs / 60 % 60)?;
+        }
+        if secs % 60 != 0 {
+            write!(f, ":{:02}", secs % 60)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TimeTz {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split = s
+            .rfind(['+', '-'])
+            .ok_or_else(|| anyhow::anyhow!("missing UTC offset in timetz {}", s))?;
+        let (time, offset) = s.split_at(split);
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let mut secs = 0;
+        for (i, part) in offset[1..].split(':').enumerate() {
+            if i > 2 {
+                anyhow::bail!("invalid UTC offset in timetz {}", s);
+            }
+            secs += part.parse::<i32>()? * [3600, 60, 1][i];
+        }
+        Ok(TimeTz {
+            time: time.parse()?,
+            offset: FixedOffset::east_opt(sign * secs)
+                .ok_or_else(|| anyhow::anyhow!("UTC offset out of range in timetz {}", s))?,
+        })
+    }
+}
+
+// binary timetz is the microseconds since midnight followed by the offset in
+// seconds west of UTC
+impl<'a> FromSql<'a> for TimeTz {
+    fn from_sql(_: &Type, mut raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        if raw.len() != 12 {
+            return Err("invalid message length: timetz size mismatch".into());
+        }
+        let micros = raw.get_i64();
+        let west = raw.get_i32();
+        let time = NaiveTime::from_num_seconds_from_midnight_opt(
+            (micros / 1_000_000) as u32,
+            (micros % 1_000_000) as u32 * 1000,
+        )
+        .ok_or("timetz out of range")?;
+        let offset = FixedOffset::west_opt(west).ok_or("timetz offset out of range")?;
+        Ok(TimeTz { time, offset })
+    }
+
+    accepts!(TIMETZ);
+}
+
+impl ToSql for TimeTz {
+    fn to_sql(&self, _: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        let micros = self.time.num_seconds_from_midnight() as i64 * 1_000_000
+            + (self.time.nanosecond() / 1000) as i64;
+        out.put_i64(micros);
+        out.put_i32(-self.offset.local_minus_utc());
+        Ok(IsNull::No)
+    }
+
+    accepts!(TIMETZ);
+    to_sql_checked!();
+}
+
+impl ToSqlText for TimeTz {
+    fn to_sql_text(
+        &self,
+        _: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        out.put_slice(self.to_string().as_bytes());
+        Ok(IsNull::No)
+    }
+}
@@ -0,0 +1,738 @@
+use bytes::Bytes;
+use value::{TypeTag, Value};
+
+#[test]
+fn jsonb_preserves_key_order() {
+    let raw = r#"{"zeta":1,"alpha":{"b":true,"a":null},"mid":[3,2,1]}"#;
+    let json = Value::JsonText(Bytes::from_static(raw.as_bytes())).to_serde_json_value();
+
+    let keys: Vec<&str> = json
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(String::as_str)
+        .collect();
+    assert_eq!(keys, ["zeta", "alpha", "mid"]);
+    assert_eq!(Value::JsonB(json).to_string().unwrap(), raw);
+}
+
+#[test]
+fn type_name_per_variant() {
+    use chrono::{NaiveDate, NaiveTime, TimeZone, Utc};
+    use postgres_inet::MaskedIpAddr;
+    use rust_decimal::Decimal;
+    use std::{collections::HashMap, net::Ipv4Addr};
+    use value::{array::ArrayValue, interval::Interval};
+
+    let date = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+    let time = NaiveTime::from_hms_opt(3, 4, 5).unwrap();
+    let ts = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+    let cases = [
+        (Value::Null, "null"),
+        (Value::NullTyped(TypeTag::Integer), "null"),
+        (Value::Bool(true), "boolean"),
+        (Value::TinyInt(1), "tinyint"),
+        (Value::SmallInt(1), "smallint"),
+        (Value::Oid(1), "oid"),
+        (Value::LargeObject(16385), "large object"),
+        (Value::Integer(1), "integer"),
+        (Value::BigInt(1), "bigint"),
+        (Value::Float(1.0), "real"),
+        (Value::Double(1.0), "double precision"),
+        (Value::Numeric(Decimal::ONE), "numeric"),
+        (Value::Char('a'), "char"),
+        (Value::VarChar("a".into()), "varchar"),
+        (Value::Text("a".into()), "text"),
+        (Value::TextBytes(Bytes::from_static(b"a")), "text"),
+        (Value::Binary(Bytes::from_static(b"a")), "binary"),
+        (Value::VarBinary(Bytes::from_static(b"a")), "bytea"),
+        (Value::Date(date), "date"),
+        (Value::Time(time), "time"),
+        (Value::TimeWithTimeZone(time), "timetz"),
+        (Value::Timestamp(ts), "timestamp"),
+        (Value::PostgresTimestamp(ts.naive_utc()), "timestamp"),
+        (Value::TimestampWithTimeZone(ts), "timestamptz"),
+        (
+            Value::IpAddr(MaskedIpAddr::new(Ipv4Addr::LOCALHOST.into(), 32)),
+            "inet",
+        ),
+        (Value::Interval(Interval::from_micros(1)), "interval"),
+        (Value::Array(ArrayValue::Integer(vec![1])), "integer[]"),
+        (Value::Array(ArrayValue::Empty), "array"),
+        (Value::Json(serde_json::Value::Null), "json"),
+        (Value::JsonB(serde_json::Value::Null), "jsonb"),
+        (Value::JsonText(Bytes::from_static(b"{}")), "json"),
+        (Value::JsonBText(Bytes::from_static(b"{}")), "jsonb"),
+        (Value::Uuid(uuid::Uuid::nil()), "uuid"),
+        (Value::Enum("a".into()), "enum"),
+        (Value::Hstore(HashMap::new()), "hstore"),
+    ];
+    for (value, name) in cases {
+        assert_eq!(value.type_name(), name);
+    }
+}
+
+#[test]
+fn oidvector_text_is_space_separated() {
+    use bytes::BytesMut;
+    use pgwire::types::ToSqlText;
+    use postgres_types::Type;
+    use value::array::ArrayValue;
+
+    let oids = ArrayValue::Oid(vec![23, 25]);
+
+    let mut out = BytesMut::new();
+    oids.to_sql_text(&Type::OID_VECTOR, &mut out).unwrap();
+    assert_eq!(&out[..], b"23 25");
+
+    let mut out = BytesMut::new();
+    oids.to_sql_text(&Type::OID_ARRAY, &mut out).unwrap();
+    assert_eq!(&out[..], b"{23,25}");
+}
+
+#[test]
+fn bool_array_text_keeps_nulls() {
+    use bytes::BytesMut;
+    use pgwire::types::ToSqlText;
+    use postgres_types::Type;
+    use value::array::ArrayValue;
+
+    let bools = ArrayValue::Bool(vec![Some(true), None, Some(false)]);
+    let mut out = BytesMut::new();
+    bools.to_sql_text(&Type::BOOL_ARRAY, &mut out).unwrap();
+    assert_eq!(&out[..], b"{t,NULL,f}");
+    assert_eq!(bools.as_json(), serde_json::json!([true, null, false]));
+}
+
+#[test]
+fn protobuf_round_trip_per_variant() {
+    use chrono::{FixedOffset, NaiveDate, NaiveTime, TimeZone, Timelike, Utc};
+    use postgres_inet::MaskedIpAddr;
+    use pt::peerdb_value as pb;
+    use rust_decimal::Decimal;
+    use std::{collections::HashMap, net::Ipv6Addr};
+    use value::{
+        array::ArrayValue,
+        interval::Interval,
+        range::{Range, RangeBound},
+        timetz::TimeTz,
+    };
+
+    let date = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+    let time = NaiveTime::from_hms_micro_opt(3, 4, 5, 678).unwrap();
+    let ts = Utc
+        .with_ymd_and_hms(1969, 12, 31, 23, 59, 59)
+        .unwrap()
+        .with_nanosecond(123_456_000)
+        .unwrap();
+    let cases = vec![
+        Value::Null,
+        Value::Bool(true),
+        Value::TinyInt(-8),
+        Value::SmallInt(-300),
+        Value::Oid(u32::MAX),
+        Value::LargeObject(16385),
+        Value::Integer(i32::MIN),
+        Value::BigInt(i64::MAX),
+        Value::Float(1.5),
+        Value::Double(-2.25),
+        Value::Numeric("-12.340".parse::<Decimal>().unwrap()),
+        Value::Char('é'),
+        Value::VarChar("var".into()),
+        Value::Text("text".into()),
+        Value::Binary(Bytes::from_static(b"\x00\xff")),
+        Value::VarBinary(Bytes::from_static(b"\x01\x02")),
+        Value::Date(date),
+        Value::Time(time),
+        Value::TimeWithTimeZone(time),
+        Value::Timestamp(ts),
+        Value::PostgresTimestamp(ts.naive_utc()),
+        Value::TimestampWithTimeZone(ts),
+        Value::IpAddr(MaskedIpAddr::new(Ipv6Addr::LOCALHOST.into(), 64)),
+        Value::Interval(Interval::from_micros(-90_000_001)),
+        Value::Interval(Interval {
+            months: -14,
+            days: 3,
+            microseconds: 3_600_000_001,
+        }),
+        Value::Json(serde_json::json!({"b": 1, "a": [true, null]})),
+        Value::JsonB(serde_json::json!([1, "two"])),
+        Value::Uuid(uuid::Uuid::from_u128(0x1234_5678_9abc_def0)),
+        Value::Enum("happy".into()),
+        Value::Hstore(HashMap::from([("k".to_string(), "v".to_string())])),
+        Value::Array(ArrayValue::Empty),
+        Value::Array(ArrayValue::Bool(vec![Some(true), None, Some(false)])),
+        Value::Array(ArrayValue::TinyInt(vec![1, -1])),
+        Value::Array(ArrayValue::SmallInt(vec![2, -2])),
+        Value::Array(ArrayValue::Integer(vec![3, -3])),
+        Value::Array(ArrayValue::BigInt(vec![4, -4])),
+        Value::Array(ArrayValue::Oid(vec![23, 25])),
+        Value::Array(ArrayValue::Float(vec![0.5])),
+        Value::Array(ArrayValue::Double(vec![0.25])),
+        Value::Array(ArrayValue::Numeric(vec!["1.10".into(), "NaN".into()])),
+        Value::Array(ArrayValue::Char(vec!['a', 'b'])),
+        Value::Array(ArrayValue::VarChar(vec!["x".into()])),
+        Value::Array(ArrayValue::Text(vec!["y".into(), String::new()])),
+        Value::Array(ArrayValue::Binary(vec![Bytes::from_static(b"\x00")])),
+        Value::Array(ArrayValue::VarBinary(vec![Bytes::from_static(b"\x01")])),
+        Value::Array(ArrayValue::Date(vec![date])),
+        Value::Array(ArrayValue::Time(vec![Some(time), None])),
+        Value::Array(ArrayValue::Interval(vec![
+            Some(Interval {
+                months: 1,
+                days: -2,
+                microseconds: 3,
+            }),
+            None,
+        ])),
+        Value::Array(ArrayValue::TimeTz(vec![
+            Some(TimeTz {
+                time,
+                offset: FixedOffset::east_opt(-(8 * 3600 + 30 * 60)).unwrap(),
+            }),
+            None,
+        ])),
+        Value::Array(ArrayValue::Timestamp(vec![ts])),
+        Value::Array(ArrayValue::TimestampWithTimeZone(vec![ts])),
+        Value::range(Range::Empty),
+        Value::range(Range::Bounds {
+            lower: RangeBound::Inclusive(Value::Date(date)),
+            upper: RangeBound::Unbounded,
+        }),
+        Value::range(Range::Bounds {
+            lower: RangeBound::Unbounded,
+            upper: RangeBound::Exclusive(Value::TimestampWithTimeZone(ts)),
+        }),
+    ];
+    for value in cases {
+        let encoded = pb::Value::from(value.clone());
+        assert_eq!(Value::try_from(encoded).unwrap(), value);
+    }
+
+    // borrowed text and json come back as their owned counterparts
+    let encoded = pb::Value::from(Value::TextBytes(Bytes::from_static(b"abc")));
+    assert_eq!(Value::try_from(encoded).unwrap(), Value::Text("abc".into()));
+    let encoded = pb::Value::from(Value::JsonText(Bytes::from_static(br#"{"a":1}"#)));
+    assert_eq!(
+        Value::try_from(encoded).unwrap(),
+        Value::Json(serde_json::json!({"a": 1}))
+    );
+}
+
+#[test]
+fn typed_null_column_keeps_its_type() {
+    use pt::peerdb_value as pb;
+
+    let column = vec![
+        Value::NullTyped(TypeTag::TimestampWithTimeZone),
+        Value::NullTyped(TypeTag::TimestampWithTimeZone),
+    ];
+    let encoded: Vec<pb::Value> = column.iter().cloned().map(pb::Value::from).collect();
+    for value in &encoded {
+        assert_eq!(
+            value.value,
+            Some(pb::value::Value::NullValue(
+                pb::TypeTag::TimestampWithTimeZone.into()
+            ))
+        );
+    }
+    let decoded = encoded
+        .into_iter()
+        .map(Value::try_from)
+        .collect::<anyhow::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(decoded, column);
+    assert!(decoded
+        .iter()
+        .all(|v| v.is_null() && v.type_tag() == Some(TypeTag::TimestampWithTimeZone)));
+
+    // a plain null has no type to keep
+    assert_eq!(pb::Value::from(Value::Null).value, None);
+    assert_eq!(Value::Null.type_tag(), None);
+    assert_eq!(
+        Value::NullTyped(TypeTag::Json).to_serde_json_value(),
+        serde_json::Value::Null
+    );
+}
+
+#[test]
+fn protobuf_rejects_mismatched_array_elements() {
+    use pt::peerdb_value as pb;
+    use value::array::ArrayValue;
+
+    let mut array = pb::Array::from(ArrayValue::Integer(vec![1]));
+    array.elements.push(Value::Text("x".into()).into());
+    assert!(ArrayValue::try_from(array).is_err());
+}
+
+#[test]
+fn numeric_rounding_modes() {
+    use rust_decimal::Decimal;
+    use value::numeric::RoundingMode;
+
+    let numeric = |s: &str| Value::Numeric(s.parse::<Decimal>().unwrap());
+    let cases = [
+        ("2.345", RoundingMode::HalfUp, "2.35"),
+        ("-2.345", RoundingMode::HalfUp, "-2.35"),
+        ("2.345", RoundingMode::HalfEven, "2.34"),
+        ("2.355", RoundingMode::HalfEven, "2.36"),
+        ("2.349", RoundingMode::Truncate, "2.34"),
+        ("-2.349", RoundingMode::Truncate, "-2.34"),
+        ("2.3", RoundingMode::HalfUp, "2.30"),
+    ];
+    for (input, mode, expected) in cases {
+        let rounded = numeric(input).round_numeric_to_scale(5, 2, mode).unwrap();
+        assert_eq!(rounded, numeric(expected), "{} {:?}", input, mode);
+        let Value::Numeric(n) = rounded else {
+            unreachable!()
+        };
+        assert_eq!(n.scale(), 2);
+    }
+
+    assert_eq!(
+        Value::Null
+            .round_numeric_to_scale(5, 2, RoundingMode::HalfUp)
+            .unwrap(),
+        Value::Null
+    );
+    assert!(Value::Integer(1)
+        .round_numeric_to_scale(5, 2, RoundingMode::HalfUp)
+        .is_err());
+}
+
+#[test]
+fn numeric_rounding_precision_overflow() {
+    use rust_decimal::Decimal;
+    use value::numeric::RoundingMode;
+
+    let numeric = |s: &str| Value::Numeric(s.parse::<Decimal>().unwrap());
+    // NUMERIC(5, 2) holds at most 999.99
+    assert!(numeric("999.994")
+        .round_numeric_to_scale(5, 2, RoundingMode::HalfUp)
+        .is_ok());
+    assert!(numeric("999.995")
+        .round_numeric_to_scale(5, 2, RoundingMode::HalfUp)
+        .is_err());
+    assert!(numeric("999.999")
+        .round_numeric_to_scale(5, 2, RoundingMode::Truncate)
+        .is_ok());
+    assert!(numeric("1000")
+        .round_numeric_to_scale(5, 2, RoundingMode::Truncate)
+        .is_err());
+    assert!(numeric("1")
+        .round_numeric_to_scale(2, 3, RoundingMode::HalfUp)
+        .is_err());
+}
+
+#[test]
+fn polygon_bounding_box() {
+    use postgres_types::Type;
+    use value::geometry::{BoundingBox, Geometry, Point};
+
+    let polygon = Value::Text("((0,0),(4,-1.5),(2,3),(-1,1))".to_string())
+        .geometry(&Type::POLYGON)
+        .unwrap();
+    assert_eq!(
+        polygon.bounding_box(),
+        Some(BoundingBox {
+            low: Point { x: -1.0, y: -1.5 },
+            high: Point { x: 4.0, y: 3.0 },
+        })
+    );
+    assert_eq!(polygon.contains(Point { x: 1.0, y: 1.0 }), Some(true));
+    assert_eq!(polygon.contains(Point { x: 3.5, y: 2.5 }), Some(false));
+
+    let line = Geometry::parse(&Type::LINE, "{1,-1,0}").unwrap();
+    assert_eq!(line.bounding_box(), None);
+    assert_eq!(Geometry::parse(&Type::POLYGON, "((0,0),(1"), None);
+}
+
+#[test]
+fn point_in_box() {
+    use postgres_types::Type;
+    use value::geometry::{Geometry, Point};
+
+    // postgres outputs the upper right corner first
+    let bbox = Geometry::parse(&Type::BOX, "(2,3),(-1,0)").unwrap();
+    assert_eq!(bbox.contains(Point { x: 0.5, y: 1.0 }), Some(true));
+    assert_eq!(bbox.contains(Point { x: 2.0, y: 3.0 }), Some(true));
+    assert_eq!(bbox.contains(Point { x: 2.5, y: 1.0 }), Some(false));
+
+    let point = Geometry::parse(&Type::POINT, "(1,2)").unwrap();
+    assert_eq!(point.contains(Point { x: 1.0, y: 2.0 }), None);
+}
+
+#[test]
+fn circle_to_json() {
+    use postgres_types::Type;
+    use value::geometry::Geometry;
+
+    let circle = Geometry::parse(&Type::CIRCLE, "<(-1.5,-2),3>").unwrap();
+    assert_eq!(
+        circle.to_json(),
+        Some(serde_json::json!({"center": {"x": -1.5, "y": -2.0}, "radius": 3.0}))
+    );
+    let point = Geometry::parse(&Type::POINT, "(1,2)").unwrap();
+    assert_eq!(point.to_json(), None);
+}
+
+#[test]
+fn interval_of_ten_thousand_years_keeps_its_microseconds() {
+    use postgres_types::{FromSql, Type};
+    use value::interval::Interval;
+
+    // 10000 years and half a second and a microsecond: microseconds, days,
+    // then months
+    let mut raw = Vec::new();
+    raw.extend_from_slice(&500_001i64.to_be_bytes());
+    raw.extend_from_slice(&0i32.to_be_bytes());
+    raw.extend_from_slice(&120_000i32.to_be_bytes());
+    let interval = Interval::from_sql(&Type::INTERVAL, &raw).unwrap();
+    assert_eq!(
+        interval,
+        Interval {
+            months: 120_000,
+            days: 0,
+            microseconds: 500_001,
+        }
+    );
+    assert_eq!(
+        interval.total_micros(),
+        120_000 * 30 * 86_400_000_000 + 500_001
+    );
+
+    let value = Value::Interval(interval);
+    let pb = pt::peerdb_value::Value::from(value.clone());
+    assert_eq!(Value::try_from(pb).unwrap(), value);
+}
+
+// the binary form of a range: its flags, then each bound that isn't
+// unbounded prefixed with its length
+fn range_bytes(
+    flags: u8,
+    bounds: &[&dyn postgres_types::ToSql],
+    element: &postgres_types::Type,
+) -> Vec<u8> {
+    let mut raw = vec![flags];
+    for bound in bounds {
+        let mut buf = bytes::BytesMut::new();
+        bound.to_sql_checked(element, &mut buf).unwrap();
+        raw.extend_from_slice(&(buf.len() as i32).to_be_bytes());
+        raw.extend_from_slice(&buf);
+    }
+    raw
+}
+
+#[test]
+fn tstzrange_without_upper_bound() {
+    use chrono::{TimeZone, Utc};
+    use postgres_types::{FromSql, Type};
+    use value::range::{Range, RangeBound};
+
+    let start = Utc.with_ymd_and_hms(2024, 3, 1, 12, 30, 0).unwrap();
+    // inclusive lower, unbounded upper
+    let raw = range_bytes(0x02 | 0x10, &[&start], &Type::TIMESTAMPTZ);
+    let range = Range::from_sql(&Type::TSTZ_RANGE, &raw).unwrap();
+    assert_eq!(
+        range,
+        Range::Bounds {
+            lower: RangeBound::Inclusive(Value::TimestampWithTimeZone(start)),
+            upper: RangeBound::Unbounded,
+        }
+    );
+    assert_eq!(range.to_string(), r#"["2024-03-01 12:30:00+00",)"#);
+}
+
+#[test]
+fn daterange_with_both_bounds() {
+    use chrono::NaiveDate;
+    use postgres_types::{FromSql, Type};
+    use value::range::{Range, RangeBound};
+
+    let first = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+    let last = NaiveDate::from_ymd_opt(2024, 2, 1).unwrap();
+    let raw = range_bytes(0x02, &[&first, &last], &Type::DATE);
+    let range = Range::from_sql(&Type::DATE_RANGE, &raw).unwrap();
+    assert_eq!(
+        range,
+        Range::Bounds {
+            lower: RangeBound::Inclusive(Value::Date(first)),
+            upper: RangeBound::Exclusive(Value::Date(last)),
+        }
+    );
+    assert_eq!(range.to_string(), "[2024-01-01,2024-02-01)");
+    assert_eq!(
+        Value::range(range).to_serde_json_value(),
+        serde_json::json!({
+            "lower": "2024-01-01",
+            "lower_inclusive": true,
+            "upper": "2024-02-01",
+            "upper_inclusive": false,
+        })
+    );
+
+    // an empty range is only its flag
+    assert_eq!(
+        Range::from_sql(&Type::DATE_RANGE, &[0x01]).unwrap(),
+        Range::Empty
+    );
+}
+
+#[test]
+fn timetz_text_keeps_offset() {
+    use value::timetz::TimeTz;
+
+    for text in [
+        "09:00:00+05:30",
+        "17:45:10.5-08",
+        "00:00:00+00",
+        "23:59:59-03:30:15",
+    ] {
+        let t = text.parse::<TimeTz>().unwrap();
+        assert_eq!(t.to_string(), text);
+    }
+    let t = "09:00:00-08".parse::<TimeTz>().unwrap();
+    assert_eq!(t.offset.local_minus_utc(), -8 * 3600);
+    assert!("09:00:00".parse::<TimeTz>().is_err());
+}
+
+fn key_hash(value: &Value) -> u64 {
+    use std::{collections::hash_map::DefaultHasher, hash::Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    value.hash_key(&mut hasher);
+    hasher.finish()
+}
+
+#[test]
+fn equal_keys_hash_equally() {
+    use rust_decimal::Decimal;
+
+    let numeric = |s: &str| Value::Numeric(s.parse::<Decimal>().unwrap());
+    let json = |s: &str| Value::JsonB(serde_json::from_str(s).unwrap());
+    for (a, b) in [
+        (Value::Integer(7), Value::BigInt(7)),
+        (numeric("1.50"), numeric("1.5")),
+        (Value::Double(f64::NAN), Value::Double(f64::NAN)),
+        (
+            Value::Text("key".into()),
+            Value::TextBytes(Bytes::from_static(b"key")),
+        ),
+        (json(r#"{"a":1,"b":[true]}"#), json(r#"{"b":[true],"a":1}"#)),
+        (
+            Value::JsonText(Bytes::from_static(br#"{"a": 1}"#)),
+            json(r#"{"a":1}"#),
+        ),
+    ] {
+        assert_eq!(key_hash(&a), key_hash(&b), "{:?} and {:?}", a, b);
+    }
+}
+
+#[test]
+fn different_keys_hash_differently() {
+    use rust_decimal::Decimal;
+
+    for (a, b) in [
+        (Value::Integer(7), Value::Integer(8)),
+        (Value::Integer(7), Value::Text("7".into())),
+        (Value::Double(0.0), Value::Double(-0.0)),
+        (
+            Value::Numeric("1.5".parse::<Decimal>().unwrap()),
+            Value::Text("1.5".into()),
+        ),
+        (Value::Null, Value::Text(String::new())),
+        (Value::Text("ab".into()), Value::Text("ba".into())),
+    ] {
+        assert_ne!(key_hash(&a), key_hash(&b), "{:?} and {:?}", a, b);
+    }
+}
+
+#[test]
+fn value_kinds_map_to_pg_types_both_ways() {
+    use postgres_types::Type;
+    use value::kind::{pg_type_to_value_kind, value_kind_to_pg_oid};
+
+    let common = [
+        (Type::BOOL, TypeTag::Bool),
+        (Type::CHAR, TypeTag::Char),
+        (Type::INT2, TypeTag::SmallInt),
+        (Type::INT4, TypeTag::Integer),
+        (Type::INT8, TypeTag::BigInt),
+        (Type::OID, TypeTag::Oid),
+        (Type::FLOAT4, TypeTag::Float),
+        (Type::FLOAT8, TypeTag::Double),
+        (Type::NUMERIC, TypeTag::Numeric),
+        (Type::TEXT, TypeTag::Text),
+        (Type::BYTEA, TypeTag::VarBinary),
+        (Type::JSON, TypeTag::Json),
+        (Type::JSONB, TypeTag::JsonB),
+        (Type::UUID, TypeTag::Uuid),
+        (Type::INET, TypeTag::IpAddr),
+        (Type::DATE, TypeTag::Date),
+        (Type::TIME, TypeTag::Time),
+        (Type::TIMETZ, TypeTag::TimeWithTimeZone),
+        (Type::TIMESTAMP, TypeTag::PostgresTimestamp),
+        (Type::TIMESTAMPTZ, TypeTag::TimestampWithTimeZone),
+        (Type::TEXT_ARRAY, TypeTag::Array),
+    ];
+    for (ty, kind) in common {
+        assert_eq!(pg_type_to_value_kind(&ty), kind, "{}", ty);
+        assert_eq!(value_kind_to_pg_oid(kind), ty.oid(), "{:?}", kind);
+    }
+
+    // read into the same kind as their canonical type
+    assert_eq!(pg_type_to_value_kind(&Type::VARCHAR), TypeTag::Text);
+    assert_eq!(pg_type_to_value_kind(&Type::INT8_ARRAY), TypeTag::Array);
+    // unknown types are read as text
+    assert_eq!(pg_type_to_value_kind(&Type::TSVECTOR), TypeTag::Text);
+}
+
+#[test]
+fn json_keeps_bigints_exact_as_strings() {
+    use value::array::ArrayValue;
+
+    // past 2^53, where a JSON number would be rounded by most readers
+    let big = 9_007_199_254_740_993_i64;
+    assert_eq!(
+        Value::BigInt(big).as_json(),
+        serde_json::json!("9007199254740993")
+    );
+    assert_eq!(
+        Value::Array(ArrayValue::BigInt(vec![big, -1])).as_json(),
+        serde_json::json!(["9007199254740993", "-1"])
+    );
+    assert_eq!(Value::Integer(7).as_json(), serde_json::json!(7));
+    assert_eq!(Value::Double(f64::NAN).as_json(), serde_json::json!("NaN"));
+}
+
+#[test]
+fn json_writes_binary_as_base64() {
+    use value::array::ArrayValue;
+
+    let bytes = Bytes::from_static(b"\x00\xffhi");
+    assert_eq!(
+        Value::VarBinary(bytes.clone()).as_json(),
+        serde_json::json!("AP9oaQ==")
+    );
+    assert_eq!(
+        Value::Array(ArrayValue::Binary(vec![bytes])).as_json(),
+        serde_json::json!(["AP9oaQ=="])
+    );
+}
+
+#[test]
+fn coerce_converts_between_kinds() {
+    use rust_decimal::Decimal;
+
+    assert_eq!(
+        Value::Text(" 12 ".into())
+            .coerce_to(TypeTag::SmallInt)
+            .unwrap(),
+        Value::SmallInt(12)
+    );
+    assert_eq!(
+        Value::Integer(3).coerce_to(TypeTag::Numeric).unwrap(),
+        Value::Numeric(Decimal::from(3))
+    );
+    assert_eq!(
+        Value::Text("on".into()).coerce_to(TypeTag::Bool).unwrap(),
+        Value::Bool(true)
+    );
+    assert_eq!(
+        Value::Text("{\"a\":1}".into())
+            .coerce_to(TypeTag::JsonB)
+            .unwrap(),
+        Value::JsonB(serde_json::json!({"a": 1}))
+    );
+    assert_eq!(Value::Null.coerce_to(TypeTag::Uuid).unwrap(), Value::Null);
+
+    assert!(Value::BigInt(70_000).coerce_to(TypeTag::SmallInt).is_err());
+    assert!(Value::Double(1.5).coerce_to(TypeTag::Integer).is_err());
+    assert!(Value::Text("ab".into()).coerce_to(TypeTag::Char).is_err());
+    assert!(Value::Bool(true).coerce_to(TypeTag::Date).is_err());
+}
+
+#[test]
+fn byte_len_counts_data_bytes() {
+    use rust_decimal::Decimal;
+    use value::array::ArrayValue;
+
+    // "héllo ✓" is 7 characters in 10 bytes
+    let text = "héllo ✓".to_string();
+    assert_eq!(Value::Text(text.clone()).byte_len(), 10);
+    assert_eq!(Value::TextBytes(Bytes::from(text.clone())).byte_len(), 10);
+    assert_eq!(Value::Char('é').byte_len(), 2);
+
+    let mut spare = String::with_capacity(64);
+    spare.push_str(&text);
+    assert_eq!(Value::Text(spare).byte_len(), 10);
+
+    let bytes = Bytes::from_static(b"\x00\xff\x10");
+    assert_eq!(Value::VarBinary(bytes.clone()).byte_len(), 3);
+    assert_eq!(Value::Binary(bytes).byte_len(), 3);
+
+    assert_eq!(Value::Numeric(Decimal::new(-12345, 2)).byte_len(), 7);
+    assert_eq!(Value::Json(serde_json::json!({"a": [1, 2]})).byte_len(), 11);
+    assert_eq!(Value::Integer(1).byte_len(), 4);
+    assert_eq!(Value::Null.byte_len(), 0);
+    assert_eq!(
+        Value::Array(ArrayValue::Text(vec!["é".into(), "ab".into()])).byte_len(),
+        4
+    );
+}
+
+#[test]
+fn equivalent_values_normalize_equal() {
+    use rust_decimal::Decimal;
+    use value::array::ArrayValue;
+
+    let pairs = [
+        (Value::Double(-0.0), Value::Double(0.0)),
+        (Value::Double(f64::NAN), Value::Double(-f64::NAN)),
+        (
+            Value::Numeric(Decimal::new(1500, 3)),
+            Value::Numeric(Decimal::new(15, 1)),
+        ),
+        (
+            Value::JsonB(
+                serde_json::from_str(r#"{"b":1,"a":{"y":2,"x":[{"d":0,"c":0}]}}"#).unwrap(),
+            ),
+            Value::JsonB(
+                serde_json::from_str(r#"{"a":{"x":[{"c":0,"d":0}],"y":2},"b":1}"#).unwrap(),
+            ),
+        ),
+        (
+            Value::JsonText(Bytes::from_static(br#"{"z": true, "a": null}"#)),
+            Value::Json(serde_json::json!({"a": null, "z": true})),
+        ),
+        (
+            Value::TextBytes(Bytes::from_static(b"same")),
+            Value::Text("same".into()),
+        ),
+        (
+            Value::Array(ArrayValue::Numeric(vec!["2.50".into(), "NaN".into()])),
+            Value::Array(ArrayValue::Numeric(vec!["2.5".into(), "NaN".into()])),
+        ),
+    ];
+    for (a, b) in pairs {
+        assert!(a.normalize().value_eq(&b.normalize()), "{:?} vs {:?}", a, b);
+    }
+
+    // the canonical forms write the same
+    match Value::Numeric(Decimal::new(1500, 3)).normalize() {
+        Value::Numeric(n) => assert_eq!(n.to_string(), "1.5"),
+        other => panic!("expected a numeric, got {:?}", other),
+    }
+    match Value::JsonText(Bytes::from_static(br#"{"z": true, "a": null}"#)).normalize() {
+        Value::Json(json) => assert_eq!(json.to_string(), r#"{"a":null,"z":true}"#),
+        other => panic!("expected json, got {:?}", other),
+    }
+
+    // differences in content remain
+    assert!(!Value::Numeric(Decimal::new(15, 1))
+        .normalize()
+        .value_eq(&Value::Numeric(Decimal::new(16, 1)).normalize()));
+    assert!(!Value::Json(serde_json::json!({"a": 1}))
+        .normalize()
+        .value_eq(&Value::JsonB(serde_json::json!({"a": 1})).normalize()));
+}